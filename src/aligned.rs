@@ -0,0 +1,82 @@
+use std::fmt;
+use std::ops;
+
+/// Alignment which satisfies direct I/O on common file systems and disks.
+pub const DEFAULT_ALIGN: usize = 4096;
+
+/// `AlignedBuf` is a zero-initialized byte buffer whose start is aligned
+/// in memory, as direct I/O requires of buffers (see `FileOpener::direct`).
+///
+/// Direct I/O also requires lengths and file offsets to be multiples of the
+/// alignment, which `round_up` helps with.
+#[derive(Clone)]
+pub struct AlignedBuf {
+    storage: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    /// Allocate `len` zero bytes aligned to `DEFAULT_ALIGN`.
+    pub fn new(len: usize) -> Self {
+        AlignedBuf::with_alignment(len, DEFAULT_ALIGN)
+    }
+
+    /// Allocate `len` zero bytes aligned to `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn with_alignment(len: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let storage = vec![0; len + align - 1];
+        let offset = storage.as_ptr().align_offset(align);
+        AlignedBuf {
+            storage,
+            offset,
+            len,
+        }
+    }
+
+    /// Round `n` up to the next multiple of `align`, a power of two.
+    pub fn round_up(n: usize, align: usize) -> usize {
+        (n + align - 1) & !(align - 1)
+    }
+}
+
+impl ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+impl ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AlignedBuf").field("len", &self.len).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn aligned() {
+        let mut buf = AlignedBuf::with_alignment(10, 512);
+        assert_eq!(0, buf.as_ptr() as usize % 512);
+        assert_eq!(10, buf.len());
+        buf[9] = 1;
+        assert_eq!(1, buf[9]);
+        assert_eq!(4096, AlignedBuf::round_up(1, 4096));
+        assert_eq!(8192, AlignedBuf::round_up(8192, 4096));
+    }
+}
@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use atomic::AtomicFile;
+use time::Tm;
+use walk::{Walk, WalkOptions};
+
+/// Entries at least this large need zip64 extensions.
+const ZIP64_THRESHOLD: u64 = 0xFFFF_FFFF;
+
+/// `Compression` is how `Dir::zip_to` stores file content.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// No compression, for content which is already compressed.
+    Stored,
+    #[default]
+    Deflated,
+}
+
+/// `ZipOptions` controls `Dir::zip_to`. By default files are deflated with
+/// the default level, and stored at their path relative to the directory.
+#[derive(Clone, Debug, Default)]
+pub struct ZipOptions {
+    compression: Compression,
+    level: Option<i64>,
+    prefix: Option<PathBuf>,
+}
+
+impl ZipOptions {
+    /// Deflate with the default level, without a prefix.
+    pub fn new() -> Self {
+        ZipOptions::default()
+    }
+
+    /// Set how file content is compressed.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the compression level, 0 to 9 for deflate.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Some(i64::from(level));
+        self
+    }
+
+    /// Store entries under `prefix`, such as `myapp-1.0/`.
+    pub fn prefix<P: AsRef<Path>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.as_ref().to_path_buf());
+        self
+    }
+
+    fn file_options(&self, meta: &fs::Metadata) -> SimpleFileOptions {
+        let method = match self.compression {
+            Compression::Stored => CompressionMethod::Stored,
+            Compression::Deflated => CompressionMethod::Deflated,
+        };
+        let mut options = SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(self.level)
+            .large_file(meta.len() >= ZIP64_THRESHOLD);
+        if let Some(time) = meta.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(time);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(meta.permissions().mode());
+        }
+        options
+    }
+
+    /// Name of the entry at `rel`, with `/` separators.
+    fn entry_name(&self, rel: &Path) -> String {
+        let path = match self.prefix {
+            Some(ref prefix) => prefix.join(rel),
+            None => rel.to_path_buf(),
+        };
+        let parts: Vec<_> = path.iter().map(|p| p.to_string_lossy()).collect();
+        parts.join("/")
+    }
+}
+
+/// Write the tree under `root` into the zip file `archive`, replacing it
+/// atomically. Returns the number of entries written.
+pub(crate) fn zip_dir(root: &Path, archive: &Path, opts: &ZipOptions) -> io::Result<usize> {
+    let out = AtomicFile::new(archive)?;
+    let excluded = inside(root, &[archive, out.tmp_path()]);
+    let mut zip = ZipWriter::new(out);
+    let mut count = 0;
+    for entry in Walk::new(root, WalkOptions::new()) {
+        let entry = entry?;
+        let rel = entry.file().strip_prefix(root).expect("walked under root");
+        if excluded.iter().any(|e| e == rel) {
+            continue;
+        }
+        let name = opts.entry_name(rel);
+        let meta = entry.metadata()?;
+        let options = opts.file_options(&meta);
+        let ty = entry.file_type();
+        if ty.is_dir() {
+            zip.add_directory(name, options)?;
+        } else if ty.is_symlink() {
+            let target = fs::read_link(entry.file())?;
+            zip.add_symlink(name, target.to_string_lossy(), options)?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut fs::File::open(entry.file())?, &mut zip)?;
+        }
+        count += 1;
+    }
+    zip.finish()?.commit()?;
+    Ok(count)
+}
+
+/// Paths of `paths` relative to `root`, for those under it.
+fn inside(root: &Path, paths: &[&Path]) -> Vec<PathBuf> {
+    let root = match root.canonicalize() {
+        Ok(root) => root,
+        Err(_) => return Vec::new(),
+    };
+    paths
+        .iter()
+        .filter_map(|path| {
+            let parent = match path.parent() {
+                Some(p) if p.as_os_str().is_empty() => Path::new("."),
+                Some(p) => p,
+                None => return None,
+            };
+            let parent = parent.canonicalize().ok()?;
+            let rel = parent.strip_prefix(&root).ok()?;
+            Some(rel.join(path.file_name()?))
+        })
+        .collect()
+}
+
+fn zip_time(t: SystemTime) -> Option<DateTime> {
+    let tm = Tm::from_system_time(t);
+    let year = u16::try_from(tm.year).ok()?;
+    DateTime::from_date_and_time(
+        year,
+        tm.month as u8,
+        tm.day as u8,
+        tm.hour as u8,
+        tm.minute as u8,
+        tm.second as u8,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::io::prelude::*;
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use zip::ZipArchive;
+    use super::*;
+
+    #[test]
+    fn zip_to() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("bin").create().unwrap();
+        dir.child("bin/app").overwrite(b"binary").unwrap();
+        dir.child("README").overwrite(b"read me").unwrap();
+
+        let archive = dir.child("out.zip");
+        let opts = ZipOptions::new().prefix("app-1.0").level(9);
+        assert_eq!(3, dir.zip_to(&archive, opts).unwrap());
+
+        let mut zip = ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(|n| n.unwrap().into_owned()).collect();
+        names.sort();
+        assert_eq!(vec!["app-1.0/README", "app-1.0/bin/", "app-1.0/bin/app"], names);
+        let mut content = String::new();
+        zip.by_name("app-1.0/bin/app").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!("binary", &content);
+
+        let opts = ZipOptions::new().compression(Compression::Stored);
+        assert_eq!(3, dir.zip_to(&archive, opts).unwrap());
+        let zip = ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let names: Vec<_> = zip.file_names().map(|n| n.unwrap().into_owned()).collect();
+        assert!(names.contains(&"README".to_owned()));
+        assert!(!names.iter().any(|n| n.contains("out.zip")));
+    }
+}
@@ -0,0 +1,103 @@
+//! Write-to-temporary-then-rename support for replacing files atomically.
+
+use std::fs;
+use std::io::{self, BufWriter, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// `AtomicFile` buffers writes into a temporary file next to `target`,
+/// which replaces `target` on `commit`. Readers of `target` see either
+/// the old or the new content, never a partial one. The temporary file
+/// is removed if `commit` is never called.
+pub struct AtomicFile {
+    target: PathBuf,
+    tmp: PathBuf,
+    writer: Option<BufWriter<fs::File>>,
+}
+
+impl AtomicFile {
+    pub fn new(target: &Path) -> io::Result<Self> {
+        let (tmp, f) = create_tmp(target)?;
+        Ok(AtomicFile {
+            target: target.to_path_buf(),
+            tmp,
+            writer: Some(BufWriter::new(f)),
+        })
+    }
+
+    /// Path of the temporary file written to.
+    #[cfg(feature = "zip")]
+    pub fn tmp_path(&self) -> &Path {
+        &self.tmp
+    }
+
+    /// Flush and sync the temporary file, then rename it over the target.
+    pub fn commit(mut self) -> io::Result<()> {
+        let writer = self.writer.take().expect("writer is only taken on commit");
+        let result = writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|f| f.sync_all())
+            .and_then(|_| fs::rename(&self.tmp, &self.target));
+        if result.is_err() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+        result
+    }
+}
+
+/// Create a new temporary file next to `target`, with the permissions of
+/// `target` if it exists, to be renamed over it once written.
+pub(crate) fn create_tmp(target: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let name = target.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    loop {
+        let mut tmp_name = ::std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = target.with_file_name(tmp_name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&tmp) {
+            Ok(f) => {
+                if let Ok(meta) = fs::metadata(target) {
+                    f.set_permissions(meta.permissions())?;
+                }
+                return Ok((tmp, f));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.as_mut().expect("writer is only taken on commit").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().expect("writer is only taken on commit").flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.writer.as_mut().expect("writer is only taken on commit").seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use std::fs::{self, FileTimes};
+use std::io;
+use std::io::prelude::*;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use atomic;
+use file::{default_buf_capacity, FileOpener, Open};
+use sys;
+use walk::{Walk, WalkOptions};
+
+/// Most bytes copied at once by the kernel, between progress reports.
+const KERNEL_COPY_CHUNK: usize = 1024 * 1024;
+
+/// `Overwrite` tells what copying does when a destination file exists.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Overwrite {
+    /// Fail with `AlreadyExists`.
+    #[default]
+    Never,
+    /// Leave the existing file and go on.
+    Skip,
+    /// Replace the existing file.
+    Always,
+    /// Replace the existing file only if the source was modified later.
+    IfNewer,
+}
+
+/// `Symlinks` tells how copying handles symbolic links.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Symlinks {
+    /// Create the same link at the destination.
+    #[default]
+    Copy,
+    /// Copy what the link points to. Directories reached through links
+    /// are copied once at most.
+    Follow,
+}
+
+/// `Reflink` tells whether copies share the data of their source on
+/// filesystems with copy-on-write clones, such as Btrfs, XFS and APFS.
+/// A clone is instant and takes no space until either file is modified.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Reflink {
+    /// Always copy the data.
+    #[default]
+    Never,
+    /// Clone when the filesystem supports it, copy otherwise.
+    Auto,
+    /// Clone, or fail with `Unsupported`.
+    Always,
+}
+
+/// `CopyOptions` controls `Dir::copy_to` and `File::copy_to`. By default
+/// existing files are not overwritten, links are copied as links,
+/// permissions are kept and missing parents of a file's destination
+/// are an error. Timestamps are only kept with `preserve`.
+pub struct CopyOptions {
+    overwrite: Overwrite,
+    symlinks: Symlinks,
+    reflink: Reflink,
+    preserve_permissions: bool,
+    preserve_times: bool,
+    preserve_owner: bool,
+    create_parents: bool,
+    progress: Option<Box<ProgressFn>>,
+}
+
+type ProgressFn = dyn FnMut(u64, u64);
+
+/// Called with the bytes copied so far and the total, `Break` cancels.
+pub(crate) type Control<'a> = dyn FnMut(u64, u64) -> ControlFlow<()> + 'a;
+
+impl CopyOptions {
+    /// Don't overwrite, copy links as links and keep permissions.
+    pub fn new() -> Self {
+        CopyOptions {
+            overwrite: Overwrite::Never,
+            symlinks: Symlinks::Copy,
+            reflink: Reflink::Never,
+            preserve_permissions: true,
+            preserve_times: false,
+            preserve_owner: false,
+            create_parents: false,
+            progress: None,
+        }
+    }
+
+    /// Set what happens to existing destination files.
+    pub fn overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Set how symbolic links are handled.
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Set whether file data is cloned rather than copied.
+    pub fn reflink(mut self, reflink: Reflink) -> Self {
+        self.reflink = reflink;
+        self
+    }
+
+    /// Give copies the permissions of their source. Otherwise they get
+    /// the defaults of newly created files.
+    pub fn preserve_permissions(mut self, preserve: bool) -> Self {
+        self.preserve_permissions = preserve;
+        self
+    }
+
+    /// Give copies the access and modification times of their source.
+    pub fn preserve_times(mut self, preserve: bool) -> Self {
+        self.preserve_times = preserve;
+        self
+    }
+
+    /// Keep everything that can be kept, for backups: permissions,
+    /// timestamps, and on Unix the owner and group, when allowed, and
+    /// extended attributes on Linux, except those which can't be set.
+    /// Directories keep their timestamps on Unix only.
+    pub fn preserve(mut self) -> Self {
+        self.preserve_permissions = true;
+        self.preserve_times = true;
+        self.preserve_owner = true;
+        self
+    }
+
+    /// Create the missing parent directories of the destination of
+    /// `File::copy_to`.
+    pub fn create_parents(mut self, create_parents: bool) -> Self {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Call `progress(bytes_copied, total_bytes)` as file content is copied.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(u64, u64) + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions::new()
+    }
+}
+
+/// Copy the content of the directory `src` into `dest`, created if
+/// missing, and return the number of bytes copied.
+pub(crate) fn copy_dir(src: &Path, dest: &Path, opts: &mut CopyOptions) -> io::Result<u64> {
+    let real_src = src.canonicalize()?;
+    if canonicalize_nearest(dest)?.starts_with(&real_src) {
+        let msg = format!("cannot copy {:?} into itself", real_src);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    let walk_options = WalkOptions::new().follow_links(opts.symlinks == Symlinks::Follow);
+    let entries = Walk::new(src, walk_options).collect::<io::Result<Vec<_>>>()?;
+    let mut total = 0;
+    for entry in &entries {
+        if entry.file_type().is_file() {
+            total += fs::metadata(entry.file())?.len();
+        }
+    }
+
+    fs::create_dir_all(dest)?;
+    let mut copied = 0;
+    let mut dirs = vec![(src.to_path_buf(), dest.to_path_buf())];
+    for entry in &entries {
+        let rel = entry.file().strip_prefix(src).expect("walked under src");
+        let target = dest.join(rel);
+        let ty = entry.file_type();
+        if ty.is_dir() {
+            fs::create_dir_all(&target)?;
+            dirs.push((entry.file().to_path_buf(), target));
+        } else if ty.is_symlink() {
+            copy_link(entry.file(), &target, opts)?;
+        } else {
+            copied += copy_file(entry.file(), &target, opts, copied, total)?;
+        }
+    }
+    // Last and deepest first, as read-only directories can't be filled
+    // and filling a directory changes its modification time.
+    for (src, target) in dirs.iter().rev() {
+        preserve_dir(src, target, opts)?;
+    }
+    Ok(copied)
+}
+
+#[cfg(unix)]
+fn preserve_dir(src: &Path, dest: &Path, opts: &CopyOptions) -> io::Result<()> {
+    if opts.preserve_permissions || opts.preserve_times || opts.preserve_owner {
+        let meta = fs::metadata(src)?;
+        preserve_metadata(src, &meta, dest, &fs::File::open(dest)?, opts)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn preserve_dir(src: &Path, dest: &Path, opts: &CopyOptions) -> io::Result<()> {
+    if opts.preserve_permissions {
+        fs::set_permissions(dest, fs::metadata(src)?.permissions())?;
+    }
+    Ok(())
+}
+
+/// Give `dest`, open as `dest_file`, the metadata `meta` of `src` which
+/// `opts` keep.
+fn preserve_metadata(
+    src: &Path,
+    meta: &fs::Metadata,
+    dest: &Path,
+    dest_file: &fs::File,
+    opts: &CopyOptions,
+) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        if opts.preserve_owner {
+            use std::os::unix::fs::{fchown, MetadataExt};
+            // Only privileged processes may give files away, and not every
+            // filesystem keeps attributes, so both are best effort.
+            let _ = fchown(dest_file, Some(meta.uid()), Some(meta.gid()));
+            let _ = sys::copy_xattrs(src, dest);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (src, dest);
+    if opts.preserve_permissions {
+        dest_file.set_permissions(meta.permissions())?;
+    }
+    if opts.preserve_times {
+        let times = FileTimes::new().set_accessed(meta.accessed()?).set_modified(meta.modified()?);
+        dest_file.set_times(times)?;
+    }
+    Ok(())
+}
+
+/// Copy the file or link `src` to `dest` as `opts` tell, and return the
+/// number of bytes copied, `None` if `control` cancelled the copy.
+pub(crate) fn copy_entry(
+    src: &Path,
+    dest: &Path,
+    opts: &mut CopyOptions,
+    control: &mut Control,
+) -> io::Result<Option<u64>> {
+    if opts.create_parents {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let meta = fs::symlink_metadata(src)?;
+    if meta.file_type().is_symlink() && opts.symlinks == Symlinks::Copy {
+        copy_link(src, dest, opts)?;
+        return Ok(Some(0));
+    }
+    let total = fs::metadata(src)?.len();
+    copy_reporting(src, dest, opts, 0, total, control)
+}
+
+/// Copy the content of the file `src` to `dest` as `opts` tell, and return
+/// the number of bytes copied. `done` and `total` are for progress reports.
+pub(crate) fn copy_file(
+    src: &Path,
+    dest: &Path,
+    opts: &mut CopyOptions,
+    done: u64,
+    total: u64,
+) -> io::Result<u64> {
+    let copied = copy_reporting(src, dest, opts, done, total, &mut |_, _| {
+        ControlFlow::Continue(())
+    })?;
+    Ok(copied.unwrap_or(0))
+}
+
+/// `copy_content` reporting to the progress function of `opts` as well
+/// as to `control`.
+fn copy_reporting(
+    src: &Path,
+    dest: &Path,
+    opts: &mut CopyOptions,
+    done: u64,
+    total: u64,
+    control: &mut Control,
+) -> io::Result<Option<u64>> {
+    let mut progress = opts.progress.take();
+    let result = copy_content(src, dest, opts, total, &mut |copied, total| {
+        if let Some(ref mut progress) = progress {
+            progress(done + copied, total);
+        }
+        control(copied, total)
+    });
+    opts.progress = progress;
+    result
+}
+
+/// Copy the content of `src` to `dest` chunk by chunk, calling `control`
+/// after each. The content is written to a temporary file next to `dest`
+/// and renamed over it, so that a link at `dest` is replaced instead of
+/// written through. When `control` breaks, the temporary file is removed.
+fn copy_content(
+    src: &Path,
+    dest: &Path,
+    opts: &CopyOptions,
+    total: u64,
+    control: &mut Control,
+) -> io::Result<Option<u64>> {
+    if let Ok(dest_meta) = fs::symlink_metadata(dest) {
+        match opts.overwrite {
+            Overwrite::Never => return Err(exists(dest)),
+            Overwrite::Skip => return Ok(Some(0)),
+            Overwrite::Always => {}
+            Overwrite::IfNewer => {
+                if fs::metadata(src)?.modified()? <= dest_meta.modified()? {
+                    return Ok(Some(0));
+                }
+            }
+        }
+        if fs::metadata(dest).is_ok() && sys::file_key(src)? == sys::file_key(dest)? {
+            let msg = format!("{} and {} are the same file", src.display(), dest.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+    }
+    let mut r = FileOpener::readonly().open(src)?;
+    let meta = r.metadata()?;
+    if opts.reflink != Reflink::Never {
+        // Clone into a sibling too, a failed clone must not touch `dest`.
+        let (tmp, _) = atomic::create_tmp(dest)?;
+        let cloned = sys::reflink(src, &tmp).and_then(|cloned| {
+            if cloned {
+                let w = FileOpener::appending().open(&tmp)?;
+                preserve_metadata(src, &meta, &tmp, &w, opts)?;
+                fs::rename(&tmp, dest)?;
+            }
+            Ok(cloned)
+        });
+        match cloned {
+            Ok(true) => {
+                let _ = control(meta.len(), total);
+                return Ok(Some(meta.len()));
+            }
+            Ok(false) => {
+                let _ = fs::remove_file(&tmp);
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        }
+        if opts.reflink == Reflink::Always {
+            let msg = format!("cannot clone {} to {}", src.display(), dest.display());
+            return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+        }
+    }
+    let (tmp, mut w) = atomic::create_tmp(dest)?;
+    let result = copy_stream(&mut r, &mut w, total, control).and_then(|copied| {
+        if copied.is_some() {
+            preserve_metadata(src, &meta, &tmp, &w, opts)?;
+        }
+        Ok(copied)
+    });
+    drop(w);
+    let result = match result {
+        Ok(Some(copied)) => fs::rename(&tmp, dest).map(|_| Some(copied)),
+        other => other,
+    };
+    if !matches!(result, Ok(Some(_))) {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Copy everything from `r` to `w`, calling `control` after each chunk.
+/// Returns `None` if it cancelled the copy.
+fn copy_stream(
+    r: &mut fs::File,
+    w: &mut fs::File,
+    total: u64,
+    control: &mut Control,
+) -> io::Result<Option<u64>> {
+    // The kernel copies by itself when it can, otherwise data goes
+    // through `buf`.
+    let mut in_kernel = true;
+    let mut buf = Vec::new();
+    let mut copied = 0;
+    loop {
+        let n = if in_kernel {
+            match sys::copy_range(r, w, KERNEL_COPY_CHUNK)? {
+                Some(n) if n > 0 || copied > 0 => n,
+                // Files like those in /proc report a length of 0 and
+                // only give their content to `read`, and an empty file
+                // costs one more read.
+                _ => {
+                    in_kernel = false;
+                    buf.resize(default_buf_capacity(), 0);
+                    continue;
+                }
+            }
+        } else {
+            match r.read(&mut buf) {
+                Ok(n) => {
+                    w.write_all(&buf[..n])?;
+                    n
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            break;
+        }
+        copied += n as u64;
+        if control(copied, total).is_break() {
+            return Ok(None);
+        }
+    }
+    Ok(Some(copied))
+}
+
+/// `path` made absolute and without links through its nearest existing
+/// ancestor, for destinations which don't exist yet.
+pub(crate) fn canonicalize_nearest(path: &Path) -> io::Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        let err = match existing.canonicalize() {
+            Ok(mut real) => {
+                real.extend(missing.iter().rev());
+                return Ok(real);
+            }
+            Err(e) => e,
+        };
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) if err.kind() == io::ErrorKind::NotFound => {
+                missing.push(name);
+                existing = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            }
+            _ => return Err(err),
+        }
+    }
+}
+
+/// Recreate the link `src` at `dest`, with the same target.
+fn copy_link(src: &Path, dest: &Path, opts: &CopyOptions) -> io::Result<()> {
+    if fs::symlink_metadata(dest).is_ok() {
+        match opts.overwrite {
+            Overwrite::Never => return Err(exists(dest)),
+            Overwrite::Skip | Overwrite::IfNewer => return Ok(()),
+            Overwrite::Always => fs::remove_file(dest)?,
+        }
+    }
+    let target = fs::read_link(src)?;
+    sys::symlink(&target, dest, src.is_dir())
+}
+
+fn exists(dest: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", dest.display()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use super::*;
+
+    #[test]
+    fn copy_dir() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        src.child_dir("a/b").create().unwrap();
+        src.child("a/b/c.txt").overwrite(b"hello").unwrap();
+        src.child("d.txt").overwrite(b"world!").unwrap();
+        #[cfg(unix)]
+        ::std::os::unix::fs::symlink("d.txt", src.child("link")).unwrap();
+        let dest = Dir::new(tmp_dir.path().join("dest"));
+
+        let reported = Rc::new(Cell::new((0, 0)));
+        let progress = {
+            let reported = reported.clone();
+            move |done, total| reported.set((done, total))
+        };
+        assert_eq!(11, src.copy_to(&*dest, CopyOptions::new().progress(progress)).unwrap());
+        assert_eq!((11, 11), reported.get());
+        assert_eq!("hello", &dest.child("a/b/c.txt").read_string().unwrap());
+        #[cfg(unix)]
+        assert_eq!(Path::new("d.txt"), fs::read_link(dest.child("link")).unwrap());
+
+        let err = src.copy_to(&*dest, CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        src.child("d.txt").overwrite(b"WORLD!").unwrap();
+        let skip = CopyOptions::new().overwrite(Overwrite::Skip);
+        assert_eq!(0, src.copy_to(&*dest, skip).unwrap());
+        let always = CopyOptions::new().overwrite(Overwrite::Always).symlinks(Symlinks::Follow);
+        assert_eq!(17, src.copy_to(&*dest, always).unwrap());
+        assert_eq!("WORLD!", &dest.child("d.txt").read_string().unwrap());
+
+        let err = src.copy_to(src.child("a"), CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        let err = src.copy_to(src.child("new/sub"), CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert!(!src.child("new").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_over_link() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let target = dir.child("target.txt");
+        target.overwrite(b"keep me").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        src.create().unwrap();
+        ::std::os::unix::fs::symlink(&*target, src.child("link")).unwrap();
+
+        let dest = Dir::new(tmp_dir.path().join("dest"));
+        src.copy_to(&*dest, CopyOptions::new()).unwrap();
+        let follow = CopyOptions::new().overwrite(Overwrite::Always).symlinks(Symlinks::Follow);
+        let err = src.copy_to(&*dest, follow).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!("keep me", &target.read_string().unwrap());
+
+        let other = dir.child("other.txt");
+        other.overwrite(b"new").unwrap();
+        let link = dir.child("link.txt");
+        ::std::os::unix::fs::symlink(&*target, &*link).unwrap();
+        other.copy_to(&*link, CopyOptions::new().overwrite(Overwrite::Always)).unwrap();
+        assert_eq!("keep me", &target.read_string().unwrap());
+        assert!(!fs::symlink_metadata(&*link).unwrap().file_type().is_symlink());
+        assert_eq!("new", &link.read_string().unwrap());
+    }
+
+    #[test]
+    fn copy_file() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let src = dir.child("src.txt");
+        src.overwrite(b"content").unwrap();
+
+        let dest = dir.child("a/b/dest.txt");
+        let err = src.copy_to(&*dest, CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        assert_eq!(7, src.copy_to(&*dest, CopyOptions::new().create_parents(true)).unwrap());
+        assert_eq!("content", &dest.read_string().unwrap());
+
+        src.truncate(b"new").unwrap();
+        let err = src.copy_to(&*dest, CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        let always = CopyOptions::new().overwrite(Overwrite::Always);
+        assert_eq!(3, src.copy_to(&*dest, always).unwrap());
+        assert_eq!("new", &dest.read_string().unwrap());
+
+        #[cfg(unix)]
+        {
+            ::std::os::unix::fs::symlink("src.txt", dir.child("link")).unwrap();
+            let link = dir.child("link");
+            assert_eq!(0, link.copy_to(dir.child("link2"), CopyOptions::new()).unwrap());
+            assert!(fs::symlink_metadata(dir.child("link2")).unwrap().file_type().is_symlink());
+            let follow = CopyOptions::new().symlinks(Symlinks::Follow);
+            assert_eq!(3, link.copy_to(dir.child("copied.txt"), follow).unwrap());
+        }
+    }
+
+    #[test]
+    fn copy_preserve() {
+        use std::time::{Duration, SystemTime};
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        src.child_dir("sub").create().unwrap();
+        let file = src.child("sub/a.txt");
+        file.overwrite(b"a").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        let times = FileTimes::new().set_accessed(past).set_modified(past);
+        FileOpener::appending().open(&*file).unwrap().set_times(times).unwrap();
+        fs::File::open(&*src.child_dir("sub")).unwrap().set_times(times).unwrap();
+
+        let copy = dir_copy(&src, "plain", CopyOptions::new());
+        assert_ne!(past, fs::metadata(copy.child("sub/a.txt")).unwrap().modified().unwrap());
+
+        let copy = dir_copy(&src, "preserved", CopyOptions::new().preserve());
+        assert_eq!(past, fs::metadata(copy.child("sub/a.txt")).unwrap().modified().unwrap());
+        #[cfg(unix)]
+        assert_eq!(past, fs::metadata(copy.child_dir("sub")).unwrap().modified().unwrap());
+
+        let dest = tmp_dir.path().join("single.txt");
+        file.copy_to(&dest, CopyOptions::new().preserve_times(true)).unwrap();
+        assert_eq!(past, fs::metadata(&dest).unwrap().modified().unwrap());
+    }
+
+    fn dir_copy(src: &Dir, name: &str, options: CopyOptions) -> Dir {
+        let dest = src.parent().unwrap().child_dir(name);
+        src.copy_to(&*dest, options).unwrap();
+        dest
+    }
+
+    #[test]
+    fn reflink() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let src = dir.child("src.bin");
+        src.overwrite(b"shared data").unwrap();
+
+        // Whether cloning works depends on the filesystem of the temp dir.
+        let clone = dir.child("clone.bin");
+        match src.reflink_to(&*clone) {
+            Ok(file) => assert_eq!("shared data", &file.read_string().unwrap()),
+            Err(e) => {
+                assert_eq!(io::ErrorKind::Unsupported, e.kind());
+                assert!(!clone.exists());
+            }
+        }
+
+        // A failed clone leaves an existing destination as it was.
+        let kept = dir.child("kept.bin");
+        kept.overwrite(b"old").unwrap();
+        let always = CopyOptions::new().reflink(Reflink::Always).overwrite(Overwrite::Always);
+        match src.copy_to(&*kept, always) {
+            Ok(_) => assert_eq!("shared data", &kept.read_string().unwrap()),
+            Err(e) => {
+                assert_eq!(io::ErrorKind::Unsupported, e.kind());
+                assert_eq!("old", &kept.read_string().unwrap());
+            }
+        }
+
+        let auto = CopyOptions::new().reflink(Reflink::Auto);
+        assert_eq!(11, src.copy_to(dir.child("auto.bin"), auto).unwrap());
+        assert_eq!("shared data", &dir.child("auto.bin").read_string().unwrap());
+    }
+
+    #[test]
+    fn copy_large_file() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let src = dir.child("large.bin");
+        let data: Vec<u8> = (0..KERNEL_COPY_CHUNK * 2 + 12345).map(|i| (i % 251) as u8).collect();
+        src.overwrite(&data).unwrap();
+        let dest = dir.child("copy.bin");
+        assert_eq!(data.len() as u64, src.copy_to(&*dest, CopyOptions::new()).unwrap());
+        assert_eq!(data, dest.read_all().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_proc_file() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dest = tmp_dir.path().join("version");
+        let src = ::file::File::new("/proc/version");
+        let copied = src.copy_to(&dest, CopyOptions::new().symlinks(Symlinks::Follow)).unwrap();
+        assert!(copied > 0);
+        assert_eq!(fs::read(&dest).unwrap(), fs::read("/proc/version").unwrap());
+    }
+
+    #[test]
+    fn copy_file_cancel() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let src = dir.child("big.bin");
+        let len = 4 * default_buf_capacity() as u64;
+        src.overwrite(&vec![7; len as usize]).unwrap();
+
+        let mut calls = Vec::new();
+        let copied = src
+            .copy_to_with(dir.child("copy.bin"), CopyOptions::new(), |copied, total| {
+                calls.push((copied, total));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(Some(len), copied);
+        assert_eq!(Some(&(len, len)), calls.last());
+
+        let dest = dir.child("cancelled.bin");
+        let copied = src
+            .copy_to_with(&*dest, CopyOptions::new(), |copied, _| {
+                if copied > 0 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(None, copied);
+        assert!(!dest.exists());
+
+        let kept = dir.child("kept.bin");
+        kept.overwrite(b"old content").unwrap();
+        let always = CopyOptions::new().overwrite(Overwrite::Always);
+        let copied = src
+            .copy_to_with(&*kept, always, |copied, _| {
+                if copied > 0 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(None, copied);
+        assert_eq!("old content", &kept.read_string().unwrap());
+
+        let mut names: Vec<_> = fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(vec!["big.bin", "copy.bin", "kept.bin"], names);
+    }
+}
@@ -0,0 +1,1210 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, SeekFrom};
+use std::io::prelude::*;
+use std::ops;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+#[cfg(feature = "rayon")]
+use rayon::iter::ParallelIterator;
+
+#[cfg(feature = "zip")]
+use archive::{self, ZipOptions};
+use copy::{self, CopyOptions};
+use file::{File, FileOpener, Open};
+use file_set::FileSet;
+use glob::{self, Glob};
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+use hash::{self, Algorithm, Manifest, ManifestReport};
+use mirror::{self, Compare, DirDiff, SyncOptions, SyncReport};
+use replace::{self, Pattern, ReplaceOptions, ReplaceReport};
+use retention::{self, CleanupReport, RetentionPolicy};
+use retry::RetryOptions;
+use tmp::TmpDir;
+use tree::{self, TreeOptions};
+use usage::{self, CountOptions, Counts, SizeOptions};
+#[cfg(feature = "rayon")]
+use walk;
+use walk::{Walk, WalkEntry, WalkOptions};
+#[cfg(feature = "notify")]
+use watch::Watch;
+
+/// Size of the blocks hashed at both ends of a file by `find_duplicates`.
+const PARTIAL_HASH_BLOCK: u64 = 4 * 1024;
+
+/// The `Dir` object wraps `PathBuf` of a directory, as `File` does
+/// for regular files.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Dir {
+    path: PathBuf,
+}
+
+/// `SortBy` is the key `Dir::list_sorted` sorts entries on. Entries with
+/// the same key are ordered by ascending name.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SortBy {
+    Name,
+    /// Modification time.
+    Mtime,
+    Size,
+}
+
+/// A `SortBy` key with a direction, ascending unless made with `desc()`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Sort {
+    by: SortBy,
+    descending: bool,
+}
+
+/// The cached value entries are sorted on.
+#[derive(Eq, Ord, PartialEq, PartialOrd)]
+enum SortKey {
+    Name,
+    Mtime(SystemTime),
+    Size(u64),
+}
+
+/// Iterator over the entries of a directory, created by `Dir::entries`.
+/// Entries come in the order of the file system, without `.` and `..`.
+#[derive(Debug)]
+pub struct Entries {
+    inner: fs::ReadDir,
+}
+
+impl Dir {
+    /// Create a new owned `Dir` with given path.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Dir { path: path.as_ref().to_path_buf() }
+    }
+
+    /// Create a new temporary directory in the system temporary directory,
+    /// deleted when the returned guard is dropped, see `TmpDir`.
+    pub fn temp() -> io::Result<TmpDir> {
+        TmpDir::new()
+    }
+
+    /// Create a new temporary directory in this directory, see `TmpDir`.
+    pub fn temp_in(&self) -> io::Result<TmpDir> {
+        TmpDir::new_in(&self.path)
+    }
+
+    /// Whether the path exists and is a directory, following symbolic links.
+    pub fn exists(&self) -> bool {
+        self.path.is_dir()
+    }
+
+    /// Create the directory and its missing parents. Succeeds if the
+    /// directory already exists.
+    pub fn create(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.path)
+    }
+
+    /// Create the directory and its missing parents, and return whether
+    /// anything was created. Succeeds if the directory already exists.
+    pub fn create_all(&self) -> io::Result<bool> {
+        let existed = self.exists();
+        self.create()?;
+        Ok(!existed)
+    }
+
+    /// Like `create_all`, creating directories with the unix permission
+    /// bits `mode`, minus the process umask. Existing ones are left as is.
+    #[cfg(unix)]
+    pub fn create_all_with_mode(&self, mode: u32) -> io::Result<bool> {
+        use std::os::unix::fs::DirBuilderExt;
+        let existed = self.exists();
+        fs::DirBuilder::new().recursive(true).mode(mode).create(&self.path)?;
+        Ok(!existed)
+    }
+
+    /// Remove the directory, which must be empty, retrying transient
+    /// failures with the default `RetryOptions`. See `delete_all` to remove
+    /// its content too.
+    pub fn delete(&self) -> io::Result<()> {
+        RetryOptions::new().run(|| fs::remove_dir(&self.path))
+    }
+
+    /// Like `delete`, succeeding if the directory doesn't exist. Returns
+    /// whether it was removed.
+    pub fn delete_if_exists(&self) -> io::Result<bool> {
+        match self.delete() {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Remove the directory and everything in it, retrying transient
+    /// failures of every removal as `opts` describes, such as files still
+    /// in use on Windows.
+    ///
+    /// Symbolic links are removed, never followed: if this directory is
+    /// itself a link, only the link goes. Refuses with `InvalidInput` to
+    /// remove a file system root, such as `/` or `C:\`, or its content.
+    pub fn delete_all(&self, opts: &RetryOptions) -> io::Result<()> {
+        let meta = fs::symlink_metadata(&self.path)?;
+        if meta.file_type().is_symlink() {
+            return remove_link(&self.path, opts);
+        }
+        self.remove_contents(opts)?;
+        opts.run(|| fs::remove_dir(&self.path))
+    }
+
+    /// Remove everything in the directory but keep the directory itself,
+    /// with its permissions and ownership. Removals are retried and
+    /// symbolic links treated as in `delete_all`, except that this
+    /// directory being a link, the content of its target is removed.
+    pub fn clear(&self, opts: &RetryOptions) -> io::Result<()> {
+        self.remove_contents(opts)
+    }
+
+    fn remove_contents(&self, opts: &RetryOptions) -> io::Result<()> {
+        if is_root(&self.path)? {
+            let msg = format!("refusing to delete the content of {:?}", self.path);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let ty = entry.file_type()?;
+            if ty.is_dir() {
+                // `remove_dir_all` opens every directory without following
+                // links, so one swapped for a link while removing is safe.
+                opts.run(|| fs::remove_dir_all(&path))?;
+            } else if ty.is_symlink() {
+                remove_link(&path, opts)?;
+            } else {
+                remove_file(&path, opts)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write everything in this directory into the zip file `archive`, and
+    /// return the number of entries written. The archive is replaced
+    /// atomically, and left out if it is inside this directory.
+    #[cfg(feature = "zip")]
+    pub fn zip_to<P: AsRef<Path>>(&self, archive: P, options: ZipOptions) -> io::Result<usize> {
+        archive::zip_dir(&self.path, archive.as_ref(), &options)
+    }
+
+    /// Copy everything in this directory into `dest`, which is created if
+    /// missing, and return the number of bytes copied. Fails with
+    /// `InvalidInput` if `dest` is inside this directory.
+    pub fn copy_to<P: AsRef<Path>>(&self, dest: P, mut options: CopyOptions) -> io::Result<u64> {
+        copy::copy_dir(&self.path, dest.as_ref(), &mut options)
+    }
+
+    /// Move this directory to `dest`, which must not exist, and return the
+    /// moved `Dir`. When `dest` is on another file system, where renaming
+    /// fails, the content is copied with `options`, which can report the
+    /// progress, keeping its metadata as `CopyOptions::preserve` does, then
+    /// this directory is removed. A failed copy removes `dest` again.
+    pub fn move_to<P: AsRef<Path>>(&self, dest: P, options: CopyOptions) -> io::Result<Dir> {
+        let dest = dest.as_ref();
+        match fs::rename(&self.path, dest) {
+            Ok(()) => Ok(Dir::new(dest)),
+            Err(ref e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                self.move_by_copy(dest, options)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn move_by_copy(&self, dest: &Path, options: CopyOptions) -> io::Result<Dir> {
+        if fs::symlink_metadata(dest).is_ok() {
+            let msg = format!("{} already exists", dest.display());
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, msg));
+        }
+        // A move keeps what a rename would, and leaves nothing behind when
+        // it fails, so that it can be tried again.
+        let mut options = options.preserve();
+        if let Err(e) = copy::copy_dir(&self.path, dest, &mut options) {
+            let _ = fs::remove_dir_all(dest);
+            return Err(e);
+        }
+        self.delete_all(&RetryOptions::new())?;
+        Ok(Dir::new(dest))
+    }
+
+    /// Make `dest` match this directory: copy new and changed files and
+    /// links, and with `SyncOptions::delete` remove what isn't here.
+    pub fn sync_to<P>(&self, dest: P, options: &SyncOptions) -> io::Result<SyncReport>
+    where
+        P: AsRef<Path>,
+    {
+        mirror::sync_dir(&self.path, dest.as_ref(), options)
+    }
+
+    /// Compare the tree under this directory with the one under `other`,
+    /// by size and modification time, see `DirDiff`.
+    pub fn diff<P: AsRef<Path>>(&self, other: P) -> io::Result<DirDiff> {
+        self.diff_with(other, Compare::default())
+    }
+
+    /// Compare the tree under this directory with the one under `other`,
+    /// deciding that files differ as `compare` tells.
+    pub fn diff_with<P: AsRef<Path>>(&self, other: P, compare: Compare) -> io::Result<DirDiff> {
+        mirror::diff_dirs(&self.path, other.as_ref(), compare)
+    }
+
+    /// Draw the tree under this directory down to `depth` levels, as the
+    /// `tree` command does, for debug output and test snapshots. The first
+    /// line is `.`, and unreadable directories are shown as errors.
+    pub fn render_tree(&self, depth: usize, options: &TreeOptions) -> String {
+        tree::render(&self.path, depth, options)
+    }
+
+    /// Hash every regular file under this directory, see `Manifest`.
+    #[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+    pub fn manifest(&self, algorithm: Algorithm) -> io::Result<Manifest> {
+        hash::manifest(&self.path, algorithm)
+    }
+
+    /// Check the regular files under this directory against `manifest`,
+    /// typically parsed from a stored one with `Manifest::parse`.
+    #[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+    pub fn verify_manifest(&self, manifest: &Manifest) -> io::Result<ManifestReport> {
+        hash::verify(&self.path, manifest)
+    }
+
+    /// Delete the files in this directory that `policy` doesn't retain,
+    /// such as those older than a week, see `RetentionPolicy`.
+    pub fn cleanup(&self, policy: &RetentionPolicy) -> io::Result<CleanupReport> {
+        retention::cleanup(&self.path, policy)
+    }
+
+    /// The sum of the sizes of the files under this directory.
+    pub fn total_size(&self, options: &SizeOptions) -> io::Result<u64> {
+        usage::total_size(&self.path, options)
+    }
+
+    /// Count the entries under this directory by kind, without collecting
+    /// them.
+    pub fn count(&self, options: &CountOptions) -> io::Result<Counts> {
+        usage::count(&self.path, options)
+    }
+
+    /// The total size of every entry of this directory, largest first,
+    /// to see what takes space.
+    pub fn size_by_child(&self, options: &SizeOptions) -> io::Result<Vec<(File, u64)>> {
+        let mut sizes = Vec::new();
+        for child in self.list()? {
+            let size = usage::total_size(&child, options)?;
+            sizes.push((child, size));
+        }
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(sizes)
+    }
+
+    /// The file named `name` in this directory.
+    pub fn child<P: AsRef<Path>>(&self, name: P) -> File {
+        File::new(self.path.join(name))
+    }
+
+    /// The subdirectory named `name` in this directory.
+    pub fn child_dir<P: AsRef<Path>>(&self, name: P) -> Dir {
+        Dir::new(self.path.join(name))
+    }
+
+    /// The directory containing this one, if any.
+    pub fn parent(&self) -> Option<Dir> {
+        self.path.parent().map(Dir::new)
+    }
+
+    /// The entries of this directory, sorted by path.
+    pub fn list(&self) -> io::Result<Vec<File>> {
+        self.entries()?.sorted()
+    }
+
+    /// The entries of this directory, sorted by `sort` such as `SortBy::Size`
+    /// or `SortBy::Mtime.desc()`. Metadata is read once per entry, following
+    /// symbolic links when they aren't broken.
+    pub fn list_sorted<S: Into<Sort>>(&self, sort: S) -> io::Result<Vec<File>> {
+        let sort = sort.into();
+        let mut keyed = Vec::new();
+        for file in self.entries()? {
+            let file = file?;
+            let key = match sort.by {
+                SortBy::Name => SortKey::Name,
+                by => {
+                    let meta = fs::metadata(&*file).or_else(|_| fs::symlink_metadata(&*file))?;
+                    match by {
+                        SortBy::Size => SortKey::Size(meta.len()),
+                        _ => SortKey::Mtime(meta.modified()?),
+                    }
+                }
+            };
+            keyed.push((key, file));
+        }
+        keyed.sort_by(|a, b| {
+            let by_key = if sort.descending { b.0.cmp(&a.0) } else { a.0.cmp(&b.0) };
+            let by_name = if sort.by == SortBy::Name && sort.descending {
+                b.1.cmp(&a.1)
+            } else {
+                a.1.cmp(&b.1)
+            };
+            by_key.then(by_name)
+        });
+        Ok(keyed.into_iter().map(|(_, f)| f).collect())
+    }
+
+    /// Iterate over the entries of this directory lazily.
+    pub fn entries(&self) -> io::Result<Entries> {
+        Ok(Entries { inner: fs::read_dir(&self.path)? })
+    }
+
+    /// The regular files in this directory, sorted by path. Symbolic links
+    /// to regular files are included.
+    pub fn files(&self) -> io::Result<Vec<File>> {
+        self.files_matching(|_| true)
+    }
+
+    /// The subdirectories of this directory, sorted by path. Symbolic links
+    /// to directories are included.
+    pub fn dirs(&self) -> io::Result<Vec<Dir>> {
+        let mut dirs = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let (path, ty) = resolve_type(entry?)?;
+            if ty.is_dir() {
+                dirs.push(Dir::from(path));
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    /// The regular files in this directory with extension `ext`, given
+    /// without the dot, sorted by path.
+    pub fn with_extension<S: AsRef<OsStr>>(&self, ext: S) -> io::Result<Vec<File>> {
+        self.files_matching(|f| f.extension() == Some(ext.as_ref()))
+    }
+
+    /// The regular files in this directory accepted by `predicate`,
+    /// sorted by path.
+    pub fn files_matching<F>(&self, mut predicate: F) -> io::Result<Vec<File>>
+    where
+        F: FnMut(&File) -> bool,
+    {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let (path, ty) = resolve_type(entry?)?;
+            let file = File::from(path);
+            if ty.is_file() && predicate(&file) {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Iterate over every descendant of this directory, see `Walk`.
+    pub fn walk(&self) -> Walk {
+        self.walk_with(WalkOptions::new())
+    }
+
+    /// Iterate over the descendants of this directory that `options` select.
+    pub fn walk_with(&self, options: WalkOptions) -> Walk {
+        Walk::new(&self.path, options)
+    }
+
+    /// Watch this directory and everything under it for changes, see `Watch`.
+    /// Fails with `NotFound` if the directory doesn't exist.
+    #[cfg(feature = "notify")]
+    pub fn watch(&self) -> io::Result<Watch> {
+        Watch::new(&self.path)
+    }
+
+    /// Visit every descendant of this directory on the rayon thread pool,
+    /// in no particular order. Symbolic links aren't followed, and a
+    /// directory which can't be read yields an error.
+    #[cfg(feature = "rayon")]
+    pub fn par_walk(&self) -> impl ParallelIterator<Item = io::Result<WalkEntry>> {
+        walk::par_walk(&self.path)
+    }
+
+    /// Call `f` on every descendant of this directory from the rayon thread
+    /// pool. Stops at the first error, of `f` or of reading a directory.
+    #[cfg(feature = "rayon")]
+    pub fn par_for_each<F>(&self, f: F) -> io::Result<()>
+    where
+        F: Fn(&WalkEntry) -> io::Result<()> + Send + Sync,
+    {
+        walk::par_walk(&self.path).try_for_each(|entry| f(&entry?))
+    }
+
+    /// Iterate over the descendants of this directory whose relative path
+    /// matches `pattern`, such as `src/**/*.rs`, see `Glob`. Fails with
+    /// `InvalidInput` if the pattern is malformed.
+    pub fn glob(&self, pattern: &str) -> io::Result<Glob> {
+        Glob::new(&self.path, pattern)
+    }
+
+    /// The first entry under this directory accepted by `predicate`, which
+    /// can look at its name, type and metadata. The tree is walked breadth
+    /// first, so the match nearest to this directory is found, and the walk
+    /// stops there. Entries which can't be read are skipped.
+    pub fn find_first<F>(&self, mut predicate: F) -> io::Result<Option<File>>
+    where
+        F: FnMut(&WalkEntry) -> bool,
+    {
+        fs::read_dir(&self.path)?;
+        let options = WalkOptions::new().breadth_first(true);
+        Ok(self
+            .walk_with(options)
+            .filter_map(Result::ok)
+            .find(|e| predicate(e))
+            .map(WalkEntry::into_file))
+    }
+
+    /// Every entry under this directory accepted by `predicate`, in walk
+    /// order. Entries which can't be read are skipped.
+    pub fn find_all<F>(&self, mut predicate: F) -> io::Result<Vec<File>>
+    where
+        F: FnMut(&WalkEntry) -> bool,
+    {
+        fs::read_dir(&self.path)?;
+        Ok(self
+            .walk()
+            .filter_map(Result::ok)
+            .filter(|e| predicate(e))
+            .map(WalkEntry::into_file)
+            .collect())
+    }
+
+    /// The most recently modified regular file in this directory.
+    pub fn newest_file(&self) -> io::Result<Option<File>> {
+        self.by_modified(|_| true, true)
+    }
+
+    /// The least recently modified regular file in this directory.
+    pub fn oldest_file(&self) -> io::Result<Option<File>> {
+        self.by_modified(|_| true, false)
+    }
+
+    /// The most recently modified regular file in this directory whose
+    /// name matches `pattern`, such as `checkpoint-*.bin`, see `Glob`.
+    pub fn newest_matching(&self, pattern: &str) -> io::Result<Option<File>> {
+        let matches = glob::name_matcher(pattern)?;
+        self.by_modified(|f| matches(&f.file_name().unwrap_or_default().to_string_lossy()), true)
+    }
+
+    /// The least recently modified regular file in this directory whose
+    /// name matches `pattern`.
+    pub fn oldest_matching(&self, pattern: &str) -> io::Result<Option<File>> {
+        let matches = glob::name_matcher(pattern)?;
+        self.by_modified(|f| matches(&f.file_name().unwrap_or_default().to_string_lossy()), false)
+    }
+
+    /// The newest or oldest of the files accepted by `filter`. Ties are
+    /// broken by path, so that the result is deterministic.
+    fn by_modified<F>(&self, filter: F, newest: bool) -> io::Result<Option<File>>
+    where
+        F: FnMut(&File) -> bool,
+    {
+        let mut found: Option<(SystemTime, File)> = None;
+        for file in self.files_matching(filter)? {
+            let modified = fs::metadata(&*file)?.modified()?;
+            let better = match found {
+                None => true,
+                Some((t, _)) if newest => modified > t,
+                Some((t, _)) => modified < t,
+            };
+            if better {
+                found = Some((modified, file));
+            }
+        }
+        Ok(found.map(|(_, f)| f))
+    }
+
+    /// Find groups of files with identical content under this directory.
+    ///
+    /// Files are grouped by size first, then by a hash of their first and
+    /// last blocks, and only the remaining candidates are hashed in full,
+    /// on as many threads as there are CPUs. Files with equal hashes are
+    /// then compared byte for byte, so a hash collision never reports
+    /// different files as duplicates. Empty files and symbolic links are
+    /// ignored.
+    pub fn find_duplicates(&self) -> io::Result<Vec<FileSet>> {
+        let mut by_size: HashMap<u64, Vec<File>> = HashMap::new();
+        for (file, len) in self.files_recursive()? {
+            if len > 0 {
+                by_size.entry(len).or_default().push(file);
+            }
+        }
+        // Small files are read whole by the partial hash anyway.
+        let (mut candidates, large): (Vec<_>, Vec<_>) = by_size
+            .into_iter()
+            .filter(|g| g.1.len() > 1)
+            .partition(|&(len, _)| len <= 2 * PARTIAL_HASH_BLOCK);
+        candidates.extend(regroup(large, |f, len| hash_content(f, Some(len)))?);
+        let candidates = regroup(candidates, |f, _| hash_content(f, None))?;
+        let duplicates = confirm(candidates)?;
+
+        let mut sets: Vec<FileSet> = duplicates
+            .into_iter()
+            .map(|mut files| {
+                files.sort();
+                FileSet::from(files)
+            })
+            .collect();
+        sets.sort_by(|a, b| a[0].cmp(&b[0]));
+        Ok(sets)
+    }
+
+    /// Replace `pattern` with `replacement` in every text file under this
+    /// directory accepted by `options`. Files are streamed line by line and
+    /// rewritten atomically, only when something matched. Files with a NUL
+    /// byte near their start, or which aren't UTF-8, are skipped as binary.
+    /// A file which fails is recorded in the report and the others are
+    /// still processed.
+    pub fn replace_in_files<P: Into<Pattern>>(
+        &self,
+        pattern: P,
+        replacement: &str,
+        options: &ReplaceOptions,
+    ) -> io::Result<ReplaceReport> {
+        let pattern = pattern.into();
+        let mut files: Vec<File> = self.files_recursive()?
+            .into_iter()
+            .map(|(f, _)| f)
+            .filter(|f| options.accepts(f))
+            .collect();
+        files.sort();
+
+        let mut report = ReplaceReport {
+            dry_run: options.is_dry_run(),
+            scanned: files.len(),
+            ..ReplaceReport::default()
+        };
+        for file in files {
+            match replace::replace_in_file(&file, &pattern, replacement, options.is_dry_run()) {
+                Ok(Some(change)) => {
+                    if change.replacements > 0 {
+                        report.changes.push(change);
+                    }
+                }
+                Ok(None) => report.skipped_binary.push(file),
+                Err(e) => report.failed.push((file, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Collect regular files under this directory with their length,
+    /// without following symbolic links.
+    fn files_recursive(&self) -> io::Result<Vec<(File, u64)>> {
+        let mut files = Vec::new();
+        for entry in self.walk() {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                let len = entry.metadata()?.len();
+                files.push((entry.into_file(), len));
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl SortBy {
+    /// Sort in ascending order.
+    pub fn asc(self) -> Sort {
+        Sort {
+            by: self,
+            descending: false,
+        }
+    }
+
+    /// Sort in descending order.
+    pub fn desc(self) -> Sort {
+        Sort {
+            by: self,
+            descending: true,
+        }
+    }
+}
+
+impl From<SortBy> for Sort {
+    fn from(by: SortBy) -> Sort {
+        by.asc()
+    }
+}
+
+impl Entries {
+    /// Collect the remaining entries sorted by path, for a deterministic order.
+    pub fn sorted(self) -> io::Result<Vec<File>> {
+        let mut files = self.collect::<io::Result<Vec<File>>>()?;
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl Iterator for Entries {
+    type Item = io::Result<File>;
+
+    fn next(&mut self) -> Option<io::Result<File>> {
+        self.inner.next().map(|entry| entry.map(|e| File::from(e.path())))
+    }
+}
+
+/// Whether `path` is, or resolves to, the root of a file system.
+fn is_root(path: &Path) -> io::Result<bool> {
+    Ok(path.as_os_str().is_empty() || path.canonicalize()?.parent().is_none())
+}
+
+/// Remove the file `path`. A read-only file can't be removed on Windows
+/// however often it is retried, so the attribute is cleared first.
+fn remove_file(path: &Path, opts: &RetryOptions) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            let mut perms = meta.permissions();
+            if perms.readonly() {
+                perms.set_readonly(false);
+                fs::set_permissions(path, perms)?;
+            }
+        }
+    }
+    opts.run(|| fs::remove_file(path))
+}
+
+/// Remove the symbolic link `path`. Links to directories are directories
+/// themselves on Windows.
+fn remove_link(path: &Path, opts: &RetryOptions) -> io::Result<()> {
+    opts.run(|| fs::remove_file(path).or_else(|e| fs::remove_dir(path).map_err(|_| e)))
+}
+
+/// The path and type of `entry`. The type comes with the entry on most
+/// platforms, only symbolic links take another call to be followed.
+fn resolve_type(entry: fs::DirEntry) -> io::Result<(PathBuf, fs::FileType)> {
+    let path = entry.path();
+    let mut ty = entry.file_type()?;
+    if ty.is_symlink() {
+        if let Ok(meta) = fs::metadata(&path) {
+            ty = meta.file_type();
+        }
+    }
+    Ok((path, ty))
+}
+
+/// Split every group of same sized files by `hash`, computed in parallel,
+/// keeping only groups that still have more than one file.
+fn regroup<F>(groups: Vec<(u64, Vec<File>)>, hash: F) -> io::Result<Vec<(u64, Vec<File>)>>
+where
+    F: Fn(&File, u64) -> io::Result<u64> + Sync,
+{
+    let jobs: Vec<(u64, File)> = groups
+        .into_iter()
+        .flat_map(|(len, files)| files.into_iter().map(move |f| (len, f)))
+        .collect();
+    let hashes = par_map(&jobs, |&(len, ref f)| hash(f, len))?;
+
+    let mut regrouped: HashMap<(u64, u64), Vec<File>> = HashMap::new();
+    for ((len, file), h) in jobs.into_iter().zip(hashes) {
+        regrouped.entry((len, h)).or_default().push(file);
+    }
+    Ok(regrouped
+        .into_iter()
+        .filter(|g| g.1.len() > 1)
+        .map(|((len, _), files)| (len, files))
+        .collect())
+}
+
+/// Split every group of files with equal hashes into groups of files with
+/// byte for byte identical content, keeping only groups that still have
+/// more than one file.
+fn confirm(groups: Vec<(u64, Vec<File>)>) -> io::Result<Vec<Vec<File>>> {
+    let mut confirmed = Vec::new();
+    for (_, files) in groups {
+        let mut same: Vec<Vec<File>> = Vec::new();
+        for file in files {
+            let mut found = None;
+            for (i, group) in same.iter().enumerate() {
+                if same_content(&group[0], &file)? {
+                    found = Some(i);
+                    break;
+                }
+            }
+            match found {
+                Some(i) => same[i].push(file),
+                None => same.push(vec![file]),
+            }
+        }
+        confirmed.extend(same.into_iter().filter(|g| g.len() > 1));
+    }
+    Ok(confirmed)
+}
+
+/// Apply `f` to every item on scoped threads, one contiguous slice per CPU.
+fn par_map<T, R, F>(items: &[T], f: F) -> io::Result<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> io::Result<R> + Sync,
+{
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let per_thread = cmp::max(1, items.len().div_ceil(threads));
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(per_thread)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<io::Result<Vec<R>>>()))
+            .collect();
+        let mut results = Vec::with_capacity(items.len());
+        for handle in handles {
+            results.extend(handle.join().expect("hashing thread panicked")?);
+        }
+        Ok(results)
+    })
+}
+
+/// Hash the whole content of `file`, or with `partial` of the given length,
+/// only its first and last blocks.
+fn hash_content(file: &File, partial: Option<u64>) -> io::Result<u64> {
+    let mut f = FileOpener::readonly().open(&**file)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    match partial {
+        Some(len) if len > 2 * PARTIAL_HASH_BLOCK => {
+            let block = &mut buf[..PARTIAL_HASH_BLOCK as usize];
+            f.read_exact(block)?;
+            hasher.write(block);
+            f.seek(SeekFrom::Start(len - PARTIAL_HASH_BLOCK))?;
+            f.read_exact(block)?;
+            hasher.write(block);
+        }
+        _ => loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.write(&buf[..n]);
+        },
+    }
+    Ok(hasher.finish())
+}
+
+/// Whether the files at `a` and `b` have the same content, compared
+/// byte for byte.
+pub(crate) fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut fa = FileOpener::readonly().open(a)?;
+    let mut fb = FileOpener::readonly().open(b)?;
+    if fa.metadata()?.len() != fb.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let n = read_full(&mut fa, &mut buf_a)?;
+        if n != read_full(&mut fb, &mut buf_b)? || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Read into `buf` until it is full or the reader ends.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl ops::Deref for Dir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.path.as_ref()
+    }
+}
+
+impl AsRef<Path> for Dir {
+    fn as_ref(&self) -> &Path {
+        self.path.as_ref()
+    }
+}
+
+impl From<PathBuf> for Dir {
+    fn from(path: PathBuf) -> Dir {
+        Dir { path }
+    }
+}
+
+impl From<File> for Dir {
+    fn from(file: File) -> Dir {
+        Dir::new(file)
+    }
+}
+
+impl From<Dir> for File {
+    fn from(dir: Dir) -> File {
+        File::from(dir.path)
+    }
+}
+
+impl From<Dir> for PathBuf {
+    fn from(dir: Dir) -> PathBuf {
+        dir.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::fs;
+    use self::tempdir::TempDir;
+    use super::*;
+
+    #[test]
+    fn create_and_children() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path()).child_dir("a").child_dir("b");
+        assert!(!dir.exists());
+        dir.create().unwrap();
+        dir.create().unwrap();
+        assert!(dir.exists());
+
+        let file = dir.child("c.txt");
+        file.overwrite(b"c").unwrap();
+        assert!(!Dir::from(file.clone()).exists());
+        assert_eq!(Some(dir.clone()), file.parent_dir());
+        assert_eq!(tmp_dir.path().join("a"), *dir.parent().unwrap());
+        assert_eq!(File::new(&*dir), File::from(dir));
+    }
+
+    #[test]
+    fn create_all() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path().join("x/y"));
+        assert!(dir.create_all().unwrap());
+        assert!(!dir.create_all().unwrap());
+        let file = File::new(tmp_dir.path().join("x/z"));
+        assert!(file.mkdirs().unwrap());
+        assert!(file.is_dir());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_all_with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path().join("private/inner"));
+        assert!(dir.create_all_with_mode(0o700).unwrap());
+        for d in &[dir.clone(), dir.parent().unwrap()] {
+            assert_eq!(0o700, fs::metadata(&**d).unwrap().permissions().mode() & 0o777);
+        }
+    }
+
+    #[test]
+    fn delete() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path().join("empty"));
+        dir.create().unwrap();
+        dir.child("file.txt").touch().unwrap();
+        assert!(dir.delete().is_err());
+
+        dir.child("file.txt").delete().unwrap();
+        dir.delete().unwrap();
+        assert!(!dir.exists());
+        assert!(!dir.delete_if_exists().unwrap());
+        dir.create().unwrap();
+        assert!(dir.delete_if_exists().unwrap());
+    }
+
+    #[test]
+    fn delete_all() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let outside = Dir::new(tmp_dir.path().join("outside"));
+        outside.create().unwrap();
+        outside.child("keep.txt").overwrite(b"").unwrap();
+        let dir = Dir::new(tmp_dir.path().join("tree"));
+        dir.child_dir("a/b").create().unwrap();
+        dir.child("a/b/c.txt").overwrite(b"").unwrap();
+        dir.child("d.txt").overwrite(b"").unwrap();
+        #[cfg(unix)]
+        {
+            ::std::os::unix::fs::symlink(&*outside, dir.child("a/link")).unwrap();
+            let root_link = tmp_dir.path().join("root-link");
+            ::std::os::unix::fs::symlink(&*outside, &root_link).unwrap();
+            Dir::new(&root_link).delete_all(&RetryOptions::never()).unwrap();
+            assert!(fs::symlink_metadata(&root_link).is_err());
+        }
+
+        dir.delete_all(&RetryOptions::new()).unwrap();
+        assert!(!dir.exists());
+        assert!(outside.child("keep.txt").exists());
+
+        assert!(is_root(tmp_dir.path().ancestors().last().unwrap()).unwrap());
+        assert!(!is_root(tmp_dir.path()).unwrap());
+    }
+
+    #[test]
+    fn clear() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path().join("cache"));
+        dir.child_dir("a/b").create().unwrap();
+        dir.child("a/b/c.bin").overwrite(b"").unwrap();
+        dir.child("d.bin").overwrite(b"").unwrap();
+        dir.clear(&RetryOptions::new()).unwrap();
+        assert!(dir.exists());
+        assert!(dir.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn move_to() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        src.child_dir("a").create().unwrap();
+        src.child("a/b.txt").overwrite(b"moved").unwrap();
+
+        let moved = src.move_to(tmp_dir.path().join("renamed"), CopyOptions::new()).unwrap();
+        assert!(!src.exists());
+        assert_eq!("moved", &moved.child("a/b.txt").read_string().unwrap());
+
+        let dest = tmp_dir.path().join("copied");
+        let copied = moved.move_by_copy(&dest, CopyOptions::new()).unwrap();
+        assert!(!moved.exists());
+        assert_eq!("moved", &copied.child("a/b.txt").read_string().unwrap());
+        let err = copied.move_by_copy(tmp_dir.path(), CopyOptions::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+
+        let old = SystemTime::UNIX_EPOCH + ::std::time::Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(copied.child("a/b.txt"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let kept = copied.move_by_copy(&tmp_dir.path().join("kept"), CopyOptions::new()).unwrap();
+        assert_eq!(old, fs::metadata(kept.child("a/b.txt")).unwrap().modified().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn move_by_copy_failed() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        src.child_dir("a").create().unwrap();
+        src.child("a/b.txt").overwrite(b"moved").unwrap();
+        // Sockets can't be opened, so the copy fails after it started.
+        let _socket = ::std::os::unix::net::UnixListener::bind(src.child("z.sock")).unwrap();
+
+        let dest = tmp_dir.path().join("dest");
+        assert!(src.move_by_copy(&dest, CopyOptions::new()).is_err());
+        assert!(!dest.exists());
+        assert!(src.child("a/b.txt").exists());
+    }
+
+    #[test]
+    fn find() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("a/b/c").create().unwrap();
+        dir.child_dir("z").create().unwrap();
+        for name in &["a/b/c/app.toml", "z/app.toml", "a/other.txt"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        let is_config = |e: &WalkEntry| e.file().file_name() == Some("app.toml".as_ref());
+        assert_eq!(Some(dir.child("z/app.toml")), dir.find_first(is_config).unwrap());
+        let mut all = dir.find_all(is_config).unwrap();
+        all.sort();
+        assert_eq!(vec![dir.child("a/b/c/app.toml"), dir.child("z/app.toml")], all);
+        let non_empty = |e: &WalkEntry| {
+            e.file_type().is_file() && e.metadata().map(|m| m.len() > 0).unwrap_or(false)
+        };
+        assert_eq!(None, dir.find_first(non_empty).unwrap());
+        assert!(dir.child_dir("missing").find_first(|_| true).is_err());
+    }
+
+    #[test]
+    fn newest_and_oldest() {
+        use std::time::{Duration, SystemTime};
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        assert_eq!(None, dir.newest_file().unwrap());
+        let now = SystemTime::now();
+        for (i, name) in ["ckpt-1.bin", "ckpt-2.bin", "notes.txt"].iter().enumerate() {
+            let f = dir.child(name);
+            f.overwrite(b"").unwrap();
+            let modified = now - Duration::from_secs(100 - 10 * i as u64);
+            fs::File::options().write(true).open(&*f).unwrap().set_modified(modified).unwrap();
+        }
+        assert_eq!(Some(dir.child("notes.txt")), dir.newest_file().unwrap());
+        assert_eq!(Some(dir.child("ckpt-1.bin")), dir.oldest_file().unwrap());
+        assert_eq!(Some(dir.child("ckpt-2.bin")), dir.newest_matching("ckpt-*.bin").unwrap());
+        assert_eq!(None, dir.oldest_matching("*.log").unwrap());
+        assert!(dir.newest_matching("a/*.bin").is_err());
+    }
+
+    #[test]
+    fn list_sorted() {
+        use std::time::{Duration, SystemTime};
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let now = SystemTime::now();
+        for &(name, len, age) in &[("a", 30, 1), ("b", 10, 3), ("c", 20, 2)] {
+            let f = dir.child(name);
+            f.overwrite(&vec![0; len]).unwrap();
+            let modified = now - Duration::from_secs(age * 60);
+            fs::File::options().write(true).open(&*f).unwrap().set_modified(modified).unwrap();
+        }
+        let names = |files: Vec<File>| -> String {
+            files.iter().map(|f| f.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+        assert_eq!("abc", &names(dir.list_sorted(SortBy::Name).unwrap()));
+        assert_eq!("cba", &names(dir.list_sorted(SortBy::Name.desc()).unwrap()));
+        assert_eq!("bca", &names(dir.list_sorted(SortBy::Size).unwrap()));
+        assert_eq!("acb", &names(dir.list_sorted(SortBy::Mtime.desc()).unwrap()));
+    }
+
+    #[test]
+    fn list() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        for name in &["b.txt", "a.txt", "c"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        dir.child_dir("sub").create().unwrap();
+
+        let names: Vec<_> = dir
+            .list()
+            .unwrap()
+            .iter()
+            .map(|f| f.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(vec!["a.txt", "b.txt", "c", "sub"], names);
+        assert_eq!(4, dir.entries().unwrap().count());
+        assert!(dir.child_dir("missing").list().is_err());
+    }
+
+    #[test]
+    fn filtered_listings() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        for name in &["b.log", "a.log", "c.txt"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        dir.child_dir("sub").create().unwrap();
+        dir.child_dir("old.log").create().unwrap();
+
+        let names = |files: Vec<File>| -> Vec<String> {
+            files.iter().map(|f| f.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+        assert_eq!(vec!["a.log", "b.log", "c.txt"], names(dir.files().unwrap()));
+        assert_eq!(vec!["a.log", "b.log"], names(dir.with_extension("log").unwrap()));
+        let not_b = dir.files_matching(|f| !f.ends_with("b.log")).unwrap();
+        assert_eq!(vec!["a.log", "c.txt"], names(not_b));
+        assert_eq!(vec![dir.child_dir("old.log"), dir.child_dir("sub")], dir.dirs().unwrap());
+    }
+
+    #[test]
+    fn find_duplicates() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        fs::create_dir(tmp_dir.path().join("sub")).unwrap();
+        let big_a: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let mut big_b = big_a.clone();
+        big_b[10_000] ^= 1;
+        let contents: &[(&str, &[u8])] = &[
+            ("a.txt", b"same"),
+            ("sub/b.txt", b"same"),
+            ("c.txt", b"diff"),
+            ("big-1.bin", &big_a),
+            ("sub/big-2.bin", &big_a),
+            ("big-3.bin", &big_b),
+            ("empty-1", b""),
+            ("empty-2", b""),
+        ];
+        for &(name, content) in contents {
+            File::new(tmp_dir.path().join(name)).overwrite(content).unwrap();
+        }
+
+        let dups = Dir::new(tmp_dir.path()).find_duplicates().unwrap();
+        let names: Vec<Vec<String>> = dups
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .map(|f| f.strip_prefix(tmp_dir.path()).unwrap().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(vec![vec!["a.txt", "sub/b.txt"], vec!["big-1.bin", "sub/big-2.bin"]], names);
+    }
+
+    #[test]
+    fn same_content() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let big: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let mut other = big.clone();
+        other[150_000] ^= 1;
+        let contents: &[(&str, &[u8])] = &[
+            ("a", &big),
+            ("b", &big),
+            ("c", &other),
+            ("d", &big[..1000]),
+        ];
+        for &(name, content) in contents {
+            File::new(tmp_dir.path().join(name)).overwrite(content).unwrap();
+        }
+        let same = |a: &str, b: &str| {
+            super::same_content(&tmp_dir.path().join(a), &tmp_dir.path().join(b)).unwrap()
+        };
+        assert!(same("a", "b"));
+        assert!(!same("a", "c"));
+        assert!(!same("a", "d"));
+    }
+
+    #[test]
+    fn replace_in_files() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        fs::create_dir(tmp_dir.path().join("sub")).unwrap();
+        let a = File::new(tmp_dir.path().join("a.txt"));
+        let b = File::new(tmp_dir.path().join("sub/b.md"));
+        let bin = File::new(tmp_dir.path().join("c.bin"));
+        a.overwrite(b"foo bar foo\r\nbaz\n").unwrap();
+        b.overwrite(b"no match\nfoo").unwrap();
+        bin.overwrite(b"foo\0foo").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+
+        let dry = ReplaceOptions::new().dry_run(true);
+        let report = dir.replace_in_files("foo", "qux", &dry).unwrap();
+        assert_eq!(3, report.total_replacements());
+        assert_eq!(1, report.skipped_binary.len());
+        assert!(report.diff().contains("@@ -1,2 +1,2 @@\n-foo bar foo\n+qux bar qux\n baz\n"));
+        assert_eq!("(dry run) 3 replacements in 2 of 3 files, 1 binary files skipped, 0 failed",
+                   &report.to_string());
+        assert_eq!("foo bar foo\r\nbaz\n", &a.read_string().unwrap());
+
+        let only_txt = ReplaceOptions::new().filter(|f| f.extension() == Some("txt".as_ref()));
+        let report = dir.replace_in_files("foo", "qux", &only_txt).unwrap();
+        assert_eq!(2, report.total_replacements());
+        assert_eq!("qux bar qux\r\nbaz\n", &a.read_string().unwrap());
+        assert_eq!("no match\nfoo", &b.read_string().unwrap());
+        let leftovers = fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with('.'))
+            .count();
+        assert_eq!(0, leftovers);
+    }
+}
@@ -1,18 +1,146 @@
+use std::collections::hash_map::RandomState;
 use std::convert::From;
-use std::fs::{self, OpenOptions};
-use std::io::{self, BufReader, BufWriter};
+use std::fs::{self, FileTimes, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, BufWriter, SeekFrom};
 use std::io::prelude::*;
-use std::ops;
+use std::ops::{self, ControlFlow};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use atomic::AtomicFile;
+use copy::{self, CopyOptions, Overwrite, Reflink, Symlinks};
+use dir::Dir;
+#[cfg(feature = "encoding")]
+use encoding_rs::Encoding;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+use hash::{self, Algorithm, Digest};
+use iter::{Chunks, LinesReversed};
+use lock::{FileLock, LockMode};
+use magic::{self, ContentType};
+#[cfg(feature = "mmap")]
+use mmap::{ReadMap, WriteMap};
+#[cfg(unix)]
+use open::WithMode;
+use open::{Fallback, Retrying, SyncParent, Timeout};
+use progress::{ProgressReader, ProgressWriter};
+use replace::{self, Pattern};
+use retry::{Completion, RetryOptions};
+use sys;
+use tee::TeeWriter;
+use time::Tm;
+use text::{self, Bom, Endian, LineEnding};
+#[cfg(feature = "encoding")]
+use text::TranscodeOptions;
+use throttle::ThrottledWriter;
+use tmp::TmpFile;
+
+/// Size of the blocks `count_lines` reads at once.
+const COUNT_BLOCK_SIZE: usize = 256 * 1024;
+
+/// Size of the blocks `shred` writes at once.
+const SHRED_BUF_SIZE: usize = 64 * 1024;
+
+/// Capacity of the buffers `buf_reader` and `buf_writer` use, see
+/// `set_default_buf_capacity`.
+static BUF_CAPACITY: AtomicUsize = AtomicUsize::new(8 * 1024);
+
+/// Set the capacity of the readers and writers `File::buf_reader` and
+/// `File::buf_writer` return, crate-wide. It is 8 KiB by default, as with
+/// `BufReader::new`; larger buffers pay off for sequential scans and large
+/// exports on fast storage.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn set_default_buf_capacity(capacity: usize) {
+    assert!(capacity > 0, "buffer capacity must be positive");
+    BUF_CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// The capacity of the readers and writers `File::buf_reader` and
+/// `File::buf_writer` return.
+pub fn default_buf_capacity() -> usize {
+    BUF_CAPACITY.load(Ordering::Relaxed)
+}
 
 /// `Open` has a responsible for handling file system entry
 /// from path and other information, such as open options,
 /// for further operation such as `io::Read` or `io::Write`.
 pub trait Open {
     fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File>;
+
+    /// The `OpenOptions` this opener opens files with, if it is based on
+    /// them, so that combinators can add to them instead of working around.
+    fn open_options(&self) -> Option<OpenOptions> {
+        None
+    }
+
+    /// Create files with the unix permission bits `mode`.
+    #[cfg(unix)]
+    fn with_mode(self, mode: u32) -> WithMode<Self>
+    where
+        Self: Sized,
+    {
+        WithMode::new(self, mode)
+    }
+
+    /// Sync the parent directory after opening, so that a newly created
+    /// file survives a crash. Does nothing on non-unix platforms.
+    fn then_sync_parent(self) -> SyncParent<Self>
+    where
+        Self: Sized,
+    {
+        SyncParent::new(self)
+    }
+
+    /// Retry opening with `options` while it fails transiently.
+    fn retrying(self, options: RetryOptions) -> Retrying<Self>
+    where
+        Self: Sized,
+    {
+        Retrying::new(self, options)
+    }
+
+    /// Fail with `TimedOut` when opening takes longer than `timeout`, e.g.
+    /// on a hanging network file system.
+    fn timeout(self, timeout: Duration) -> Timeout<Self>
+    where
+        Self: Sized + Clone + Send + 'static,
+    {
+        Timeout::new(self, timeout)
+    }
+
+    /// Open with `other` when this opener fails.
+    fn fallback<O: Open>(self, other: O) -> Fallback<Self, O>
+    where
+        Self: Sized,
+    {
+        Fallback::new(self, other)
+    }
+}
+
+/// `OpenDyn` is the object safe counterpart of `Open`, for openers chosen
+/// at runtime or kept in collections as `Box<dyn OpenDyn>`. Its streams
+/// are boxed, so implementors can wrap the file, e.g. to compress it.
+///
+/// Every `Open` is an `OpenDyn` handing out the plain file.
+pub trait OpenDyn {
+    /// Open `path` for reading.
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+
+    /// Open `path` for writing.
+    fn open_writer(&self, path: &Path) -> io::Result<Box<dyn Write>>;
 }
 
 /// `CreateMode` defines if a file must be created when it opens.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum CreateMode {
     CreateNew,
     IfNotExists,
@@ -21,15 +149,57 @@ pub enum CreateMode {
 
 /// `WriteOption` represents how an input would be handled
 /// on further `Write` operation on a file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum WriteOption {
     Append,
     Overwrite,
     Truncate,
 }
 
+/// `SyncPolicy` tells how much of a written file must reach the disk
+/// before a write operation returns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyncPolicy {
+    /// Leave it to the operating system.
+    None,
+    /// Sync file content, like `File::sync_data`.
+    Data,
+    /// Sync file content and metadata, like `File::sync_all`.
+    All,
+}
+
+/// `BackupPolicy` names the copy `File::write_with_backup` keeps of the
+/// previous content of a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BackupPolicy {
+    /// `name.bak`, replacing the previous backup.
+    Bak,
+    /// `name.20240131T235959.999Z.bak`, keeping every backup.
+    Timestamped,
+}
+
 /// `FileOpener` is typical implementation of `Open` trait, handles that
 /// how a file must be created, or what operations will be allowed on open file.
-pub struct FileOpener(CreateMode, bool, Option<WriteOption>);
+///
+/// Besides the presets such as `FileOpener::truncate()`, any combination can
+/// be built from `FileOpener::new()`, such as reading and appending with
+/// `FileOpener::new().read(true).write(WriteOption::Append)`.
+#[derive(Clone, Debug)]
+pub struct FileOpener {
+    create: CreateMode,
+    read: bool,
+    write: Option<WriteOption>,
+    create_parents: bool,
+    direct: bool,
+    nonblocking: bool,
+    lock: Option<LockMode>,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    #[cfg(unix)]
+    custom_flags: i32,
+    #[cfg(windows)]
+    custom_flags: u32,
+}
 
 /// The `File` object wraps `PathBuf` and provides convenient functions
 /// to perform I/O operation.
@@ -42,39 +212,215 @@ pub struct File {
 }
 
 impl FileOpener {
+    /// Open existing files only, neither for reading nor writing.
+    /// Set what is allowed with the other methods.
+    pub fn new() -> Self {
+        FileOpener {
+            create: CreateMode::Never,
+            read: false,
+            write: None,
+            create_parents: false,
+            direct: false,
+            nonblocking: false,
+            lock: None,
+            #[cfg(unix)]
+            mode: None,
+            #[cfg(any(unix, windows))]
+            custom_flags: 0,
+        }
+    }
+
+    /// Set whether and how the file is created if it does not exist.
+    pub fn create(mut self, mode: CreateMode) -> Self {
+        self.create = mode;
+        self
+    }
+
+    /// Allow reading.
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Allow writing, with `option` telling what happens to the content.
+    pub fn write(mut self, option: WriteOption) -> Self {
+        self.write = Some(option);
+        self
+    }
+
     /// Open file for appending, fails if file does not exist.
     pub fn appending() -> Self {
-        FileOpener(CreateMode::Never, false, Some(WriteOption::Append))
+        FileOpener::new().write(WriteOption::Append)
     }
 
     /// Open file for writing, create new file if the file does not exist.
     /// The content of file will be truncated.
     pub fn truncate() -> Self {
-        FileOpener(CreateMode::IfNotExists, false, Some(WriteOption::Truncate))
+        FileOpener::new().create(CreateMode::IfNotExists).write(WriteOption::Truncate)
     }
 
     /// Open file for writing, create new file if the file does not exist.
     /// The content of the file will be overwritten.
     pub fn overwrite() -> Self {
-        FileOpener(CreateMode::IfNotExists, false, Some(WriteOption::Overwrite))
+        FileOpener::new().create(CreateMode::IfNotExists).write(WriteOption::Overwrite)
     }
 
     /// Open file for appending, create new file if the file does not exist.
     /// The content of the file will be preserved.
     pub fn append_or_create() -> Self {
-        FileOpener(CreateMode::IfNotExists, false, Some(WriteOption::Append))
+        FileOpener::new().create(CreateMode::IfNotExists).write(WriteOption::Append)
+    }
+
+    /// Create a new file for writing, fails if the file already exists.
+    /// Useful for lock files and outputs that must be written only once.
+    pub fn create_new_write() -> Self {
+        FileOpener::new().create(CreateMode::CreateNew).write(WriteOption::Overwrite)
+    }
+
+    /// Create a new file for appending, fails if the file already exists.
+    pub fn create_new_append() -> Self {
+        FileOpener::new().create(CreateMode::CreateNew).write(WriteOption::Append)
     }
 
     /// Open file for reading, fails if the file does not exist.
     pub fn readonly() -> Self {
-        FileOpener(CreateMode::Never, true, None)
+        FileOpener::new().read(true)
+    }
+
+    /// Open file for reading and writing in place, fails if the file
+    /// does not exist. The content of the file is kept as is.
+    pub fn read_write() -> Self {
+        FileOpener::new().read(true).write(WriteOption::Overwrite)
+    }
+
+    /// Open file for reading and appending, create new file if the file
+    /// does not exist. Writes always go to the end of the file.
+    pub fn read_append() -> Self {
+        FileOpener::new().read(true).create(CreateMode::IfNotExists).write(WriteOption::Append)
+    }
+
+    /// Create an unnamed temporary file in `dir`, which disappears when
+    /// dropped, or on a crash, unless `TmpFile::link_into` names it.
+    ///
+    /// On Linux this uses `O_TMPFILE`. Elsewhere, or on file systems that
+    /// don't support it, a file is created and removed right away.
+    pub fn tmpfile_in<P: AsRef<Path>>(dir: P) -> io::Result<TmpFile> {
+        TmpFile::new_in(dir.as_ref())
+    }
+
+    /// Create the missing parent directories of the file before opening it.
+    pub fn create_parents(mut self, create_parents: bool) -> Self {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Set the permission bits of a newly created file, before the umask.
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Pass additional `open(2)` flags, such as `O_NOFOLLOW`.
+    #[cfg(unix)]
+    pub fn custom_flags(mut self, flags: i32) -> Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    /// Pass additional `CreateFileW` flags, such as `FILE_FLAG_WRITE_THROUGH`.
+    #[cfg(windows)]
+    pub fn custom_flags(mut self, flags: u32) -> Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    /// Bypass the operating system's cache: `O_DIRECT` on Linux,
+    /// `F_NOCACHE` on macOS and `FILE_FLAG_NO_BUFFERING` on Windows.
+    /// Opening fails with `Unsupported` elsewhere.
+    ///
+    /// Reads and writes then need buffers, lengths and offsets aligned to
+    /// the block size, see `AlignedBuf`; buffered readers and writers of
+    /// this crate don't meet that.
+    pub fn direct(mut self, direct: bool) -> Self {
+        self.direct = direct;
+        self
+    }
+
+    /// Open with `O_NONBLOCK`, so that opening a named pipe for reading
+    /// doesn't wait for a writer, and reads and writes on pipes and other
+    /// special files fail with `WouldBlock` instead of waiting.
+    /// Ignored on Windows, fails with `Unsupported` on unknown unix platforms.
+    pub fn nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Take a shared advisory lock right after opening, blocking while
+    /// another process holds an exclusive one.
+    pub fn lock_shared(mut self) -> Self {
+        self.lock = Some(LockMode::Shared);
+        self
+    }
+
+    /// Take an exclusive advisory lock right after opening, blocking while
+    /// another process holds any lock on the file.
+    pub fn lock_exclusive(mut self) -> Self {
+        self.lock = Some(LockMode::Exclusive);
+        self
+    }
+
+    /// Open the file with `open`, locked as `lock_shared` or `lock_exclusive`
+    /// set, or exclusively by default, and return a guard which unlocks it
+    /// when dropped. A truncating opener only truncates once locked.
+    pub fn open_locked<P: AsRef<Path>>(&self, path: P) -> io::Result<FileLock> {
+        let mode = self.lock.unwrap_or(LockMode::Exclusive);
+        let unlocked = FileOpener {
+            lock: None,
+            ..self.without_truncate()
+        };
+        let lock = FileLock::new(unlocked.open(path)?, mode)?;
+        if self.write == Some(WriteOption::Truncate) {
+            lock.set_len(0)?;
+        }
+        Ok(lock)
+    }
+
+    /// This opener writing in place instead of truncating, so that a
+    /// locking open truncates only after the lock is held, not while
+    /// another process still works on the file.
+    fn without_truncate(&self) -> FileOpener {
+        let write = match self.write {
+            Some(WriteOption::Truncate) => Some(WriteOption::Overwrite),
+            other => other,
+        };
+        FileOpener {
+            write,
+            ..self.clone()
+        }
+    }
+
+    /// Whether opening takes more than `OpenOptions`.
+    fn needs_open_step(&self) -> bool {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        let direct_step = self.direct && sys::O_DIRECT.is_none();
+        #[cfg(target_os = "macos")]
+        let direct_step = self.direct;
+        #[cfg(not(unix))]
+        let direct_step = false;
+        #[cfg(unix)]
+        let nonblocking_step = self.nonblocking && sys::O_NONBLOCK.is_none();
+        #[cfg(not(unix))]
+        let nonblocking_step = false;
+        self.create_parents || self.lock.is_some() || direct_step || nonblocking_step
     }
 
     /// Construct owned `OpenOptions` from this opener.
+    #[allow(clippy::wrong_self_convention)]
     fn into_open_options(&self) -> OpenOptions {
         let mut opts = OpenOptions::new();
         // set creation mode
-        match self.0 {
+        match self.create {
             CreateMode::CreateNew => {
                 opts.create_new(true);
             }
@@ -84,9 +430,9 @@ impl FileOpener {
             _ => {}
         }
         // set read option
-        opts.read(self.1);
+        opts.read(self.read);
         // set write option
-        match self.2 {
+        match self.write {
             Some(WriteOption::Append) => {
                 opts.append(true);
             }
@@ -94,17 +440,147 @@ impl FileOpener {
                 opts.write(true);
             }
             Some(WriteOption::Truncate) => {
-                opts.truncate(true);
+                opts.write(true).truncate(true);
             }
             None => {}
         }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            if let Some(mode) = self.mode {
+                opts.mode(mode);
+            }
+            let direct = match sys::O_DIRECT {
+                Some(flag) if self.direct => flag,
+                _ => 0,
+            };
+            let nonblocking = match sys::O_NONBLOCK {
+                Some(flag) if self.nonblocking => flag,
+                _ => 0,
+            };
+            opts.custom_flags(self.custom_flags | direct | nonblocking);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            let direct = if self.direct { sys::FILE_FLAG_NO_BUFFERING } else { 0 };
+            opts.custom_flags(self.custom_flags | direct);
+        }
         opts
     }
 }
 
+impl SyncPolicy {
+    /// Whether this policy syncs anything at all.
+    pub fn is_enabled(&self) -> bool {
+        *self != SyncPolicy::None
+    }
+
+    /// Sync `f` according to this policy.
+    pub fn apply(&self, f: &fs::File) -> io::Result<()> {
+        match *self {
+            SyncPolicy::None => Ok(()),
+            SyncPolicy::Data => f.sync_data(),
+            SyncPolicy::All => f.sync_all(),
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// The backup file of `file` under this policy.
+    fn backup_of(&self, file: &Path) -> PathBuf {
+        let mut name = file.file_name().unwrap_or_default().to_os_string();
+        if let BackupPolicy::Timestamped = *self {
+            name.push(format!(".{}", Tm::now().basic()));
+        }
+        name.push(".bak");
+        file.with_file_name(name)
+    }
+}
+
+impl Default for FileOpener {
+    fn default() -> Self {
+        FileOpener::new()
+    }
+}
+
+impl<O: Open> OpenDyn for O {
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(self.open(path)?))
+    }
+
+    fn open_writer(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(self.open(path)?))
+    }
+}
+
+impl<O: Open + ?Sized> Open for &O {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        (**self).open(path)
+    }
+
+    fn open_options(&self) -> Option<OpenOptions> {
+        (**self).open_options()
+    }
+}
+
+/// Plain `OpenOptions`, for code which already configures them.
+impl Open for OpenOptions {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        OpenOptions::open(self, path)
+    }
+
+    fn open_options(&self) -> Option<OpenOptions> {
+        Some(self.clone())
+    }
+}
+
 impl Open for FileOpener {
     fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
-        self.into_open_options().open(&path)
+        if self.create_parents {
+            File::new(&path).ensure_parent()?;
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            if self.direct && sys::O_DIRECT.is_none() {
+                let msg = "direct I/O is not supported on this platform";
+                return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+            }
+        }
+        #[cfg(unix)]
+        {
+            if self.nonblocking && sys::O_NONBLOCK.is_none() {
+                let msg = "non-blocking open is not supported on this platform";
+                return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+            }
+        }
+        let f = match self.lock {
+            Some(_) => self.without_truncate().into_open_options().open(&path)?,
+            None => self.into_open_options().open(&path)?,
+        };
+        #[cfg(target_os = "macos")]
+        {
+            if self.direct {
+                sys::disable_cache(&f)?;
+            }
+        }
+        if let Some(mode) = self.lock {
+            mode.acquire(&f)?;
+            if self.write == Some(WriteOption::Truncate) {
+                f.set_len(0)?;
+            }
+        }
+        Ok(f)
+    }
+
+    /// Creating parents, locking or some direct I/O setups take more than
+    /// `OpenOptions`, so combinators have to go through `open` then.
+    fn open_options(&self) -> Option<OpenOptions> {
+        if self.needs_open_step() {
+            None
+        } else {
+            Some(self.into_open_options())
+        }
     }
 }
 
@@ -119,118 +595,1697 @@ impl File {
         opt.open(&self.path)
     }
 
+    /// The directory containing this file, if any.
+    pub fn parent_dir(&self) -> Option<Dir> {
+        self.path.parent().map(Dir::new)
+    }
+
+    /// Create a directory at the path of this `File`, with its missing
+    /// parents, and return whether anything was created. See
+    /// `Dir::create_all`.
+    pub fn mkdirs(&self) -> io::Result<bool> {
+        Dir::new(&self.path).create_all()
+    }
+
+    /// Create the parent directories of the file if they are missing.
+    pub fn ensure_parent(&self) -> io::Result<()> {
+        match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+            _ => Ok(()),
+        }
+    }
+
     pub fn create_if_absent(&self) -> io::Result<fs::File> {
         self.open_with(FileOpener::append_or_create())
     }
 
+    /// Create the file empty if absent, and set its access and modification
+    /// times to now, like the `touch` utility. An existing file is only
+    /// opened to set its times, so read-only files and directories work too.
+    pub fn touch(&self) -> io::Result<()> {
+        let f = match fs::OpenOptions::new().write(true).create_new(true).open(&self.path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let mut opts = fs::OpenOptions::new();
+                #[cfg(windows)]
+                {
+                    use std::os::windows::fs::OpenOptionsExt;
+                    // FILE_WRITE_ATTRIBUTES, and FILE_FLAG_BACKUP_SEMANTICS
+                    // to open directories.
+                    opts.access_mode(0x100).custom_flags(0x0200_0000);
+                }
+                #[cfg(not(windows))]
+                opts.read(true);
+                opts.open(&self.path)?
+            }
+            Err(e) => return Err(e),
+        };
+        let now = SystemTime::now();
+        f.set_times(FileTimes::new().set_accessed(now).set_modified(now))
+    }
+
+    /// Open a buffered reader with the crate-wide default capacity, see
+    /// `set_default_buf_capacity`.
     pub fn buf_reader(&self) -> io::Result<BufReader<fs::File>> {
+        self.buf_reader_with_capacity(default_buf_capacity())
+    }
+
+    /// Open a buffered reader with a buffer of `capacity` bytes.
+    pub fn buf_reader_with_capacity(&self, capacity: usize) -> io::Result<BufReader<fs::File>> {
         let f = FileOpener::readonly().open(&self.path)?;
-        Ok(BufReader::new(f))
+        Ok(BufReader::with_capacity(capacity, f))
     }
 
+    /// Open a buffered writer with the crate-wide default capacity, see
+    /// `set_default_buf_capacity`.
     pub fn buf_writer<O: Open>(&self, opt: O) -> io::Result<BufWriter<fs::File>> {
+        self.buf_writer_with_capacity(opt, default_buf_capacity())
+    }
+
+    /// Open a buffered writer with a buffer of `capacity` bytes.
+    pub fn buf_writer_with_capacity<O: Open>(
+        &self,
+        opt: O,
+        capacity: usize,
+    ) -> io::Result<BufWriter<fs::File>> {
         let f = opt.open(&self.path)?;
-        Ok(BufWriter::new(f))
+        Ok(BufWriter::with_capacity(capacity, f))
+    }
+
+    /// Open a buffered writer which writes to the file at no more than
+    /// `bytes_per_sec` bytes per second on average, e.g. to keep a backup
+    /// job from saturating a shared disk.
+    pub fn buf_writer_throttled<O: Open>(
+        &self,
+        opt: O,
+        bytes_per_sec: u64,
+    ) -> io::Result<BufWriter<ThrottledWriter<fs::File>>> {
+        let f = opt.open(&self.path)?;
+        let w = ThrottledWriter::new(f, bytes_per_sec);
+        Ok(BufWriter::with_capacity(default_buf_capacity(), w))
+    }
+
+    /// Open every file of `files` with `opt` and return a writer which
+    /// duplicates writes to all of them, each through its own buffer.
+    /// More destinations, such as standard output, can be added with
+    /// `TeeWriter::with`.
+    pub fn tee_with<O: Open>(files: &[File], opt: O) -> io::Result<TeeWriter> {
+        let mut tee = TeeWriter::new();
+        for file in files {
+            tee = tee.with(file.buf_writer(&opt)?);
+        }
+        Ok(tee)
     }
 
     pub fn read_all(&self) -> io::Result<Vec<u8>> {
         let mut v = Vec::new();
-        let mut r = self.buf_reader()?;
-        r.read_to_end(&mut v)?;
+        self.read_all_into(&mut v)?;
         Ok(v)
     }
 
+    /// Read the whole file as UTF-8 text, without its BOM if it has one.
     pub fn read_string(&self) -> io::Result<String> {
         let mut s = String::new();
-        let mut r = self.buf_reader()?;
-        r.read_to_string(&mut s)?;
+        self.read_string_into(&mut s)?;
         Ok(s)
     }
 
-    pub fn append(&self, buf: &[u8]) -> io::Result<()> {
-        self.write_all_with(buf, FileOpener::appending())
+    /// Read the whole file, calling `progress(bytes_read, total)` as data
+    /// comes in, `total` being the length of the file when it was opened.
+    pub fn read_all_with_progress<F>(&self, progress: F) -> io::Result<Vec<u8>>
+    where
+        F: FnMut(u64, u64),
+    {
+        let f = FileOpener::readonly().open(&self.path)?;
+        let total = f.metadata()?.len();
+        let mut v = Vec::with_capacity(total as usize);
+        let r = BufReader::with_capacity(default_buf_capacity(), f);
+        let mut r = ProgressReader::new(r, total, progress);
+        r.read_to_end(&mut v)?;
+        Ok(v)
     }
 
-    pub fn overwrite(&self, buf: &[u8]) -> io::Result<()> {
-        self.write_all_with(buf, FileOpener::overwrite())
+    /// Read the whole file into `buf`, replacing its content but reusing
+    /// its allocation. Returns the number of bytes read.
+    pub fn read_all_into(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        buf.clear();
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        let len = f.metadata().map(|m| m.len() as usize).unwrap_or(0);
+        buf.reserve(len);
+        f.read_to_end(buf)
     }
 
-    pub fn truncate(&self, buf: &[u8]) -> io::Result<()> {
-        self.write_all_with(buf, FileOpener::truncate())
+    /// Read the whole file into `buf` as UTF-8 text, without its BOM if it
+    /// has one, replacing its content but reusing its allocation. On error
+    /// `buf` is left empty.
+    pub fn read_string_into(&self, buf: &mut String) -> io::Result<()> {
+        buf.clear();
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        let len = f.metadata().map(|m| m.len() as usize).unwrap_or(0);
+        buf.reserve(len);
+        if let Err(e) = f.read_to_string(buf) {
+            buf.clear();
+            return Err(e);
+        }
+        if buf.starts_with('\u{feff}') {
+            buf.drain(..'\u{feff}'.len_utf8());
+        }
+        Ok(())
     }
 
-    pub fn write_all_with<O: Open>(&self, buf: &[u8], opt: O) -> io::Result<()> {
-        let mut w = self.buf_writer(opt)?;
-        w.write_all(buf)?;
-        w.get_ref().sync_all()?;
-        Ok(())
+    /// Read the whole file as text, decoded as UTF-8 or UTF-16 according to
+    /// its BOM, and return the BOM found if any. Without a BOM the content
+    /// must be UTF-8.
+    pub fn read_string_bom(&self) -> io::Result<(Option<Bom>, String)> {
+        let bytes = self.read_all()?;
+        match Bom::detect(&bytes) {
+            Some(bom) => Ok((Some(bom), bom.decode(&bytes)?)),
+            None => {
+                let s = String::from_utf8(bytes).map_err(text::invalid_data)?;
+                Ok((None, s))
+            }
+        }
     }
-}
 
-impl Default for File {
-    fn default() -> Self {
-        File { path: PathBuf::new() }
+    /// Count lines the way `lines()` would, i.e. a last line without
+    /// terminator counts too. The file is scanned in large blocks for
+    /// newline bytes, without decoding or allocating per line.
+    pub fn count_lines(&self) -> io::Result<u64> {
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        let mut buf = vec![0; COUNT_BLOCK_SIZE];
+        let mut count = 0;
+        let mut last = b'\n';
+        loop {
+            let n = match f.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            count += buf[..n].iter().filter(|&&b| b == b'\n').count() as u64;
+            last = buf[n - 1];
+        }
+        if last != b'\n' {
+            count += 1;
+        }
+        Ok(count)
     }
-}
 
-impl ops::Deref for File {
-    type Target = Path;
+    /// Return the first `n` lines of the file, without line terminators.
+    pub fn head(&self, n: usize) -> io::Result<Vec<String>> {
+        self.buf_reader()?.lines().take(n).collect()
+    }
 
-    fn deref(&self) -> &Path {
-        self.path.as_ref()
+    /// Return the last `n` lines of the file, without line terminators.
+    /// Only the end of the file is read, see `lines_reversed`.
+    pub fn tail(&self, n: usize) -> io::Result<Vec<String>> {
+        let mut lines = self.lines_reversed()?.take(n).collect::<io::Result<Vec<_>>>()?;
+        lines.reverse();
+        Ok(lines)
     }
-}
 
-impl From<PathBuf> for File {
-    fn from(path: PathBuf) -> File {
-        File { path: path }
+    /// Iterate over lines from the end of the file towards the beginning,
+    /// reading the file backwards in blocks.
+    pub fn lines_reversed(&self) -> io::Result<LinesReversed> {
+        let f = FileOpener::readonly().open(&self.path)?;
+        LinesReversed::new(f)
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Iterate over the content in chunks of `size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn chunks(&self, size: usize) -> io::Result<Chunks> {
+        let f = FileOpener::readonly().open(&self.path)?;
+        Ok(Chunks::new(f, size))
+    }
 
-    extern crate tempdir;
+    /// Map the whole file into memory for reading, instead of copying
+    /// it into a buffer. See `ReadMap` for the caveats.
+    #[cfg(feature = "mmap")]
+    pub fn map_read(&self) -> io::Result<ReadMap> {
+        let f = FileOpener::readonly().open(&self.path)?;
+        ReadMap::new(&f)
+    }
 
-    use std::io::prelude::*;
-    use std::path::Path;
-    use self::tempdir::TempDir;
-    use super::*;
+    /// Map the file into memory for reading and writing, after setting its
+    /// length to `len`, creating it if absent. See `WriteMap` for the caveats.
+    #[cfg(feature = "mmap")]
+    pub fn map_write(&self, len: u64) -> io::Result<WriteMap> {
+        let f = FileOpener::overwrite().read(true).open(&self.path)?;
+        f.set_len(len)?;
+        WriteMap::new(&f)
+    }
 
-    fn test_dir() -> io::Result<TempDir> {
-        let dir = TempDir::new("good-files-test")?;
-        Ok(dir)
+    /// Compute the checksum or digest of the content with `algorithm`,
+    /// streaming it through a buffered reader.
+    #[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+    pub fn hash(&self, algorithm: Algorithm) -> io::Result<Digest> {
+        hash::digest_reader(self.buf_reader()?, algorithm)
     }
 
-    #[test]
-    fn file_object() {
-        let f = File::new("/path/to/some/file");
-        assert_eq!(Path::new("/path/to/some/file"), &f.path);
+    /// Tell the type of content from the first bytes of the file,
+    /// regardless of its extension.
+    pub fn detect_type(&self) -> io::Result<ContentType> {
+        let f = FileOpener::readonly().open(&self.path)?;
+        let mut head = Vec::with_capacity(magic::SNIFF_LEN);
+        f.take(magic::SNIFF_LEN as u64).read_to_end(&mut head)?;
+        Ok(ContentType::sniff(&head))
     }
 
-    #[test]
-    #[should_panic]
-    fn open_readonly() {
-        let tmp_dir = test_dir().unwrap();
-        let path = tmp_dir.path().join("panics.txt");
-        let mut f = FileOpener::readonly()
-            .open(&path)
-            .unwrap();
-        let _ = f.write(b"this should never be written").unwrap();
+    /// Stream the whole file into `w`, returning the number of bytes copied.
+    /// The copy goes through one buffer, or a kernel-side copy where the
+    /// platform allows it.
+    pub fn copy_to_writer<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<u64> {
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        io::copy(&mut f, w)
     }
 
-    #[test]
-    fn read_write_ops() {
-        // TODO: revisit when good-files' utilities are ready
-        let tmp_dir = test_dir().unwrap();
-        let path = tmp_dir.path().join("foo.txt");
-        let f = File::new(&path);
+    /// Read exactly `len` bytes starting at `offset`.
+    /// Fails with `UnexpectedEof` if the file ends before the range does.
+    pub fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        f.seek(SeekFrom::Start(offset))?;
+        let mut v = vec![0; len];
+        f.read_exact(&mut v)?;
+        Ok(v)
+    }
 
-        // writing
-        f.overwrite(b"some text\n2nd line").unwrap();
+    /// Shrink or extend the file to exactly `len` bytes, extending with
+    /// zeros, like `std::fs::File::set_len`. Fails if the file does not exist.
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        let f = FileOpener::new().write(WriteOption::Overwrite).open(&self.path)?;
+        f.set_len(len)
+    }
 
-        // reading
-        let s = f.read_string().unwrap();
-        assert_eq!("some text\n2nd line", &s);
+    /// Reserve disk space for `len` bytes up front, so that writing that
+    /// much later can't fail for lack of space. The file is created if
+    /// absent and grows to `len` bytes if shorter, filled with zeros;
+    /// it is never shrunk. Uses `posix_fallocate` on Linux and extends
+    /// the file elsewhere, writing zeros where space isn't allocated
+    /// otherwise.
+    pub fn preallocate(&self, len: u64) -> io::Result<()> {
+        let f = FileOpener::overwrite().open(&self.path)?;
+        sys::preallocate(&f, len)?;
+        f.sync_all()
+    }
+
+    /// Read the last `n` bytes of the file, or all of it if it is shorter.
+    pub fn tail_bytes(&self, n: u64) -> io::Result<Vec<u8>> {
+        let mut f = FileOpener::readonly().open(&self.path)?;
+        let len = f.metadata()?.len();
+        let n = n.min(len);
+        f.seek(SeekFrom::Start(len - n))?;
+        let mut v = Vec::with_capacity(n as usize);
+        f.take(n).read_to_end(&mut v)?;
+        Ok(v)
+    }
+
+    /// Read into `buf` from `offset`, like `pread`, returning bytes read.
+    /// No seek position is shared between calls.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let f = FileOpener::readonly().open(&self.path)?;
+        sys::read_at(&f, buf, offset)
+    }
+
+    /// Write `buf` at `offset`, like `pwrite`, returning bytes written.
+    /// The file is created if absent and other content is kept intact.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let f = FileOpener::overwrite().open(&self.path)?;
+        sys::write_at(&f, buf, offset)
+    }
+
+    /// Write all of `buf` at `offset`, patching the file in place without
+    /// truncating it. The file is opened for reading and writing, and
+    /// created if absent; writing past its end extends it, filling any gap
+    /// with zeros.
+    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let f = FileOpener::overwrite().read(true).open(&self.path)?;
+        sys::write_all_at(&f, buf, offset)
+    }
+
+    /// Read the whole file as text in `encoding`, e.g. `encoding_rs::SHIFT_JIS`.
+    /// A BOM of that encoding is stripped, malformed input is an error.
+    #[cfg(feature = "encoding")]
+    pub fn read_string_with_encoding(&self, encoding: &'static Encoding) -> io::Result<String> {
+        text::decode(&self.read_all()?, encoding)
+    }
+
+    /// Read the whole file as text, guessing its encoding from the BOM or
+    /// the content, and return the encoding that was used.
+    #[cfg(feature = "encoding")]
+    pub fn read_string_detect_encoding(&self) -> io::Result<(String, &'static Encoding)> {
+        let bytes = self.read_all()?;
+        let encoding = text::detect(&bytes);
+        Ok((text::decode(&bytes, encoding)?, encoding))
+    }
+
+    /// Read the whole file as UTF-16 text. A BOM decides the byte order
+    /// when present, otherwise `endian` is used.
+    pub fn read_utf16(&self, endian: Endian) -> io::Result<String> {
+        let bytes = self.read_all()?;
+        match Bom::detect(&bytes) {
+            Some(Bom::Utf16Le) => text::decode_utf16(&bytes[2..], Endian::Little),
+            Some(Bom::Utf16Be) => text::decode_utf16(&bytes[2..], Endian::Big),
+            _ => text::decode_utf16(&bytes, endian),
+        }
+    }
+
+    /// Write `s` as UTF-16 in the given byte order, starting with a BOM
+    /// as Windows tools expect.
+    pub fn write_utf16<O: Open>(&self, s: &str, endian: Endian, opt: O) -> io::Result<()> {
+        let mut buf = Bom::utf16(endian).as_bytes().to_vec();
+        text::encode_utf16(s, endian, &mut buf);
+        self.write_all_with(&buf, opt)
+    }
+
+    /// Convert this file from encoding `from` into `dest` in encoding `to`,
+    /// streaming in constant memory. `dest` is created or truncated.
+    /// Returns how many conversion errors were replaced or skipped
+    /// according to `opts`.
+    #[cfg(feature = "encoding")]
+    pub fn transcode_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        from: &'static Encoding,
+        to: &'static Encoding,
+        opts: &TranscodeOptions,
+    ) -> io::Result<usize> {
+        let r = self.buf_reader()?;
+        let w = File::new(dest).buf_writer(FileOpener::truncate())?;
+        text::transcode(r, w, from, to, opts)
+    }
+
+    pub fn append(&self, buf: &[u8]) -> io::Result<()> {
+        self.write_all_with(buf, FileOpener::appending())
+    }
+
+    /// Append `line` followed by `\n`, creating the file if needed.
+    /// Appending from several processes at once isn't synchronized, use a
+    /// lock if lines may be written concurrently.
+    pub fn append_line(&self, line: &str) -> io::Result<()> {
+        let mut buf = String::with_capacity(line.len() + 1);
+        buf.push_str(line);
+        buf.push('\n');
+        self.write_all_with(buf.as_bytes(), FileOpener::append_or_create())
+    }
+
+    pub fn overwrite(&self, buf: &[u8]) -> io::Result<()> {
+        self.write_all_with(buf, FileOpener::overwrite())
+    }
+
+    pub fn truncate(&self, buf: &[u8]) -> io::Result<()> {
+        self.write_all_with(buf, FileOpener::truncate())
+    }
+
+    /// Write `s` with every line break converted to `ending`, whatever
+    /// line endings it was written with.
+    pub fn write_text<O: Open>(&self, s: &str, ending: LineEnding, opt: O) -> io::Result<()> {
+        self.write_all_with(ending.normalize(s).as_bytes(), opt)
+    }
+
+    /// Write every item of `lines` followed by `\n`, through one buffered writer.
+    pub fn write_lines<I, O>(&self, lines: I, opt: O) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: Open,
+    {
+        self.write_lines_with_ending(lines, LineEnding::Lf, opt)
+    }
+
+    /// Write every item of `lines` followed by `ending`, through one
+    /// buffered writer. Items are written as they are.
+    pub fn write_lines_with_ending<I, O>(&self, lines: I, ending: LineEnding, opt: O) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: Open,
+    {
+        self.write_lines_impl(lines, ending, false, opt)
+    }
+
+    /// Like `write_lines_with_ending`, converting line breaks within items
+    /// to `ending` too, so the file is consistent whatever the input.
+    pub fn write_lines_normalized<I, O>(&self, lines: I, ending: LineEnding, opt: O) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: Open,
+    {
+        self.write_lines_impl(lines, ending, true, opt)
+    }
+
+    fn write_lines_impl<I, O>(&self, lines: I, ending: LineEnding, normalize: bool, opt: O) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: Open,
+    {
+        let mut w = self.buf_writer(opt)?;
+        for line in lines {
+            let line = line.as_ref();
+            if normalize {
+                w.write_all(ending.normalize(line).as_bytes())?;
+            } else {
+                w.write_all(line.as_bytes())?;
+            }
+            w.write_all(ending.as_str().as_bytes())?;
+        }
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        Ok(())
+    }
+
+    /// Write all of `buf` to the file opened with `opt`, and sync it all
+    /// to the disk before returning.
+    pub fn write_all_with<O: Open>(&self, buf: &[u8], opt: O) -> io::Result<()> {
+        self.write_all_with_sync(buf, opt, SyncPolicy::All)
+    }
+
+    /// Write all of `buf` to the file opened with `opt`, syncing it to the
+    /// disk as `sync` tells. `SyncPolicy::None` is much faster when writing
+    /// many small files, at the cost of durability on power loss.
+    pub fn write_all_with_sync<O: Open>(
+        &self,
+        buf: &[u8],
+        opt: O,
+        sync: SyncPolicy,
+    ) -> io::Result<()> {
+        let mut f = opt.open(&self.path)?;
+        f.write_all(buf)?;
+        sync.apply(&f)
+    }
+
+    /// Stream everything `r` yields into the file opened with `opt`, then
+    /// sync it to the disk. Returns the number of bytes written.
+    pub fn write_from_reader<R: Read + ?Sized, O: Open>(&self, r: &mut R, opt: O) -> io::Result<u64> {
+        let mut f = opt.open(&self.path)?;
+        let n = io::copy(r, &mut f)?;
+        f.sync_all()?;
+        Ok(n)
+    }
+
+    /// Write all of `buf` to the file opened with `opt`, calling
+    /// `progress(bytes_written, total)` after each block of the default
+    /// buffer capacity, then sync it to the disk.
+    pub fn write_all_with_progress<O, F>(&self, buf: &[u8], opt: O, progress: F) -> io::Result<()>
+    where
+        O: Open,
+        F: FnMut(u64, u64),
+    {
+        let f = opt.open(&self.path)?;
+        let mut w = ProgressWriter::new(f, buf.len() as u64, progress);
+        for block in buf.chunks(default_buf_capacity()) {
+            w.write_all(block)?;
+        }
+        w.get_ref().sync_all()
+    }
+
+    /// Read the file as text, pass it to `f` and atomically replace the
+    /// content with what `f` returns. A BOM is not passed to `f`, nor kept.
+    pub fn edit<F: FnOnce(String) -> String>(&self, f: F) -> io::Result<()> {
+        let edited = f(self.read_string()?);
+        self.replace_content(edited.as_bytes())
+    }
+
+    /// Read the file, pass its bytes to `f` and atomically replace the
+    /// content with what `f` returns.
+    pub fn edit_bytes<F: FnOnce(Vec<u8>) -> Vec<u8>>(&self, f: F) -> io::Result<()> {
+        let edited = f(self.read_all()?);
+        self.replace_content(&edited)
+    }
+
+    /// Stream the file line by line through `f` into a temporary file which
+    /// then atomically replaces it. `f` gets each line without terminator
+    /// and returns its replacement, or `None` to drop it. Lines keep their
+    /// original terminator.
+    pub fn map_lines<F>(&self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut r = self.buf_reader()?;
+        let mut w = AtomicFile::new(&self.path)?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                break;
+            }
+            let content = line.trim_end_matches(['\n', '\r']);
+            let terminator = &line[content.len()..];
+            if let Some(mapped) = f(content) {
+                w.write_all(mapped.as_bytes())?;
+                w.write_all(terminator.as_bytes())?;
+            }
+        }
+        w.commit()
+    }
+
+    /// Replace every match of `pattern` with `replacement`, streaming the
+    /// file line by line, and return the number of replacements. The file
+    /// is rewritten atomically, only if something matched. Fails with
+    /// `InvalidData` if the file isn't UTF-8 text.
+    pub fn replace<P: Into<Pattern>>(&self, pattern: P, replacement: &str) -> io::Result<usize> {
+        match replace::replace_in_file(self, &pattern.into(), replacement, false)? {
+            Some(change) => Ok(change.replacements),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "not a text file")),
+        }
+    }
+
+    /// Atomically replace the content of the file with `buf`.
+    fn replace_content(&self, buf: &[u8]) -> io::Result<()> {
+        let mut w = AtomicFile::new(&self.path)?;
+        w.write_all(buf)?;
+        w.commit()
+    }
+
+    /// Replace the content of the file with `buf` atomically and durably:
+    /// the new content is synced before it replaces the old one, and on
+    /// Unix the directory is synced after, so that the replacement itself
+    /// survives a crash. Other platforms don't allow syncing directories.
+    pub fn write_durable(&self, buf: &[u8]) -> io::Result<()> {
+        self.replace_content(buf)?;
+        sys::sync_parent(&self.path)
+    }
+
+    /// Replace the content of the file with `buf`, after copying the
+    /// current content to a backup file named according to `policy`.
+    /// The new content is written atomically. Returns the backup, or
+    /// `None` if the file did not exist.
+    pub fn write_with_backup(&self, buf: &[u8], policy: BackupPolicy) -> io::Result<Option<File>> {
+        let backup = if self.path.is_file() {
+            let backup = policy.backup_of(&self.path);
+            fs::copy(&self.path, &backup)?;
+            Some(File::from(backup))
+        } else {
+            None
+        };
+        self.replace_content(buf)?;
+        Ok(backup)
+    }
+
+    /// Copy the file to `dest` and return the number of bytes copied.
+    /// By default an existing `dest` is an error, see `CopyOptions` to
+    /// replace it or to create its missing parents. A symbolic link is
+    /// copied as a link unless `Symlinks::Follow` is set.
+    pub fn copy_to<P: AsRef<Path>>(&self, dest: P, mut options: CopyOptions) -> io::Result<u64> {
+        let copied = copy::copy_entry(&self.path, dest.as_ref(), &mut options, &mut |_, _| {
+            ControlFlow::Continue(())
+        })?;
+        Ok(copied.unwrap_or(0))
+    }
+
+    /// Like `copy_to`, calling `f(bytes_copied, total_bytes)` after every
+    /// chunk. When `f` breaks, the copy stops, the partial copy is removed
+    /// and `None` is returned. An existing `dest` is only replaced once the
+    /// copy completes, so it is kept on cancel or error.
+    pub fn copy_to_with<P, F>(
+        &self,
+        dest: P,
+        mut options: CopyOptions,
+        mut f: F,
+    ) -> io::Result<Option<u64>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, u64) -> ControlFlow<()>,
+    {
+        copy::copy_entry(&self.path, dest.as_ref(), &mut options, &mut f)
+    }
+
+    /// Clone the file to `dest`, which must not exist, sharing its data
+    /// until either file is modified. This uses `FICLONE` on Linux (Btrfs,
+    /// XFS) and `clonefile` on macOS (APFS), and fails with `Unsupported`
+    /// elsewhere. `Reflink::Auto` with `copy_to` falls back to a copy.
+    pub fn reflink_to<P: AsRef<Path>>(&self, dest: P) -> io::Result<File> {
+        let options = CopyOptions::new().reflink(Reflink::Always).symlinks(Symlinks::Follow);
+        self.copy_to(dest.as_ref(), options)?;
+        Ok(File::new(dest))
+    }
+
+    /// Remove the file, retrying transient failures with the default
+    /// `RetryOptions`, such as sharing violations while another process
+    /// still holds the file open on Windows. A symbolic link is removed
+    /// itself, not its target.
+    pub fn delete(&self) -> io::Result<()> {
+        self.remove_with(&RetryOptions::new()).map(|_| ())
+    }
+
+    /// Like `delete`, succeeding if the file doesn't exist. Returns
+    /// whether it was removed.
+    pub fn delete_if_exists(&self) -> io::Result<bool> {
+        match self.delete() {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Overwrite the content of the file `passes` times, with random data
+    /// and zeros on the last pass, syncing after each pass, then truncate
+    /// and remove it. At least one pass is made. Fails with `InvalidInput`
+    /// if the path isn't a regular file, links are not followed.
+    ///
+    /// This only wipes the data where writes go to the same blocks. On
+    /// copy-on-write file systems (Btrfs, ZFS, APFS), with data journaling,
+    /// snapshots or backups, and on SSDs and flash media which remap writes,
+    /// old copies remain out of reach of this method: rely on full disk
+    /// encryption there.
+    pub fn shred(&self, passes: u32) -> io::Result<()> {
+        let not_regular = || {
+            let msg = format!("{} is not a regular file", self.path.display());
+            io::Error::new(io::ErrorKind::InvalidInput, msg)
+        };
+        let mut opts = fs::OpenOptions::new();
+        opts.write(true);
+        // The open itself refuses links, so that none swapped in after a
+        // check redirects the writes, and doesn't wait on a FIFO.
+        #[cfg(unix)]
+        let check_first = {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.custom_flags(sys::O_NOFOLLOW.unwrap_or(0) | sys::O_NONBLOCK.unwrap_or(0));
+            sys::O_NOFOLLOW.is_none()
+        };
+        #[cfg(not(unix))]
+        let check_first = true;
+        if check_first && !fs::symlink_metadata(&self.path)?.is_file() {
+            return Err(not_regular());
+        }
+        let mut f = match opts.open(&self.path) {
+            Ok(f) => f,
+            Err(e) => {
+                return match fs::symlink_metadata(&self.path) {
+                    Ok(ref meta) if !meta.is_file() => Err(not_regular()),
+                    _ => Err(e),
+                };
+            }
+        };
+        let meta = f.metadata()?;
+        if !meta.is_file() {
+            return Err(not_regular());
+        }
+        let len = meta.len();
+        let mut buf = vec![0; SHRED_BUF_SIZE];
+        let mut rng = RandomState::new().build_hasher().finish() | 1;
+        let passes = passes.max(1);
+        for pass in 1..=passes {
+            let last = pass == passes;
+            f.seek(SeekFrom::Start(0))?;
+            let mut left = len;
+            while left > 0 {
+                let n = left.min(buf.len() as u64) as usize;
+                if last {
+                    buf[..n].iter_mut().for_each(|b| *b = 0);
+                } else {
+                    fill_random(&mut buf[..n], &mut rng);
+                }
+                f.write_all(&buf[..n])?;
+                left -= n as u64;
+            }
+            f.sync_data()?;
+        }
+        f.set_len(0)?;
+        f.sync_all()?;
+        drop(f);
+        self.delete()
+    }
+
+    /// Remove the file, retrying transient failures as `opts` describes.
+    /// Returns `Completion::Scheduled` if the removal was only scheduled
+    /// for the next reboot.
+    pub fn remove_with(&self, opts: &RetryOptions) -> io::Result<Completion<()>> {
+        let result = opts.run(|| fs::remove_file(&self.path));
+        #[cfg(windows)]
+        {
+            if let Err(ref e) = result {
+                if opts.is_delay_until_reboot() && ::retry::is_transient(e) {
+                    sys::remove_on_reboot(&self.path)?;
+                    return Ok(Completion::Scheduled);
+                }
+            }
+        }
+        result.map(Completion::Done)
+    }
+
+    /// Rename the file to `dest`, retrying transient failures as `opts`
+    /// describes, and return the renamed `File`. Returns
+    /// `Completion::Scheduled` if the rename was only scheduled for the
+    /// next reboot, in which case `dest` doesn't exist yet.
+    pub fn rename_with<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        opts: &RetryOptions,
+    ) -> io::Result<Completion<File>> {
+        let dest = dest.as_ref();
+        let result = opts.run(|| fs::rename(&self.path, dest));
+        #[cfg(windows)]
+        {
+            if let Err(ref e) = result {
+                if opts.is_delay_until_reboot() && ::retry::is_transient(e) {
+                    sys::rename_on_reboot(&self.path, dest)?;
+                    return Ok(Completion::Scheduled);
+                }
+            }
+        }
+        result.map(|_| Completion::Done(File::new(dest)))
+    }
+
+    /// Move the file to `dest`, replacing it if it exists, and return the
+    /// moved `File`. When `dest` is on another file system, where renaming
+    /// fails, the file is copied with its metadata next to `dest`, synced,
+    /// renamed over `dest`, and only then removed.
+    pub fn move_to<P: AsRef<Path>>(&self, dest: P) -> io::Result<File> {
+        let dest = dest.as_ref();
+        match fs::rename(&self.path, dest) {
+            Ok(()) => Ok(File::new(dest)),
+            Err(ref e) if e.kind() == io::ErrorKind::CrossesDevices => self.move_by_copy(dest),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn move_by_copy(&self, dest: &Path) -> io::Result<File> {
+        let name = dest.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path has no file name")
+        })?;
+        let mut tmp_name = ::std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".{}.move", ::std::process::id()));
+        let tmp = dest.with_file_name(tmp_name);
+        let moved = self
+            .copy_to(&tmp, CopyOptions::new().preserve().overwrite(Overwrite::Always))
+            .and_then(|_| {
+                if !fs::symlink_metadata(&tmp)?.file_type().is_symlink() {
+                    fs::File::open(&tmp)?.sync_all()?;
+                }
+                fs::rename(&tmp, dest)
+            });
+        if let Err(e) = moved {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        sys::sync_parent(dest)?;
+        fs::remove_file(&self.path)?;
+        Ok(File::new(dest))
+    }
+}
+
+/// Fill `buf` with pseudo random bytes from the xorshift state `state`,
+/// which must not be zero.
+fn fill_random(buf: &mut [u8], state: &mut u64) {
+    for chunk in buf.chunks_mut(8) {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+    }
+}
+
+impl Default for File {
+    fn default() -> Self {
+        File { path: PathBuf::new() }
+    }
+}
+
+impl ops::Deref for File {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.path.as_ref()
+    }
+}
+
+impl AsRef<Path> for File {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl From<PathBuf> for File {
+    #[allow(clippy::redundant_field_names)]
+    fn from(path: PathBuf) -> File {
+        File { path: path }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::io::prelude::*;
+    use std::path::Path;
+    use self::tempdir::TempDir;
+    use super::*;
+
+    fn test_dir() -> io::Result<TempDir> {
+        let dir = TempDir::new("good-files-test")?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn file_object() {
+        let f = File::new("/path/to/some/file");
+        assert_eq!(Path::new("/path/to/some/file"), &f.path);
+    }
+
+    #[test]
+    #[should_panic]
+    fn open_readonly() {
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("panics.txt");
+        let mut f = FileOpener::readonly()
+            .open(&path)
+            .unwrap();
+        let _ = f.write(b"this should never be written").unwrap();
+    }
+
+    #[test]
+    fn opener_builder() {
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("built.txt");
+        let fresh = FileOpener::new().create(CreateMode::CreateNew).write(WriteOption::Overwrite);
+        fresh.open(&path).unwrap().write_all(b"first").unwrap();
+        assert_eq!(io::ErrorKind::AlreadyExists, fresh.open(&path).unwrap_err().kind());
+
+        let mut f = FileOpener::new().read(true).write(WriteOption::Append).open(&path).unwrap();
+        f.write_all(b" second").unwrap();
+        f.seek(SeekFrom::Start(0)).unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!("first second", &s);
+    }
+
+    #[test]
+    fn read_write_presets() {
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("rw.txt");
+        assert!(FileOpener::read_write().open(&path).is_err());
+
+        let mut f = FileOpener::read_append().open(&path).unwrap();
+        f.write_all(b"hello world").unwrap();
+        drop(f);
+
+        let mut f = FileOpener::read_write().open(&path).unwrap();
+        let mut buf = [0; 5];
+        f.read_exact(&mut buf).unwrap();
+        assert_eq!(b"hello", &buf);
+        f.seek(SeekFrom::Start(0)).unwrap();
+        f.write_all(b"HELLO").unwrap();
+        assert_eq!("HELLO world", &File::new(&path).read_string().unwrap());
+    }
+
+    #[test]
+    fn create_new_presets() {
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("once.lock");
+        FileOpener::create_new_write().open(&path).unwrap().write_all(b"1").unwrap();
+        let err = FileOpener::create_new_write().open(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        let err = FileOpener::create_new_append().open(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        assert_eq!("1", &File::new(&path).read_string().unwrap());
+    }
+
+    #[test]
+    fn open_dyn() {
+        struct Upper;
+
+        struct UpperWriter(fs::File);
+
+        impl Write for UpperWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.write(&buf.to_ascii_uppercase())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.0.flush()
+            }
+        }
+
+        impl OpenDyn for Upper {
+            fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+                FileOpener::readonly().open_reader(path)
+            }
+
+            fn open_writer(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+                Ok(Box::new(UpperWriter(FileOpener::append_or_create().open(path)?)))
+            }
+        }
+
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("dyn.txt");
+        let openers: Vec<Box<dyn OpenDyn>> = vec![Box::new(FileOpener::truncate()), Box::new(Upper)];
+        for opener in &openers {
+            opener.open_writer(&path).unwrap().write_all(b"ab").unwrap();
+        }
+        let mut s = String::new();
+        openers[1].open_reader(&path).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!("abAB", &s);
+    }
+
+    #[test]
+    fn open_with_closure() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("closure.txt"));
+        let opened = ::std::cell::Cell::new(0);
+        let counting = ::open::from_fn(|path: &Path| {
+            opened.set(opened.get() + 1);
+            FileOpener::append_or_create().open(path)
+        });
+        f.write_all_with(b"a", &counting).unwrap();
+        f.write_all_with(b"b", counting.then_sync_parent()).unwrap();
+        assert_eq!(2, opened.get());
+        assert_eq!("ab", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn open_with_open_options() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("options.txt"));
+        let mut opts = OpenOptions::new();
+        opts.create(true).append(true);
+        f.write_all_with(b"a", &opts).unwrap();
+        f.write_all_with(b"b", opts.clone().then_sync_parent()).unwrap();
+        assert_eq!("ab", &f.read_string().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn opener_unix_flags() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = test_dir().unwrap();
+        let target = tmp_dir.path().join("target.txt");
+        let link = tmp_dir.path().join("link.txt");
+        let private = FileOpener::truncate().mode(0o600);
+        File::new(&target).write_all_with(b"secret", &private).unwrap();
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+
+        ::std::os::unix::fs::symlink(&target, &link).unwrap();
+        #[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            const O_NOFOLLOW: i32 = 0o400_000;
+            let nofollow = FileOpener::readonly().custom_flags(O_NOFOLLOW);
+            assert!(nofollow.open(&link).is_err());
+            assert!(nofollow.open(&target).is_ok());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn nonblocking_fifo() {
+        use std::ffi::CString;
+        use std::os::raw::c_char;
+        use std::os::unix::ffi::OsStrExt;
+
+        extern "C" {
+            fn mkfifo(path: *const c_char, mode: u32) -> i32;
+        }
+
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("control.fifo");
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(0, unsafe { mkfifo(c_path.as_ptr(), 0o600) });
+
+        let mut f = FileOpener::readonly().nonblocking(true).open(&path).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(0, f.read(&mut buf).unwrap());
+        let mut w = FileOpener::appending().open(&path).unwrap();
+        w.write_all(b"stop").unwrap();
+        assert_eq!(4, f.read(&mut buf).unwrap());
+        assert_eq!(io::ErrorKind::WouldBlock, f.read(&mut buf).unwrap_err().kind());
+    }
+
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[test]
+    fn direct_io() {
+        use aligned::AlignedBuf;
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("direct.bin");
+        let mut f = match FileOpener::truncate().read(true).direct(true).open(&path) {
+            Ok(f) => f,
+            // tmpfs and some other file systems refuse O_DIRECT
+            Err(ref e) if e.raw_os_error() == Some(22) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let mut buf = AlignedBuf::new(4096);
+        buf[..6].copy_from_slice(b"direct");
+        f.write_all(&buf).unwrap();
+        f.seek(SeekFrom::Start(0)).unwrap();
+        let mut read = AlignedBuf::new(4096);
+        f.read_exact(&mut read).unwrap();
+        assert_eq!(b"direct", &read[..6]);
+    }
+
+    #[test]
+    fn create_parents() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("out/reports/2024/summary.txt"));
+        assert_eq!(io::ErrorKind::NotFound, f.overwrite(b"x").unwrap_err().kind());
+        f.write_all_with(b"summary", FileOpener::truncate().create_parents(true)).unwrap();
+        assert_eq!("summary", &f.read_string().unwrap());
+
+        let g = File::new(tmp_dir.path().join("other/dir/file.txt"));
+        g.ensure_parent().unwrap();
+        assert!(tmp_dir.path().join("other/dir").is_dir());
+        File::new("relative.txt").ensure_parent().unwrap();
+    }
+
+    #[test]
+    fn touch() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("touched"));
+        f.touch().unwrap();
+        assert_eq!(0, f.metadata().unwrap().len());
+
+        f.overwrite(b"content").unwrap();
+        let old = SystemTime::UNIX_EPOCH + ::std::time::Duration::from_secs(1_000_000);
+        f.create_if_absent().unwrap().set_modified(old).unwrap();
+        f.touch().unwrap();
+        assert!(f.metadata().unwrap().modified().unwrap() > old);
+        assert_eq!("content", &f.read_string().unwrap());
+
+        f.create_if_absent().unwrap().set_modified(old).unwrap();
+        let writable = f.metadata().unwrap().permissions();
+        let mut readonly = writable.clone();
+        readonly.set_readonly(true);
+        fs::set_permissions(&*f, readonly).unwrap();
+        f.touch().unwrap();
+        assert!(f.metadata().unwrap().modified().unwrap() > old);
+        fs::set_permissions(&*f, writable).unwrap();
+
+        let dir = File::new(tmp_dir.path());
+        dir.touch().unwrap();
+        assert!(dir.metadata().unwrap().is_dir());
+    }
+
+    #[test]
+    fn read_write_ops() {
+        // TODO: revisit when good-files' utilities are ready
+        let tmp_dir = test_dir().unwrap();
+        let path = tmp_dir.path().join("foo.txt");
+        let f = File::new(&path);
+
+        // writing
+        f.overwrite(b"some text\n2nd line").unwrap();
+
+        // reading
+        let s = f.read_string().unwrap();
+        assert_eq!("some text\n2nd line", &s);
+    }
+
+    #[test]
+    fn read_range() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("range.bin"));
+        f.overwrite(b"0123456789").unwrap();
+
+        assert_eq!(b"345".to_vec(), f.read_range(3, 3).unwrap());
+        assert_eq!(b"89".to_vec(), f.read_range(8, 2).unwrap());
+        let err = f.read_range(8, 3).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn set_len() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("sized.bin"));
+        f.overwrite(b"0123456789").unwrap();
+
+        f.set_len(12).unwrap();
+        assert_eq!(b"89\0\0".to_vec(), f.read_range(8, 4).unwrap());
+        f.set_len(4).unwrap();
+        assert_eq!("0123", &f.read_string().unwrap());
+        let missing = File::new(tmp_dir.path().join("missing.bin"));
+        assert_eq!(io::ErrorKind::NotFound, missing.set_len(1).unwrap_err().kind());
+    }
+
+    #[test]
+    fn tail_bytes() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("tail.bin"));
+        f.overwrite(b"0123456789").unwrap();
+
+        assert_eq!(b"789".to_vec(), f.tail_bytes(3).unwrap());
+        assert_eq!(b"0123456789".to_vec(), f.tail_bytes(100).unwrap());
+        assert!(f.tail_bytes(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn copy_to_writer() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("copied.bin"));
+        f.overwrite(b"streamed content").unwrap();
+        let mut out = Vec::new();
+        assert_eq!(16, f.copy_to_writer(&mut out).unwrap());
+        let w: &mut dyn Write = &mut out;
+        f.copy_to_writer(w).unwrap();
+        assert_eq!(b"streamed contentstreamed content", &out[..]);
+    }
+
+    #[test]
+    fn write_all_with_sync() {
+        let tmp_dir = test_dir().unwrap();
+        for &policy in &[SyncPolicy::None, SyncPolicy::Data, SyncPolicy::All] {
+            let f = File::new(tmp_dir.path().join(format!("{:?}.txt", policy)));
+            f.write_all_with_sync(b"synced", FileOpener::truncate(), policy).unwrap();
+            assert_eq!("synced", &f.read_string().unwrap());
+        }
+    }
+
+    #[test]
+    fn write_all_with_progress() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("progress.bin"));
+        let content = vec![7u8; 100_000];
+        let mut calls = 0;
+        let mut last = (0, 0);
+        f.write_all_with_progress(&content, FileOpener::truncate(), |n, total| {
+            calls += 1;
+            last = (n, total);
+        }).unwrap();
+        assert!(calls > 1);
+        assert_eq!((100_000, 100_000), last);
+        assert_eq!(content, f.read_all().unwrap());
+    }
+
+    #[test]
+    fn buf_writer_throttled() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("throttled.bin"));
+        let mut w = f.buf_writer_throttled(FileOpener::truncate(), 1024 * 1024).unwrap();
+        w.write_all(b"throttled").unwrap();
+        w.flush().unwrap();
+        assert_eq!("throttled", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn tee_with() {
+        let tmp_dir = test_dir().unwrap();
+        let a = File::new(tmp_dir.path().join("a.log"));
+        let b = File::new(tmp_dir.path().join("b.log"));
+        {
+            let mut tee = File::tee_with(&[a.clone(), b.clone()], FileOpener::truncate()).unwrap();
+            tee.write_all(b"twice").unwrap();
+            tee.flush().unwrap();
+        }
+        assert_eq!("twice", &a.read_string().unwrap());
+        assert_eq!("twice", &b.read_string().unwrap());
+    }
+
+    #[test]
+    fn write_from_reader() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("downloaded.bin"));
+        let mut body = io::Cursor::new(vec![1u8; 70_000]);
+        assert_eq!(70_000, f.write_from_reader(&mut body, FileOpener::truncate()).unwrap());
+        assert_eq!(vec![1u8; 70_000], f.read_all().unwrap());
+    }
+
+    #[test]
+    fn write_lines() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("lines.txt"));
+        f.write_lines(&["a", "b"], FileOpener::truncate()).unwrap();
+        assert_eq!("a\nb\n", &f.read_string().unwrap());
+        let owned = vec!["c".to_owned(), "d".to_owned()];
+        f.write_lines_with_ending(owned, LineEnding::CrLf, FileOpener::truncate()).unwrap();
+        assert_eq!("c\r\nd\r\n", &f.read_string().unwrap());
+        f.write_lines(&["e\r\nf"], FileOpener::truncate()).unwrap();
+        assert_eq!("e\r\nf\n", &f.read_string().unwrap());
+        f.write_lines_normalized(&["e\r\nf"], LineEnding::Lf, FileOpener::truncate()).unwrap();
+        assert_eq!("e\nf\n", &f.read_string().unwrap());
+        f.write_text("g\nh\r\n", LineEnding::CrLf, FileOpener::truncate()).unwrap();
+        assert_eq!("g\r\nh\r\n", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn append_line() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("audit.log"));
+        f.append_line("first").unwrap();
+        f.append_line("second").unwrap();
+        assert_eq!("first\nsecond\n", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn edit() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("edited.conf"));
+        f.overwrite(b"debug = false\n").unwrap();
+        f.edit(|s| s.replace("false", "true")).unwrap();
+        assert_eq!("debug = true\n", &f.read_string().unwrap());
+        f.edit_bytes(|mut v| {
+            v.truncate(5);
+            v
+        }).unwrap();
+        assert_eq!("debug", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn map_lines() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("mapped.txt"));
+        f.overwrite(b"keep\r\n# drop\nshout").unwrap();
+        f.map_lines(|line| {
+            if line.starts_with('#') {
+                None
+            } else {
+                Some(line.to_uppercase())
+            }
+        }).unwrap();
+        assert_eq!("KEEP\r\nSHOUT", &f.read_string().unwrap());
+
+        f.overwrite(b"\xff\n").unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, f.map_lines(|l| Some(l.to_owned())).unwrap_err().kind());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn replace() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("replaced.txt"));
+        f.overwrite(b"one two one\nthree one\n").unwrap();
+        assert_eq!(3, f.replace("one", "1").unwrap());
+        assert_eq!("1 two 1\nthree 1\n", &f.read_string().unwrap());
+        assert_eq!(0, f.replace("missing", "x").unwrap());
+        #[cfg(feature = "regex")]
+        {
+            assert_eq!(3, f.replace(Pattern::regex(r"\d").unwrap(), "<$0>").unwrap());
+            assert_eq!("<1> two <1>\nthree <1>\n", &f.read_string().unwrap());
+        }
+        f.overwrite(b"\0").unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, f.replace("x", "y").unwrap_err().kind());
+    }
+
+    #[test]
+    fn write_durable() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("state.json"));
+        f.write_durable(b"{\"v\":1}").unwrap();
+        f.write_durable(b"{}").unwrap();
+        assert_eq!("{}", &f.read_string().unwrap());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn write_with_backup() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("settings.conf"));
+        assert_eq!(None, f.write_with_backup(b"v1", BackupPolicy::Bak).unwrap());
+        let bak = f.write_with_backup(b"v2", BackupPolicy::Bak).unwrap().unwrap();
+        assert!(bak.ends_with("settings.conf.bak"));
+        assert_eq!("v1", &bak.read_string().unwrap());
+        assert_eq!("v2", &f.read_string().unwrap());
+
+        let stamped = f.write_with_backup(b"v3", BackupPolicy::Timestamped).unwrap().unwrap();
+        let name = stamped.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("settings.conf.") && name.ends_with("Z.bak"));
+        assert_eq!("v2", &stamped.read_string().unwrap());
+        assert_eq!("v1", &bak.read_string().unwrap());
+    }
+
+    #[test]
+    fn head_and_tail() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("lines.txt"));
+        let text: Vec<String> = (0..5000).map(|i| format!("line {}", i)).collect();
+        f.overwrite(text.join("\r\n").as_bytes()).unwrap();
+
+        assert_eq!(&text[..3], &f.head(3).unwrap()[..]);
+        assert_eq!(&text[4997..], &f.tail(3).unwrap()[..]);
+        assert_eq!(text, f.tail(10000).unwrap());
+
+        let g = File::new(tmp_dir.path().join("blank.txt"));
+        g.overwrite(b"a\nb\n\nc\n").unwrap();
+        assert_eq!(vec!["", "c"], g.tail(2).unwrap());
+        assert!(g.tail(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn lines_reversed() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("reversed.txt"));
+        let text: Vec<String> = (0..3000).map(|i| "x".repeat(i % 17)).collect();
+        f.overwrite((text.join("\n") + "\n").as_bytes()).unwrap();
+
+        let mut lines = f.lines_reversed().unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        lines.reverse();
+        assert_eq!(text, lines);
+
+        let empty = File::new(tmp_dir.path().join("empty.txt"));
+        empty.create_if_absent().unwrap();
+        assert_eq!(0, empty.lines_reversed().unwrap().count());
+    }
+
+    #[test]
+    fn chunks() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("chunks.bin"));
+        f.overwrite(b"0123456789").unwrap();
+
+        let chunks = f.chunks(4).unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(vec![b"0123".to_vec(), b"4567".to_vec(), b"89".to_vec()], chunks);
+
+        let mut chunks = f.chunks(5).unwrap();
+        assert_eq!(b"01234", chunks.next_chunk().unwrap().unwrap());
+        assert_eq!(b"56789", chunks.next_chunk().unwrap().unwrap());
+        assert!(chunks.next_chunk().is_none());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn map_read() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("mapped.bin"));
+        f.overwrite(b"mapped content").unwrap();
+        assert_eq!(b"mapped content", &f.map_read().unwrap()[..]);
+
+        let empty = File::new(tmp_dir.path().join("empty.bin"));
+        empty.create_if_absent().unwrap();
+        assert!(empty.map_read().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn map_write() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("index.bin"));
+        {
+            let mut map = f.map_write(8).unwrap();
+            map[..4].copy_from_slice(b"abcd");
+            map[7] = b'z';
+            map.flush().unwrap();
+        }
+        assert_eq!(b"abcd\0\0\0z".to_vec(), f.read_all().unwrap());
+        assert_eq!(b"ab".to_vec(), f.map_write(2).unwrap().to_vec());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn read_string_with_encoding() {
+        use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("sjis.txt"));
+        // "日本語のテキスト" in Shift_JIS
+        let sjis = b"\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x65\x83\x4c\x83\x58\x83\x67";
+        f.overwrite(sjis).unwrap();
+        assert_eq!("日本語のテキスト", &f.read_string_with_encoding(SHIFT_JIS).unwrap());
+
+        let g = File::new(tmp_dir.path().join("latin1.txt"));
+        g.overwrite(b"caf\xe9").unwrap();
+        assert_eq!("café", &g.read_string_with_encoding(WINDOWS_1252).unwrap());
+        assert!(g.read_string_with_encoding(UTF_8).is_err());
+
+        let h = File::new(tmp_dir.path().join("bom.txt"));
+        h.overwrite(b"\xef\xbb\xbfplain").unwrap();
+        assert_eq!(("plain".to_owned(), UTF_8), h.read_string_detect_encoding().unwrap());
+    }
+
+    #[test]
+    fn bom_aware_reads() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("bom.txt"));
+        f.overwrite(b"\xef\xbb\xbf{\"key\": 1}").unwrap();
+        assert_eq!("{\"key\": 1}", &f.read_string().unwrap());
+        assert_eq!((Some(Bom::Utf8), "{\"key\": 1}".to_owned()), f.read_string_bom().unwrap());
+
+        let g = File::new(tmp_dir.path().join("plain.txt"));
+        g.overwrite(b"plain").unwrap();
+        assert_eq!((None, "plain".to_owned()), g.read_string_bom().unwrap());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn transcode_to() {
+        use encoding_rs::{SHIFT_JIS, UTF_16LE, UTF_8, WINDOWS_1252};
+        use text::ErrorPolicy;
+        let tmp_dir = test_dir().unwrap();
+        let src = File::new(tmp_dir.path().join("sjis.txt"));
+        let dest = File::new(tmp_dir.path().join("utf8.txt"));
+        let text = "日本語のテキスト\n".repeat(5000);
+        let (sjis, _, _) = SHIFT_JIS.encode(&text);
+        src.overwrite(&sjis).unwrap();
+
+        let opts = TranscodeOptions::new().bom(true);
+        assert_eq!(0, src.transcode_to(&dest, SHIFT_JIS, UTF_8, &opts).unwrap());
+        assert_eq!((Some(Bom::Utf8), text.clone()), dest.read_string_bom().unwrap());
+
+        let utf16 = File::new(tmp_dir.path().join("utf16.txt"));
+        dest.transcode_to(&utf16, UTF_8, UTF_16LE, &opts).unwrap();
+        assert_eq!((Some(Bom::Utf16Le), text), utf16.read_string_bom().unwrap());
+
+        let broken = File::new(tmp_dir.path().join("broken.txt"));
+        broken.overwrite(b"caf\xe9 \x80").unwrap();
+        let strict = TranscodeOptions::new();
+        assert!(broken.transcode_to(&dest, UTF_8, WINDOWS_1252, &strict).is_err());
+        // U+FFFD is unmappable in Shift_JIS, malformed input is still
+        // counted once
+        let replace = TranscodeOptions::new().errors(ErrorPolicy::Replace);
+        assert_eq!(2, broken.transcode_to(&dest, UTF_8, SHIFT_JIS, &replace).unwrap());
+        assert_eq!("caf? ?", &dest.read_string().unwrap());
+        let one = File::new(tmp_dir.path().join("one.txt"));
+        one.overwrite(b"ab\xffcd").unwrap();
+        assert_eq!(1, one.transcode_to(&dest, UTF_8, WINDOWS_1252, &replace).unwrap());
+        assert_eq!(b"ab?cd", &dest.read_all().unwrap()[..]);
+        assert_eq!(1, one.transcode_to(&dest, UTF_8, UTF_8, &replace).unwrap());
+        assert_eq!("ab\u{fffd}cd", &dest.read_string().unwrap());
+        let skip = TranscodeOptions::new().errors(ErrorPolicy::Skip);
+        assert_eq!(2, broken.transcode_to(&dest, UTF_8, UTF_8, &skip).unwrap());
+        assert_eq!("caf ", &dest.read_string().unwrap());
+    }
+
+    #[test]
+    fn utf16_text() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("utf16.txt"));
+        f.write_utf16("Grüße, 世界", Endian::Little, FileOpener::truncate()).unwrap();
+        assert_eq!(b"\xff\xfeG\x00r\x00", &f.read_range(0, 6).unwrap()[..]);
+        assert_eq!("Grüße, 世界", &f.read_utf16(Endian::Big).unwrap());
+        assert_eq!((Some(Bom::Utf16Le), "Grüße, 世界".to_owned()), f.read_string_bom().unwrap());
+
+        let no_bom = File::new(tmp_dir.path().join("no-bom.txt"));
+        no_bom.overwrite(b"\x00o\x00k").unwrap();
+        assert_eq!("ok", &no_bom.read_utf16(Endian::Big).unwrap());
+    }
+
+    #[test]
+    fn detect_type() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("image.txt"));
+        f.overwrite(b"\x89PNG\r\n\x1a\n").unwrap();
+        assert_eq!(ContentType::Png, f.detect_type().unwrap());
+
+        let g = File::new(tmp_dir.path().join("long.txt"));
+        g.overwrite("é".repeat(magic::SNIFF_LEN).as_bytes()).unwrap();
+        assert_eq!(ContentType::Utf8Text, g.detect_type().unwrap());
+    }
+
+    #[test]
+    fn count_lines() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("count.txt"));
+        let text = "some line\r\n".repeat(100_000);
+        f.overwrite(text.as_bytes()).unwrap();
+        assert_eq!(100_000, f.count_lines().unwrap());
+
+        let g = File::new(tmp_dir.path().join("no-terminator.txt"));
+        g.overwrite(b"a\n\nb").unwrap();
+        assert_eq!(3, g.count_lines().unwrap());
+        assert_eq!(g.buf_reader().unwrap().lines().count() as u64, g.count_lines().unwrap());
+
+        let empty = File::new(tmp_dir.path().join("empty.txt"));
+        empty.create_if_absent().unwrap();
+        assert_eq!(0, empty.count_lines().unwrap());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn hash() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("hashed.txt"));
+        f.overwrite(b"hello world").unwrap();
+        let digest = f.hash(Algorithm::Sha256).unwrap();
+        assert_eq!(32, digest.as_bytes().len());
+        assert_eq!(
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            &digest.to_string()
+        );
+    }
+
+    #[test]
+    fn read_into_buffers() {
+        let tmp_dir = test_dir().unwrap();
+        let a = File::new(tmp_dir.path().join("a.txt"));
+        let b = File::new(tmp_dir.path().join("b.txt"));
+        a.overwrite(b"\xef\xbb\xbfa longer first file").unwrap();
+        b.overwrite(b"short").unwrap();
+
+        let mut bytes = Vec::new();
+        assert_eq!(22, a.read_all_into(&mut bytes).unwrap());
+        let capacity = bytes.capacity();
+        assert_eq!(5, b.read_all_into(&mut bytes).unwrap());
+        assert_eq!(b"short", &bytes[..]);
+        assert_eq!(capacity, bytes.capacity());
+
+        let mut s = String::new();
+        a.read_string_into(&mut s).unwrap();
+        assert_eq!("a longer first file", &s);
+        b.read_string_into(&mut s).unwrap();
+        assert_eq!("short", &s);
+    }
+
+    #[test]
+    fn buf_reader_capacity() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("buffered.txt"));
+        f.overwrite(b"buffered").unwrap();
+        let r = f.buf_reader_with_capacity(1024 * 1024).unwrap();
+        assert_eq!(1024 * 1024, r.capacity());
+        assert_eq!(default_buf_capacity(), f.buf_reader().unwrap().capacity());
+    }
+
+    #[test]
+    fn buf_writer_capacity() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("buffered.txt"));
+        let w = f.buf_writer_with_capacity(FileOpener::truncate(), 1024 * 1024).unwrap();
+        assert_eq!(1024 * 1024, w.capacity());
+        let w = f.buf_writer(FileOpener::appending()).unwrap();
+        assert_eq!(default_buf_capacity(), w.capacity());
+    }
+
+    #[test]
+    fn read_all_with_progress() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("progress.bin"));
+        let content = vec![7u8; 100_000];
+        f.overwrite(&content).unwrap();
+        let mut last = (0, 0);
+        let read = f.read_all_with_progress(|n, total| last = (n, total)).unwrap();
+        assert_eq!(content, read);
+        assert_eq!((100_000, 100_000), last);
+    }
+
+    #[test]
+    fn preallocate() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("prealloc.bin"));
+        f.preallocate(100_000).unwrap();
+        assert_eq!(100_000, f.metadata().unwrap().len());
+        f.overwrite(b"head").unwrap();
+        f.preallocate(10).unwrap();
+        assert_eq!(100_000, f.metadata().unwrap().len());
+        assert_eq!(b"head\0".to_vec(), f.read_range(0, 5).unwrap());
+    }
+
+    #[test]
+    fn positional_io() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("positional.bin"));
+        f.overwrite(b"aaaaaaaa").unwrap();
+
+        assert_eq!(2, f.write_at(b"bb", 3).unwrap());
+        let mut buf = [0; 4];
+        assert_eq!(4, f.read_at(&mut buf, 2).unwrap());
+        assert_eq!(b"abba", &buf);
+        assert_eq!("aaabbaaa", &f.read_string().unwrap());
+    }
+
+    #[test]
+    fn write_all_at() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("records.bin"));
+        f.write_all_at(b"aaaa", 0).unwrap();
+        f.write_all_at(b"bbbb", 4).unwrap();
+        assert_eq!("aaaabbbb", &f.read_string().unwrap());
+
+        f.write_all_at(b"cc", 2).unwrap();
+        assert_eq!("aaccbbbb", &f.read_string().unwrap());
+        f.write_all_at(b"d", 10).unwrap();
+        assert_eq!(b"aaccbbbb\0\0d".to_vec(), f.read_all().unwrap());
+    }
+
+    #[test]
+    fn rename_and_remove() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("before.txt"));
+        f.overwrite(b"moving").unwrap();
+
+        let opts = RetryOptions::new();
+        let g = f.rename_with(tmp_dir.path().join("after.txt"), &opts).unwrap().done().unwrap();
+        assert!(!f.exists());
+        assert_eq!("moving", &g.read_string().unwrap());
+
+        assert_eq!(Completion::Done(()), g.remove_with(&opts).unwrap());
+        assert!(!g.exists());
+    }
+
+    #[test]
+    fn move_to() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("before.txt"));
+        f.overwrite(b"moving").unwrap();
+        let existing = File::new(tmp_dir.path().join("after.txt"));
+        existing.overwrite(b"replaced").unwrap();
+
+        let g = f.move_to(&*existing).unwrap();
+        assert!(!f.exists());
+        assert_eq!("moving", &g.read_string().unwrap());
+
+        let dest = tmp_dir.path().join("sub");
+        fs::create_dir(&dest).unwrap();
+        let modified = fs::metadata(&*g).unwrap().modified().unwrap();
+        let h = g.move_by_copy(&dest.join("copied.txt")).unwrap();
+        assert!(!g.exists());
+        assert_eq!("moving", &h.read_string().unwrap());
+        assert_eq!(modified, fs::metadata(&*h).unwrap().modified().unwrap());
+        assert_eq!(1, fs::read_dir(&dest).unwrap().count());
+    }
+
+    #[test]
+    fn delete() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("deleted.txt"));
+        f.overwrite(b"gone").unwrap();
+
+        f.delete().unwrap();
+        assert!(!f.exists());
+        assert_eq!(io::ErrorKind::NotFound, f.delete().unwrap_err().kind());
+        assert!(!f.delete_if_exists().unwrap());
+        f.touch().unwrap();
+        assert!(f.delete_if_exists().unwrap());
+    }
+
+    #[test]
+    fn shred() {
+        let tmp_dir = test_dir().unwrap();
+        let f = File::new(tmp_dir.path().join("secret.txt"));
+        f.overwrite(&vec![b'x'; SHRED_BUF_SIZE + 10]).unwrap();
+        let link = tmp_dir.path().join("link.txt");
+        fs::hard_link(&*f, &link).unwrap();
+
+        f.shred(3).unwrap();
+        assert!(!f.exists());
+        assert_eq!(0, fs::metadata(&link).unwrap().len());
+        let err = File::new(tmp_dir.path()).shred(1).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        #[cfg(unix)]
+        {
+            let target = File::new(tmp_dir.path().join("target.txt"));
+            target.overwrite(b"keep").unwrap();
+            let symlink = File::new(tmp_dir.path().join("symlink.txt"));
+            ::std::os::unix::fs::symlink(&*target, &*symlink).unwrap();
+            let err = symlink.shred(1).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+            assert_eq!("keep", &target.read_string().unwrap());
+        }
+
+        let mut buf = [0; 20];
+        let mut state = 1;
+        fill_random(&mut buf, &mut state);
+        assert!(buf.iter().any(|&b| b != 0));
     }
 }
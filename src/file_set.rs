@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter::FromIterator;
+use std::ops;
+use std::path::{self, PathBuf};
+use std::process;
+use std::vec;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use file::File;
+
+/// `FileSet` is an ordered collection of `File`s to operate on at once.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileSet {
+    files: Vec<File>,
+}
+
+/// `Rename` describes how every file in a `FileSet` gets its new name.
+/// Only the file name changes, files stay in their directory.
+pub enum Rename {
+    /// A template such as `photo-{n:04}.{ext}`. Supported placeholders are
+    /// `{n}` (1-based position in the set, `{n:04}` pads it with zeros),
+    /// `{name}` (file stem) and `{ext}` (extension). `{{` and `}}` escape braces.
+    Template(String),
+    /// A function from a file and its 0-based position to the new file name.
+    Fn(Box<RenameFn>),
+    /// A regular expression matched against file names, replaced with
+    /// `$1`/`${name}` capture references. Files which don't match keep their name.
+    #[cfg(feature = "regex")]
+    Regex(Regex, String),
+}
+
+/// Function computing a new file name for `Rename::Fn`.
+pub type RenameFn = dyn Fn(&File, usize) -> String;
+
+/// `RenamePlan` is the validated list of renames which `FileSet::rename_with`
+/// performs. It displays as one `from -> to` line per rename, for dry runs.
+#[derive(Clone, Debug)]
+pub struct RenamePlan {
+    moves: Vec<(File, File)>,
+}
+
+impl FileSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        FileSet { files: Vec::new() }
+    }
+
+    /// Add a file at the end of the set.
+    pub fn push(&mut self, file: File) {
+        self.files.push(file);
+    }
+
+    /// Compute the renames `rename` would do, without touching the disk.
+    /// Fails with `AlreadyExists` if two files would get the same name, or if
+    /// a new name is taken by a file which isn't renamed away, and with
+    /// `InvalidInput` if a new name isn't a plain file name.
+    pub fn plan_rename(&self, rename: &Rename) -> io::Result<RenamePlan> {
+        let mut moves = Vec::new();
+        for (i, file) in self.files.iter().enumerate() {
+            if let Some(name) = rename.new_name(file, i)? {
+                check_name(file, &name)?;
+                let to = File::from(file.with_file_name(name));
+                if to != *file {
+                    moves.push((file.clone(), to));
+                }
+            }
+        }
+        let sources: HashSet<&File> = moves.iter().map(|m| &m.0).collect();
+        let mut targets = HashSet::new();
+        for (from, to) in &moves {
+            if !targets.insert(to) {
+                return Err(collision(from, to, "another file is renamed to the same name"));
+            }
+            if to.exists() && !sources.contains(to) {
+                return Err(collision(from, to, "the destination already exists"));
+            }
+        }
+        Ok(RenamePlan { moves })
+    }
+
+    /// Rename every file as `rename` describes and return the renamed set.
+    /// Nothing is renamed when the plan has collisions.
+    pub fn rename_with(&self, rename: &Rename) -> io::Result<FileSet> {
+        let plan = self.plan_rename(rename)?;
+        let renamed: HashMap<File, File> = plan.apply()?.into_iter().collect();
+        Ok(self.files.iter().map(|f| renamed.get(f).unwrap_or(f).clone()).collect())
+    }
+}
+
+impl Rename {
+    /// Rename with a template, see `Rename::Template`.
+    pub fn template<S: Into<String>>(template: S) -> Self {
+        Rename::Template(template.into())
+    }
+
+    /// Rename with a function, see `Rename::Fn`.
+    pub fn with<F>(f: F) -> Self
+    where
+        F: Fn(&File, usize) -> String + 'static,
+    {
+        Rename::Fn(Box::new(f))
+    }
+
+    /// Rename with a regular expression, see `Rename::Regex`.
+    #[cfg(feature = "regex")]
+    pub fn regex<S: Into<String>>(pattern: &str, replacement: S) -> Result<Self, ::regex::Error> {
+        Ok(Rename::Regex(Regex::new(pattern)?, replacement.into()))
+    }
+
+    /// The new file name of `file` at position `i`, `None` to leave it as is.
+    fn new_name(&self, file: &File, i: usize) -> io::Result<Option<String>> {
+        match *self {
+            Rename::Template(ref template) => expand_template(template, file, i + 1).map(Some),
+            Rename::Fn(ref f) => Ok(Some(f(file, i))),
+            #[cfg(feature = "regex")]
+            Rename::Regex(ref re, ref replacement) => {
+                let name = lossy(file.file_name());
+                if re.is_match(&name) {
+                    Ok(Some(re.replace(&name, replacement.as_str()).into_owned()))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+impl RenamePlan {
+    /// Pairs of current and new files.
+    pub fn moves(&self) -> &[(File, File)] {
+        &self.moves
+    }
+
+    /// Whether the plan renames nothing.
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Perform the renames. When a new name is currently used by another file
+    /// of the plan, everything is first moved to temporary names so that
+    /// swaps and shifts (`1 -> 2`, `2 -> 3`) work. If a rename fails, those
+    /// already done are undone as far as possible before returning the error.
+    pub fn apply(self) -> io::Result<Vec<(File, File)>> {
+        let sources: HashSet<&File> = self.moves.iter().map(|m| &m.0).collect();
+        let chained = self.moves.iter().any(|m| sources.contains(&m.1));
+        if !chained {
+            for (i, (from, to)) in self.moves.iter().enumerate() {
+                if let Err(e) = fs::rename(&**from, &**to) {
+                    for (from, to) in self.moves[..i].iter().rev() {
+                        let _ = fs::rename(&**to, &**from);
+                    }
+                    return Err(e);
+                }
+            }
+            return Ok(self.moves);
+        }
+        let mut staged = Vec::with_capacity(self.moves.len());
+        for (i, (from, _)) in self.moves.iter().enumerate() {
+            let tmp = from.with_file_name(format!(".rename-{}-{}.tmp", process::id(), i));
+            if let Err(e) = fs::rename(&**from, &tmp) {
+                for (tmp, (from, _)) in staged.iter().zip(&self.moves).rev() {
+                    let _ = fs::rename(tmp, &**from);
+                }
+                return Err(e);
+            }
+            staged.push(tmp);
+        }
+        for (i, (tmp, (_, to))) in staged.iter().zip(&self.moves).enumerate() {
+            if let Err(e) = fs::rename(tmp, &**to) {
+                // Free the new names first, they may be the old names of
+                // other files.
+                for (tmp, (_, to)) in staged[..i].iter().zip(&self.moves).rev() {
+                    let _ = fs::rename(&**to, tmp);
+                }
+                for (tmp, (from, _)) in staged.iter().zip(&self.moves).rev() {
+                    let _ = fs::rename(tmp, &**from);
+                }
+                return Err(e);
+            }
+        }
+        Ok(self.moves)
+    }
+}
+
+impl fmt::Display for RenamePlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (from, to) in &self.moves {
+            writeln!(f, "{} -> {}", from.display(), to.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn collision(from: &File, to: &File, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("cannot rename {} to {}: {}", from.display(), to.display(), reason),
+    )
+}
+
+/// Fail unless `name` names a file in the directory of `file`.
+fn check_name(file: &File, name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.chars().any(path::is_separator) {
+        let msg = format!("cannot rename {} to {:?}: not a file name", file.display(), name);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
+
+fn expand_template(template: &str, file: &File, n: usize) -> io::Result<String> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let spec: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let mut parts = spec.splitn(2, ':');
+                let key = parts.next().unwrap_or("");
+                let format = parts.next();
+                let value = match key {
+                    "n" => n.to_string(),
+                    "name" => lossy(file.file_stem()),
+                    "ext" => lossy(file.extension()),
+                    _ => return Err(invalid(format!("unknown placeholder {{{}}}", spec))),
+                };
+                match format {
+                    None => out.push_str(&value),
+                    Some(width) if key == "n" && width.starts_with('0') => {
+                        let width = width.parse::<usize>()
+                            .map_err(|_| invalid(format!("invalid width in {{{}}}", spec)))?;
+                        out.push_str(&format!("{:0>1$}", value, width));
+                    }
+                    Some(_) => return Err(invalid(format!("invalid format in {{{}}}", spec))),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+fn lossy(s: Option<&::std::ffi::OsStr>) -> String {
+    s.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+impl ops::Deref for FileSet {
+    type Target = [File];
+
+    fn deref(&self) -> &[File] {
+        &self.files
+    }
+}
+
+impl From<Vec<File>> for FileSet {
+    fn from(files: Vec<File>) -> FileSet {
+        FileSet { files }
+    }
+}
+
+impl FromIterator<File> for FileSet {
+    fn from_iter<I: IntoIterator<Item = File>>(iter: I) -> Self {
+        FileSet { files: iter.into_iter().collect() }
+    }
+}
+
+impl FromIterator<PathBuf> for FileSet {
+    fn from_iter<I: IntoIterator<Item = PathBuf>>(iter: I) -> Self {
+        iter.into_iter().map(File::from).collect()
+    }
+}
+
+impl IntoIterator for FileSet {
+    type Item = File;
+    type IntoIter = vec::IntoIter<File>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a FileSet {
+    type Item = &'a File;
+    type IntoIter = ::std::slice::Iter<'a, File>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use super::*;
+
+    fn files(dir: &TempDir, names: &[&str]) -> FileSet {
+        names
+            .iter()
+            .map(|name| {
+                let f = File::new(dir.path().join(name));
+                f.overwrite(name.as_bytes()).unwrap();
+                f
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rename_with_template() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let set = files(&tmp_dir, &["a.jpg", "b.jpg"]);
+
+        let plan = set.plan_rename(&Rename::template("photo-{n:04}.{ext}")).unwrap();
+        assert_eq!(2, plan.moves().len());
+        assert!(plan.to_string().ends_with("photo-0002.jpg\n"));
+        assert!(set[0].exists());
+
+        let renamed = set.rename_with(&Rename::template("photo-{n:04}.{ext}")).unwrap();
+        assert_eq!("a.jpg", &renamed[0].read_string().unwrap());
+        assert!(renamed[1].ends_with("photo-0002.jpg"));
+        assert!(!set[0].exists());
+    }
+
+    #[test]
+    fn rename_collisions_and_swaps() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let set = files(&tmp_dir, &["1.txt", "2.txt", "other.txt"]);
+
+        let same = Rename::with(|_, _| "same.txt".to_owned());
+        assert_eq!(io::ErrorKind::AlreadyExists, set.plan_rename(&same).unwrap_err().kind());
+        let taken = FileSet::from(vec![set[0].clone()]);
+        let to_other = Rename::with(|_, _| "other.txt".to_owned());
+        assert_eq!(io::ErrorKind::AlreadyExists, taken.plan_rename(&to_other).unwrap_err().kind());
+
+        let swap = FileSet::from(set[..2].to_vec());
+        swap.rename_with(&Rename::with(|_, i| format!("{}.txt", 2 - i))).unwrap();
+        assert_eq!("2.txt", &set[0].read_string().unwrap());
+        assert_eq!("1.txt", &set[1].read_string().unwrap());
+
+        let escape = Rename::with(|_, _| "../out.txt".to_owned());
+        assert_eq!(io::ErrorKind::InvalidInput, set.plan_rename(&escape).unwrap_err().kind());
+        let nested = Rename::template("sub/{name}.{ext}");
+        assert_eq!(io::ErrorKind::InvalidInput, set.plan_rename(&nested).unwrap_err().kind());
+    }
+
+    #[test]
+    fn rename_rolls_back() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let mut set = files(&tmp_dir, &["1.txt", "2.txt"]);
+        set.push(File::new(tmp_dir.path().join("missing.txt")));
+
+        let shift = Rename::with(|_, i| format!("{}.txt", i + 2));
+        assert!(set.rename_with(&shift).is_err());
+        assert_eq!("1.txt", &set[0].read_string().unwrap());
+        assert_eq!("2.txt", &set[1].read_string().unwrap());
+        assert_eq!(2, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn rename_with_regex() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let set = files(&tmp_dir, &["IMG_001.JPG", "notes.txt"]);
+        let rename = Rename::regex(r"^IMG_(\d+)\.JPG$", "img-$1.jpg").unwrap();
+        let renamed = set.rename_with(&rename).unwrap();
+        assert!(renamed[0].ends_with("img-001.jpg"));
+        assert_eq!(set[1], renamed[1]);
+    }
+}
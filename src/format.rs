@@ -0,0 +1,506 @@
+use std::error;
+use std::fmt;
+#[cfg(any(feature = "csv", feature = "json"))]
+use std::fs;
+use std::io;
+#[cfg(any(feature = "csv", feature = "json"))]
+use std::io::BufReader;
+#[cfg(any(feature = "json", feature = "toml"))]
+use std::io::prelude::*;
+#[cfg(any(feature = "csv", feature = "json"))]
+use std::marker::PhantomData;
+use std::result;
+
+#[cfg(any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml"))]
+use serde::de::DeserializeOwned;
+#[cfg(any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml"))]
+use serde::Serialize;
+
+#[cfg(any(feature = "csv", feature = "json", feature = "toml", feature = "yaml"))]
+use atomic::AtomicFile;
+
+#[cfg(any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml"))]
+use file::File;
+#[cfg(any(feature = "bin", feature = "csv", feature = "json"))]
+use file::FileOpener;
+#[cfg(feature = "csv")]
+use file::Open;
+
+/// `Error` is the error of reading or writing a file in a structured
+/// format: either I/O failed, or the content didn't match the format.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    #[cfg(feature = "bin")]
+    Bin(::postcard::Error),
+    #[cfg(feature = "csv")]
+    Csv(::csv::Error),
+    #[cfg(feature = "json")]
+    Json(::serde_json::Error),
+    /// Invalid JSON on the given 1-based line of a JSON Lines file.
+    #[cfg(feature = "json")]
+    JsonLine(usize, ::serde_json::Error),
+    #[cfg(feature = "toml")]
+    TomlDe(::toml::de::Error),
+    #[cfg(feature = "toml")]
+    TomlSer(::toml::ser::Error),
+    #[cfg(feature = "yaml")]
+    Yaml(::serde_yaml::Error),
+}
+
+/// `Style` tells whether text formats are written for humans or compactly.
+#[cfg(any(feature = "json", feature = "toml"))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    /// Indented, over multiple lines.
+    Pretty,
+    /// As short as the format allows.
+    Compact,
+}
+
+/// Iterator over the records of a CSV file, created by `File::read_csv`.
+/// Records are read one at a time from a buffered reader.
+#[cfg(feature = "csv")]
+pub struct CsvRecords<T> {
+    reader: ::csv::Reader<BufReader<fs::File>>,
+    headers: ::csv::StringRecord,
+    record: ::csv::StringRecord,
+    record_type: PhantomData<T>,
+}
+
+/// Iterator over the values of a JSON Lines (NDJSON) file, created by
+/// `File::read_jsonl`. Blank lines are skipped.
+#[cfg(feature = "json")]
+pub struct JsonLines<T> {
+    reader: BufReader<fs::File>,
+    line: String,
+    lineno: usize,
+    value_type: PhantomData<T>,
+}
+
+/// `Result` of reading or writing a file in a structured format.
+pub type Result<T> = result::Result<T, Error>;
+
+#[cfg(any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml"))]
+impl File {
+    /// Deserialize the file from the compact binary format written by
+    /// `write_bin`.
+    #[cfg(feature = "bin")]
+    pub fn read_bin<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(::postcard::from_bytes(&self.read_all()?)?)
+    }
+
+    /// Serialize `value` into the file, in a compact binary format
+    /// (postcard), replacing previous content.
+    #[cfg(feature = "bin")]
+    pub fn write_bin<T: Serialize>(&self, value: &T) -> Result<()> {
+        let buf = ::postcard::to_stdvec(value)?;
+        Ok(self.write_all_with(&buf, FileOpener::truncate())?)
+    }
+
+    /// Iterate over the records of the file as CSV with a header row,
+    /// each deserialized by matching header names with fields of `T`.
+    #[cfg(feature = "csv")]
+    pub fn read_csv<T: DeserializeOwned>(&self) -> Result<CsvRecords<T>> {
+        let mut reader = ::csv::Reader::from_reader(self.buf_reader()?);
+        let headers = reader.headers()?.clone();
+        Ok(CsvRecords {
+            reader,
+            headers,
+            record: ::csv::StringRecord::new(),
+            record_type: PhantomData,
+        })
+    }
+
+    /// Serialize `records` into the file as CSV, with a header row taken
+    /// from the field names of `T`, replacing previous content atomically.
+    #[cfg(feature = "csv")]
+    pub fn write_csv<I, T>(&self, records: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: Serialize,
+    {
+        let mut w = ::csv::Writer::from_writer(AtomicFile::new(self)?);
+        for record in records {
+            w.serialize(record)?;
+        }
+        let w = w.into_inner().map_err(|e| e.into_error())?;
+        Ok(w.commit()?)
+    }
+
+    /// Append `record` to the file as CSV, creating it if needed. The
+    /// header row is written only when the file is empty.
+    #[cfg(feature = "csv")]
+    pub fn append_csv<T: Serialize>(&self, record: &T) -> Result<()> {
+        let f = FileOpener::append_or_create().open(self)?;
+        let empty = f.metadata()?.len() == 0;
+        let mut w = ::csv::WriterBuilder::new().has_headers(empty).from_writer(f);
+        w.serialize(record)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Deserialize the file as JSON.
+    #[cfg(feature = "json")]
+    pub fn read_json<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(::serde_json::from_reader(self.buf_reader()?)?)
+    }
+
+    /// Serialize `value` into the file as JSON, replacing previous content
+    /// atomically.
+    #[cfg(feature = "json")]
+    pub fn write_json<T: Serialize>(&self, value: &T, style: Style) -> Result<()> {
+        let mut w = AtomicFile::new(self)?;
+        match style {
+            Style::Pretty => ::serde_json::to_writer_pretty(&mut w, value)?,
+            Style::Compact => ::serde_json::to_writer(&mut w, value)?,
+        }
+        if style == Style::Pretty {
+            w.write_all(b"\n")?;
+        }
+        Ok(w.commit()?)
+    }
+
+    /// Iterate over the values of the file as JSON Lines, one JSON value
+    /// per line. Errors tell the line they occurred on.
+    #[cfg(feature = "json")]
+    pub fn read_jsonl<T: DeserializeOwned>(&self) -> Result<JsonLines<T>> {
+        Ok(JsonLines {
+            reader: self.buf_reader()?,
+            line: String::new(),
+            lineno: 0,
+            value_type: PhantomData,
+        })
+    }
+
+    /// Append `value` as one line of JSON Lines, creating the file if
+    /// needed. Appending from several processes at once isn't
+    /// synchronized, use a lock if lines may be written concurrently.
+    #[cfg(feature = "json")]
+    pub fn append_jsonl<T: Serialize>(&self, value: &T) -> Result<()> {
+        let mut buf = ::serde_json::to_vec(value)?;
+        buf.push(b'\n');
+        Ok(self.write_all_with(&buf, FileOpener::append_or_create())?)
+    }
+
+    /// Deserialize the file as TOML.
+    #[cfg(feature = "toml")]
+    pub fn read_toml<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(::toml::from_str(&self.read_string()?)?)
+    }
+
+    /// Serialize `value` into the file as TOML, replacing previous content
+    /// atomically. `Style::Pretty` also lays arrays out one item per line.
+    #[cfg(feature = "toml")]
+    pub fn write_toml<T: Serialize>(&self, value: &T, style: Style) -> Result<()> {
+        let s = match style {
+            Style::Pretty => ::toml::to_string_pretty(value)?,
+            Style::Compact => ::toml::to_string(value)?,
+        };
+        let mut w = AtomicFile::new(self)?;
+        w.write_all(s.as_bytes())?;
+        Ok(w.commit()?)
+    }
+
+    /// Deserialize the file as YAML.
+    #[cfg(feature = "yaml")]
+    pub fn read_yaml<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(::serde_yaml::from_reader(self.buf_reader()?)?)
+    }
+
+    /// Serialize `value` into the file as YAML, replacing previous content
+    /// atomically.
+    #[cfg(feature = "yaml")]
+    pub fn write_yaml<T: Serialize>(&self, value: &T) -> Result<()> {
+        let mut w = AtomicFile::new(self)?;
+        ::serde_yaml::to_writer(&mut w, value)?;
+        Ok(w.commit()?)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<T> CsvRecords<T> {
+    /// The header row.
+    pub fn headers(&self) -> &::csv::StringRecord {
+        &self.headers
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<T: DeserializeOwned> Iterator for CsvRecords<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => Some(self.record.deserialize(Some(&self.headers)).map_err(Error::from)),
+            Ok(false) => None,
+            Err(e) => Some(Err(Error::from(e))),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: DeserializeOwned> Iterator for JsonLines<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+            self.lineno += 1;
+            let line = self.line.trim();
+            if !line.is_empty() {
+                let lineno = self.lineno;
+                return Some(::serde_json::from_str(line).map_err(|e| Error::JsonLine(lineno, e)));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => e.fmt(f),
+            #[cfg(feature = "bin")]
+            Error::Bin(ref e) => write!(f, "invalid binary data: {}", e),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref e) => write!(f, "invalid CSV: {}", e),
+            #[cfg(feature = "json")]
+            Error::Json(ref e) => write!(f, "invalid JSON: {}", e),
+            #[cfg(feature = "json")]
+            Error::JsonLine(line, ref e) => write!(f, "invalid JSON on line {}: {}", line, e),
+            #[cfg(feature = "toml")]
+            Error::TomlDe(ref e) => write!(f, "invalid TOML: {}", e),
+            #[cfg(feature = "toml")]
+            Error::TomlSer(ref e) => write!(f, "cannot write TOML: {}", e),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(ref e) => write!(f, "invalid YAML: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            #[cfg(feature = "bin")]
+            Error::Bin(ref e) => Some(e),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::Json(ref e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::JsonLine(_, ref e) => Some(e),
+            #[cfg(feature = "toml")]
+            Error::TomlDe(ref e) => Some(e),
+            #[cfg(feature = "toml")]
+            Error::TomlSer(ref e) => Some(e),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[cfg(feature = "bin")]
+impl From<::postcard::Error> for Error {
+    fn from(e: ::postcard::Error) -> Error {
+        Error::Bin(e)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<::csv::Error> for Error {
+    fn from(e: ::csv::Error) -> Error {
+        Error::Csv(e)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<::serde_json::Error> for Error {
+    fn from(e: ::serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<::toml::de::Error> for Error {
+    fn from(e: ::toml::de::Error) -> Error {
+        Error::TomlDe(e)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<::toml::ser::Error> for Error {
+    fn from(e: ::toml::ser::Error) -> Error {
+        Error::TomlSer(e)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<::serde_yaml::Error> for Error {
+    fn from(e: ::serde_yaml::Error) -> Error {
+        Error::Yaml(e)
+    }
+}
+
+#[cfg(all(test, any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml")))]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Config {
+        name: String,
+        port: u16,
+    }
+
+    fn expected() -> Config {
+        Config {
+            name: "app".to_owned(),
+            port: 8080,
+        }
+    }
+
+    #[cfg(feature = "bin")]
+    #[test]
+    fn binary_round_trip() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("config.bin"));
+        f.write_bin(&expected()).unwrap();
+        assert_eq!(expected(), f.read_bin::<Config>().unwrap());
+
+        f.write_bin(&1u8).unwrap();
+        match f.read_bin::<Config>() {
+            Err(Error::Bin(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn read_csv() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("configs.csv"));
+        f.overwrite(b"port,name\n8080,app\nnot-a-port,bad\n").unwrap();
+
+        let mut records = f.read_csv::<Config>().unwrap();
+        assert_eq!(vec!["port", "name"], records.headers().iter().collect::<Vec<_>>());
+        assert_eq!(expected(), records.next().unwrap().unwrap());
+        match records.next() {
+            Some(Err(Error::Csv(_))) => {}
+            other => panic!("unexpected {:?}", other.map(|r| r.is_ok())),
+        }
+        assert!(records.next().is_none());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn write_and_append_csv() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("configs.csv"));
+        f.append_csv(&expected()).unwrap();
+        f.append_csv(&Config { port: 9090, ..expected() }).unwrap();
+        assert_eq!("name,port\napp,8080\napp,9090\n", &f.read_string().unwrap());
+
+        f.write_csv(vec![expected()]).unwrap();
+        assert_eq!("name,port\napp,8080\n", &f.read_string().unwrap());
+        let records: Vec<Config> = f.read_csv().unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(vec![expected()], records);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn read_json() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("config.json"));
+        f.overwrite(br#"{"name": "app", "port": 8080}"#).unwrap();
+        assert_eq!(expected(), f.read_json::<Config>().unwrap());
+
+        f.truncate(b"{").unwrap();
+        match f.read_json::<Config>() {
+            Err(Error::Json(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match File::new(tmp_dir.path().join("missing.json")).read_json::<Config>() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn write_json() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("config.json"));
+        f.write_json(&expected(), Style::Compact).unwrap();
+        assert_eq!(r#"{"name":"app","port":8080}"#, &f.read_string().unwrap());
+        f.write_json(&expected(), Style::Pretty).unwrap();
+        assert_eq!("{\n  \"name\": \"app\",\n  \"port\": 8080\n}\n", &f.read_string().unwrap());
+        assert_eq!(expected(), f.read_json::<Config>().unwrap());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn read_jsonl() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("events.jsonl"));
+        let lines = "{\"name\": \"app\", \"port\": 8080}\n\n  \r\n{\"name\": 1}\n";
+        f.overwrite(lines.as_bytes()).unwrap();
+
+        let mut values = f.read_jsonl::<Config>().unwrap();
+        assert_eq!(expected(), values.next().unwrap().unwrap());
+        match values.next() {
+            Some(Err(Error::JsonLine(4, _))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(values.next().is_none());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn append_jsonl() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("appended.jsonl"));
+        f.append_jsonl(&expected()).unwrap();
+        f.append_jsonl(&expected()).unwrap();
+        assert_eq!(2, f.read_jsonl::<Config>().unwrap().count());
+        assert!(f.read_string().unwrap().ends_with("8080}\n"));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn read_toml() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("config.toml"));
+        f.overwrite(b"name = \"app\"\nport = 8080\n").unwrap();
+        assert_eq!(expected(), f.read_toml::<Config>().unwrap());
+
+        f.write_toml(&Config { port: 9090, ..expected() }, Style::Compact).unwrap();
+        assert_eq!("name = \"app\"\nport = 9090\n", &f.read_string().unwrap());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn read_yaml() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let f = File::new(tmp_dir.path().join("config.yaml"));
+        f.overwrite(b"name: app\nport: 8080\n").unwrap();
+        assert_eq!(expected(), f.read_yaml::<Config>().unwrap());
+
+        f.write_yaml(&Config { port: 9090, ..expected() }).unwrap();
+        assert_eq!("name: app\nport: 9090\n", &f.read_string().unwrap());
+    }
+}
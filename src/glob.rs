@@ -0,0 +1,307 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use file::File;
+use walk::{Walk, WalkOptions};
+
+/// `Glob` iterates over the files and directories under a directory whose
+/// path relative to it matches a pattern, created by `Dir::glob`.
+///
+/// Patterns use `/` between components whatever the platform. In a
+/// component, `*` matches any characters, `?` one character, and `[abc]`,
+/// `[a-z]` or `[!abc]` one character of a set. A `**` component matches
+/// any number of directories, including none. Hidden entries are matched
+/// like any other.
+pub struct Glob {
+    root: PathBuf,
+    pattern: Vec<Segment>,
+    walk: Option<Walk>,
+}
+
+/// A parsed component of a pattern.
+#[derive(Clone, Debug)]
+enum Segment {
+    AnyDirs,
+    Name(Vec<char>),
+}
+
+impl Glob {
+    pub(crate) fn new(root: &Path, pattern: &str) -> io::Result<Self> {
+        let pattern = parse(pattern)?;
+        // Walk from the directory named by the leading literal components,
+        // so that `src/**` doesn't scan the whole tree.
+        let mut start = root.to_path_buf();
+        let mut depth = pattern.len();
+        for segment in &pattern[..pattern.len().saturating_sub(1)] {
+            match *segment {
+                Segment::Name(ref chars) if is_literal(chars) => {
+                    start.push(chars.iter().collect::<String>());
+                    depth -= 1;
+                }
+                _ => break,
+            }
+        }
+        let is_dir = |path: &Path| fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+        let walk = if start == root || is_dir(&start) {
+            let mut options = WalkOptions::new();
+            if !pattern.iter().any(|s| matches!(*s, Segment::AnyDirs)) {
+                options = options.max_depth(depth);
+            }
+            Some(Walk::new(&start, options))
+        } else {
+            None
+        };
+        Ok(Glob {
+            root: root.to_path_buf(),
+            pattern,
+            walk,
+        })
+    }
+}
+
+impl Iterator for Glob {
+    type Item = io::Result<File>;
+
+    fn next(&mut self) -> Option<io::Result<File>> {
+        loop {
+            let entry = match self.walk.as_mut()?.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            let names: Vec<Vec<char>> = match entry.file().strip_prefix(&self.root) {
+                Ok(rel) => rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().chars().collect())
+                    .collect(),
+                Err(_) => continue,
+            };
+            if match_path(&self.pattern, &names) {
+                return Some(Ok(entry.into_file()));
+            }
+        }
+    }
+}
+
+/// Build a matcher of file names from a pattern of a single component,
+/// such as `*.log`.
+pub(crate) fn name_matcher(pattern: &str) -> io::Result<impl Fn(&str) -> bool> {
+    match parse(pattern)?.as_slice() {
+        [Segment::Name(pat)] => {
+            let pat = pat.clone();
+            Ok(move |name: &str| match_name(&pat, &name.chars().collect::<Vec<_>>()))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected a file name pattern, got {:?}", pattern),
+        )),
+    }
+}
+
+fn parse(pattern: &str) -> io::Result<Vec<Segment>> {
+    let invalid = |msg: &str| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} in pattern {:?}", msg, pattern))
+    };
+    if pattern.is_empty() || pattern.starts_with('/') {
+        return Err(invalid("expected a relative path"));
+    }
+    let mut segments = Vec::new();
+    for part in pattern.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        if part == "**" {
+            segments.push(Segment::AnyDirs);
+            continue;
+        }
+        let chars: Vec<char> = part.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '[' {
+                match chars[i + 1..].iter().position(|&c| c == ']') {
+                    Some(0) | None => return Err(invalid("unclosed `[`")),
+                    Some(n) => i += n + 1,
+                }
+            }
+            i += 1;
+        }
+        segments.push(Segment::Name(chars));
+    }
+    Ok(segments)
+}
+
+fn is_literal(chars: &[char]) -> bool {
+    !chars.iter().any(|&c| c == '*' || c == '?' || c == '[')
+}
+
+/// Match components against segments, with `**` matching any number of
+/// them. Like `match_name`, it only ever backtracks to the last `**`,
+/// which is enough as each `**` can absorb what the previous ones would.
+fn match_path(pattern: &[Segment], names: &[Vec<char>]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut any_dirs: Option<(usize, usize)> = None;
+    while n < names.len() {
+        match pattern.get(p) {
+            Some(&Segment::AnyDirs) => {
+                any_dirs = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(Segment::Name(pat)) if match_name(pat, &names[n]) => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match any_dirs {
+            Some((after, from)) => {
+                any_dirs = Some((after, from + 1));
+                p = after;
+                n = from + 1;
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|s| matches!(*s, Segment::AnyDirs))
+}
+
+/// Match a name against a component pattern without recursion, in at most
+/// `O(pattern * name)` steps, backtracking only to the last `*`.
+fn match_name(pat: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if pat.get(p) == Some(&'*') {
+            star = Some((p + 1, n));
+            p += 1;
+            continue;
+        }
+        if let Some(next) = match_char(pat, p, name[n]) {
+            p = next;
+            n += 1;
+            continue;
+        }
+        match star {
+            Some((after, from)) => {
+                star = Some((after, from + 1));
+                p = after;
+                n = from + 1;
+            }
+            None => return false,
+        }
+    }
+    pat[p..].iter().all(|&c| c == '*')
+}
+
+/// If the single character pattern at `pat[p]` matches `c`, the position
+/// after it.
+fn match_char(pat: &[char], p: usize, c: char) -> Option<usize> {
+    match pat.get(p) {
+        None | Some(&'*') => None,
+        Some(&'?') => Some(p + 1),
+        Some(&'[') => {
+            let rest = &pat[p + 1..];
+            let close = rest.iter().position(|&c| c == ']').expect("validated on parse");
+            if match_set(&rest[..close], c) {
+                Some(p + close + 2)
+            } else {
+                None
+            }
+        }
+        Some(&ch) if ch == c => Some(p + 1),
+        Some(_) => None,
+    }
+}
+
+fn match_set(set: &[char], c: char) -> bool {
+    let (negated, set) = match set.split_first() {
+        Some((&'!', rest)) if !rest.is_empty() => (true, rest),
+        _ => (false, set),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            found |= set[i] <= c && c <= set[i + 2];
+            i += 3;
+        } else {
+            found |= set[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use dir::Dir;
+
+    #[test]
+    fn glob() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("src/fs/unix").create().unwrap();
+        for name in &["src/lib.rs", "src/fs/mod.rs", "src/fs/unix/a.rs", "src/notes.md", "b.rs"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        let found = |pattern: &str| -> Vec<String> {
+            let mut found: Vec<String> = dir
+                .glob(pattern)
+                .unwrap()
+                .map(|f| {
+                    let f = f.unwrap();
+                    f.strip_prefix(tmp_dir.path()).unwrap().to_string_lossy().replace('\\', "/")
+                })
+                .collect();
+            found.sort();
+            found
+        };
+        let all_rs = vec!["src/fs/mod.rs", "src/fs/unix/a.rs", "src/lib.rs"];
+        assert_eq!(all_rs, found("src/**/*.rs"));
+        assert_eq!(vec!["b.rs"], found("*.rs"));
+        assert_eq!(vec!["src/fs/mod.rs"], found("src/?s/[l-n]*.rs"));
+        assert_eq!(vec!["src/lib.rs", "src/notes.md"], found("src/[!f]*"));
+        assert!(dir.glob("src/[.rs").is_err());
+        assert!(found("./").is_empty());
+    }
+
+    #[test]
+    fn literal_prefix() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("src/fs").create().unwrap();
+        dir.child_dir("target/debug").create().unwrap();
+        for name in &["src/lib.rs", "src/fs/mod.rs", "target/debug/lib.rs"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        let count = |pattern: &str| dir.glob(pattern).unwrap().count();
+        assert_eq!(3, count("src/**"));
+        assert_eq!(1, count("src/fs/*.rs"));
+        assert_eq!(0, count("missing/**"));
+        assert_eq!(0, count("src/lib.rs/*"));
+    }
+
+    #[test]
+    fn no_exponential_backtracking() {
+        use super::{match_name, match_path, Segment};
+
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        let name = chars(&"a".repeat(200));
+        assert!(!match_name(&chars("*a*a*a*a*a*a*a*b"), &name));
+        assert!(match_name(&chars("*a*a*a*a*a*a*a*"), &name));
+        assert!(match_name(&chars("[a-c]*?a"), &chars("bxya")));
+        assert!(!match_name(&chars("a*b?"), &chars("ab")));
+
+        let pattern = vec![Segment::AnyDirs; 8]
+            .into_iter()
+            .chain(Some(Segment::Name(chars("b"))))
+            .collect::<Vec<_>>();
+        let names = vec![chars("a"); 200];
+        assert!(!match_path(&pattern, &names));
+        let mut names = names;
+        names.push(chars("b"));
+        assert!(match_path(&pattern, &names));
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use walk::{Walk, WalkOptions};
+
+/// Size of the buffer the content is streamed through while hashing.
+const HASH_BUF_SIZE: usize = 64 * 1024;
+
+/// `Algorithm` is a checksum or digest algorithm, each behind the
+/// feature of the same name (`sha2` for SHA-256).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Algorithm {
+    #[cfg(feature = "blake3")]
+    Blake3,
+    #[cfg(feature = "crc32")]
+    Crc32,
+    #[cfg(feature = "sha1")]
+    Sha1,
+    #[cfg(feature = "sha2")]
+    Sha256,
+}
+
+/// `Digest` is the result of hashing content with an `Algorithm`.
+/// It displays as lowercase hex.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Digest {
+    algorithm: Algorithm,
+    bytes: Vec<u8>,
+}
+
+/// `Manifest` maps the paths of the files under a directory, relative to
+/// it, to the digest of their content. It is created by `Dir::manifest` and
+/// checked by `Dir::verify_manifest`. It displays as one `<hex>  <path>`
+/// line per file, the format of `sha256sum` and friends, which `parse` reads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Manifest {
+    algorithm: Algorithm,
+    digests: BTreeMap<PathBuf, Digest>,
+}
+
+/// `ManifestReport` tells how a tree differs from a `Manifest`, as paths
+/// relative to the directory. It displays as a one line summary.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ManifestReport {
+    /// Files of the manifest which don't exist anymore.
+    pub missing: Vec<PathBuf>,
+    /// Files whose content doesn't match their digest.
+    pub mismatched: Vec<PathBuf>,
+    /// Files which aren't in the manifest.
+    pub extra: Vec<PathBuf>,
+    /// Number of files which match their digest.
+    pub verified: usize,
+}
+
+enum State {
+    #[cfg(feature = "blake3")]
+    Blake3(Box<::blake3::Hasher>),
+    #[cfg(feature = "crc32")]
+    Crc32(::crc32fast::Hasher),
+    #[cfg(feature = "sha1")]
+    Sha1(::sha1::Sha1),
+    #[cfg(feature = "sha2")]
+    Sha256(::sha2::Sha256),
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => "blake3",
+            #[cfg(feature = "crc32")]
+            Algorithm::Crc32 => "crc32",
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => "sha1",
+            #[cfg(feature = "sha2")]
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+impl Digest {
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The raw digest; for CRC32, the big endian bytes of the checksum.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The digest as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Manifest {
+    /// Read a manifest in the `<hex>  <path>` format, where digests were
+    /// computed with `algorithm`. Fails with `InvalidData` on malformed lines.
+    pub fn parse(text: &str, algorithm: Algorithm) -> io::Result<Manifest> {
+        let mut digests = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid manifest line {}: {}", i + 1, line),
+                )
+            };
+            let mut parts = line.splitn(2, "  ");
+            let hex = parts.next().unwrap_or("");
+            let path = parts.next().ok_or_else(invalid)?;
+            let bytes = from_hex(hex).ok_or_else(invalid)?;
+            digests.insert(PathBuf::from(path), Digest { algorithm, bytes });
+        }
+        Ok(Manifest { algorithm, digests })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The digest of the file at the relative path `path`.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&Digest> {
+        self.digests.get(path.as_ref())
+    }
+
+    /// The relative paths and digests, ordered by path.
+    pub fn digests(&self) -> &BTreeMap<PathBuf, Digest> {
+        &self.digests
+    }
+
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (path, digest) in &self.digests {
+            writeln!(f, "{}  {}", digest, slashed(path))?;
+        }
+        Ok(())
+    }
+}
+
+impl ManifestReport {
+    /// Whether the tree matches the manifest exactly.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty() && self.extra.is_empty()
+    }
+}
+
+impl fmt::Display for ManifestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} verified, {} mismatched, {} missing, {} extra",
+            self.verified,
+            self.mismatched.len(),
+            self.missing.len(),
+            self.extra.len()
+        )
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in &self.bytes {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    fn new(algorithm: Algorithm) -> State {
+        match algorithm {
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => State::Blake3(Box::new(::blake3::Hasher::new())),
+            #[cfg(feature = "crc32")]
+            Algorithm::Crc32 => State::Crc32(::crc32fast::Hasher::new()),
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => State::Sha1(::sha1::Digest::new()),
+            #[cfg(feature = "sha2")]
+            Algorithm::Sha256 => State::Sha256(::sha2::Digest::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match *self {
+            #[cfg(feature = "blake3")]
+            State::Blake3(ref mut h) => {
+                h.update(data);
+            }
+            #[cfg(feature = "crc32")]
+            State::Crc32(ref mut h) => h.update(data),
+            #[cfg(feature = "sha1")]
+            State::Sha1(ref mut h) => ::sha1::Digest::update(h, data),
+            #[cfg(feature = "sha2")]
+            State::Sha256(ref mut h) => ::sha2::Digest::update(h, data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "blake3")]
+            State::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            #[cfg(feature = "crc32")]
+            State::Crc32(h) => h.finalize().to_be_bytes().to_vec(),
+            #[cfg(feature = "sha1")]
+            State::Sha1(h) => ::sha1::Digest::finalize(h).to_vec(),
+            #[cfg(feature = "sha2")]
+            State::Sha256(h) => ::sha2::Digest::finalize(h).to_vec(),
+        }
+    }
+}
+
+/// Hash everything `r` yields.
+pub(crate) fn digest_reader<R: Read>(mut r: R, algorithm: Algorithm) -> io::Result<Digest> {
+    let mut state = State::new(algorithm);
+    let mut buf = vec![0; HASH_BUF_SIZE];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => state.update(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Digest {
+        algorithm,
+        bytes: state.finish(),
+    })
+}
+
+/// Hash every regular file under `root`. Symbolic links aren't followed.
+pub(crate) fn manifest(root: &Path, algorithm: Algorithm) -> io::Result<Manifest> {
+    let mut digests = BTreeMap::new();
+    for entry in Walk::new(root, WalkOptions::new()) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.file().strip_prefix(root).expect("walked under root");
+        let digest = digest_reader(fs::File::open(entry.file())?, algorithm)?;
+        digests.insert(PathBuf::from(slashed(rel)), digest);
+    }
+    Ok(Manifest { algorithm, digests })
+}
+
+/// Check the regular files under `root` against `manifest`.
+pub(crate) fn verify(root: &Path, manifest: &Manifest) -> io::Result<ManifestReport> {
+    let current = self::manifest(root, manifest.algorithm)?;
+    let mut report = ManifestReport::default();
+    for (path, digest) in &manifest.digests {
+        match current.digests.get(path) {
+            None => report.missing.push(path.clone()),
+            Some(d) if d != digest => report.mismatched.push(path.clone()),
+            Some(_) => report.verified += 1,
+        }
+    }
+    for path in current.digests.keys() {
+        if !manifest.digests.contains_key(path) {
+            report.extra.push(path.clone());
+        }
+    }
+    Ok(report)
+}
+
+/// `path` with `/` separators, so that manifests are portable.
+fn slashed(path: &Path) -> String {
+    let parts: Vec<_> = path.iter().map(|p| p.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[cfg(feature = "sha2")]
+    extern crate tempdir;
+
+    #[cfg(feature = "sha2")]
+    use self::tempdir::TempDir;
+    #[cfg(feature = "sha2")]
+    use dir::Dir;
+    use super::*;
+
+    fn hex(algorithm: Algorithm) -> String {
+        digest_reader(&b"hello world"[..], algorithm).unwrap().to_hex()
+    }
+
+    #[test]
+    fn known_digests() {
+        #[cfg(feature = "blake3")]
+        assert_eq!(
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24",
+            hex(Algorithm::Blake3)
+        );
+        #[cfg(feature = "crc32")]
+        assert_eq!("0d4a1185", hex(Algorithm::Crc32));
+        #[cfg(feature = "sha1")]
+        assert_eq!("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed", hex(Algorithm::Sha1));
+        #[cfg(feature = "sha2")]
+        assert_eq!(
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            hex(Algorithm::Sha256)
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn manifest() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("sub").create().unwrap();
+        dir.child("sub/a.txt").overwrite(b"hello world").unwrap();
+        dir.child("b.txt").overwrite(b"b").unwrap();
+
+        let manifest = dir.manifest(Algorithm::Sha256).unwrap();
+        assert_eq!(2, manifest.len());
+        assert_eq!(hex(Algorithm::Sha256), manifest.get("sub/a.txt").unwrap().to_hex());
+        let stored = manifest.to_string();
+        assert!(stored.ends_with("  sub/a.txt\n"));
+        let parsed = Manifest::parse(&stored, Algorithm::Sha256).unwrap();
+        assert_eq!(manifest, parsed);
+        assert!(Manifest::parse("xyz  a.txt", Algorithm::Sha256).is_err());
+        assert!(dir.verify_manifest(&parsed).unwrap().is_ok());
+
+        dir.child("sub/a.txt").overwrite(b"tampered").unwrap();
+        ::std::fs::remove_file(dir.child("b.txt")).unwrap();
+        dir.child("c.txt").overwrite(b"c").unwrap();
+        let report = dir.verify_manifest(&parsed).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(vec![PathBuf::from("sub/a.txt")], report.mismatched);
+        assert_eq!("0 verified, 1 mismatched, 1 missing, 1 extra", &report.to_string());
+    }
+}
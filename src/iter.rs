@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::{self, SeekFrom};
+use std::io::prelude::*;
+
+/// Size of the blocks read backwards from the end of a file.
+const REVERSE_BLOCK_SIZE: u64 = 8 * 1024;
+
+/// Iterator over fixed size chunks of a file, created by `File::chunks`.
+///
+/// Every chunk but the last one has exactly the requested size.
+/// `next_chunk` lends the chunk from an internal buffer to avoid
+/// allocating on every step, while `Iterator` yields owned copies.
+pub struct Chunks {
+    file: fs::File,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+/// Iterator over the lines of a file from the last one to the first,
+/// created by `File::lines_reversed`.
+///
+/// The file is read backwards in fixed size blocks, so only the part of
+/// the file actually iterated over is read.
+pub struct LinesReversed {
+    file: fs::File,
+    pos: u64,
+    buf: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl Chunks {
+    pub(crate) fn new(file: fs::File, size: usize) -> Self {
+        assert!(size != 0, "chunk size must be non-zero");
+        Chunks {
+            file,
+            buf: vec![0; size],
+            done: false,
+        }
+    }
+
+    /// Read the next chunk into the internal buffer and borrow it.
+    pub fn next_chunk(&mut self) -> Option<io::Result<&[u8]>> {
+        if self.done {
+            return None;
+        }
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match self.file.read(&mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        if filled < self.buf.len() {
+            self.done = true;
+        }
+        if filled == 0 {
+            None
+        } else {
+            Some(Ok(&self.buf[..filled]))
+        }
+    }
+}
+
+impl Iterator for Chunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        self.next_chunk().map(|chunk| chunk.map(|c| c.to_vec()))
+    }
+}
+
+impl LinesReversed {
+    pub(crate) fn new(file: fs::File) -> io::Result<Self> {
+        let pos = file.metadata()?.len();
+        Ok(LinesReversed {
+            file,
+            pos,
+            buf: Vec::new(),
+            started: false,
+            done: pos == 0,
+        })
+    }
+
+    /// Prepend the block before `pos` to the buffer.
+    fn read_block(&mut self) -> io::Result<()> {
+        let size = REVERSE_BLOCK_SIZE.min(self.pos);
+        self.pos -= size;
+        let mut block = vec![0; size as usize];
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        self.file.read_exact(&mut block)?;
+        if !self.started {
+            // a newline right at the end of file doesn't start another line
+            self.started = true;
+            if block.last() == Some(&b'\n') {
+                block.pop();
+            }
+        }
+        block.extend_from_slice(&self.buf);
+        self.buf = block;
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(i) = self.buf.iter().rposition(|&b| b == b'\n') {
+                let line = self.buf.split_off(i + 1);
+                self.buf.pop();
+                return decode_line(line).map(Some);
+            }
+            if self.pos == 0 {
+                self.done = true;
+                let line = ::std::mem::take(&mut self.buf);
+                return decode_line(line).map(Some);
+            }
+            self.read_block()?;
+        }
+    }
+}
+
+fn decode_line(mut line: Vec<u8>) -> io::Result<String> {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Iterator for LinesReversed {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        if self.done {
+            return None;
+        }
+        let line = self.next_line();
+        if line.is_err() {
+            self.done = true;
+        }
+        line.transpose()
+    }
+}
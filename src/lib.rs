@@ -1,16 +1,321 @@
 //! **TODO: Crate level documentation**
 
+#[cfg(feature = "blake3")]
+extern crate blake3;
+#[cfg(feature = "encoding")]
+extern crate chardetng;
+#[cfg(feature = "crc32")]
+extern crate crc32fast;
+#[cfg(feature = "csv")]
+pub extern crate csv;
+#[cfg(feature = "encoding")]
+pub extern crate encoding_rs;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "notify")]
+extern crate notify;
+#[cfg(feature = "bin")]
+extern crate postcard;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "sha1")]
+extern crate sha1;
+#[cfg(feature = "sha2")]
+extern crate sha2;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "zip")]
+extern crate zip;
+
+#[cfg(all(test, any(feature = "bin", feature = "csv", feature = "json", feature = "toml", feature = "yaml")))]
+#[macro_use]
+extern crate serde_derive;
+
+/// `aligned` module provides `AlignedBuf`, the aligned buffers
+/// direct I/O needs.
+pub mod aligned;
+
+/// `archive` module contains the options of `Dir::zip_to`, behind the
+/// `zip` feature.
+#[cfg(feature = "zip")]
+pub mod archive;
+
+/// `copy` module contains the options of copying files and directories.
+pub mod copy;
+
+/// `dir` module provides `Dir`, the directory counterpart of `File`.
+pub mod dir;
+
 /// `file` module is the core of `good-files`, contains
 /// convenient wrapper around `std::fs` and `std::io`
 /// modules.
 pub mod file;
 
+/// `file_set` module provides `FileSet`, a collection of files
+/// to operate on in bulk, such as renaming.
+pub mod file_set;
+
+/// `format` module contains the error type of reading and writing
+/// files in structured formats, such as JSON, behind their features.
+pub mod format;
+
+/// `glob` module provides `Glob`, the pattern matching of `Dir::glob`.
+pub mod glob;
+
+/// `hash` module computes checksums and digests of file content, and
+/// manifests of directories, with algorithms behind their features.
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+pub mod hash;
+
+/// `iter` module contains iterators over the content of a file.
+pub mod iter;
+
+/// `lock` module provides advisory file locks taken when opening.
+pub mod lock;
+
+/// `log` module provides `LogFileWriter`, a small structured logger
+/// that writes JSON or logfmt lines to a file.
+pub mod log;
+
+/// `magic` module detects the type of content from its first bytes.
+pub mod magic;
+
+/// `mirror` module contains the options and report of `Dir::sync_to`,
+/// and the report of `Dir::diff`.
+pub mod mirror;
+
+/// `mmap` module provides memory maps of files, behind the `mmap` feature.
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+/// `open` module contains the combinators of the `Open` trait, and
+/// `OpenSpec` to describe openers in configuration.
+pub mod open;
+
+/// `progress` module provides `ProgressReader`, which reports
+/// the progress of long reads.
+pub mod progress;
+
+/// `replace` module contains the search-and-replace support
+/// of `Dir::replace_in_files`.
+pub mod replace;
+
+/// `retention` module contains the policy and report of `Dir::cleanup`.
+pub mod retention;
+
+/// `retry` module defines how transient file system failures,
+/// such as sharing violations on Windows, are retried.
+pub mod retry;
+
+/// `rotate` module provides writers which rotate files by size
+/// or by time period.
+pub mod rotate;
+
+/// `tee` module provides `TeeWriter`, which duplicates writes
+/// to several destinations.
+pub mod tee;
+
+/// `text` module contains text decoding helpers, such as BOM detection.
+pub mod text;
+
+/// `throttle` module provides `ThrottledWriter`, which limits
+/// the rate of writes.
+pub mod throttle;
+
+/// `tmp` module provides `TmpFile`, an unnamed temporary file, and
+/// `TmpDir`, a directory deleted when dropped.
+pub mod tmp;
+
+/// `tree` module contains the options of `Dir::render_tree`.
+pub mod tree;
+
+/// `usage` module contains the options of measuring directories,
+/// such as `Dir::total_size` and `Dir::count`.
+pub mod usage;
+
+/// `walk` module provides `Walk`, the recursive iterator of `Dir::walk`.
+pub mod walk;
+
+/// `watch` module provides `Watch`, the change notifications of
+/// `Dir::watch`, behind the `notify` feature.
+#[cfg(feature = "notify")]
+pub mod watch;
+
+mod atomic;
+
+mod sys;
+
+mod time;
+
+pub use aligned::AlignedBuf;
+
+#[cfg(feature = "zip")]
+pub use archive::Compression;
+
+#[cfg(feature = "zip")]
+pub use archive::ZipOptions;
+
+pub use copy::CopyOptions;
+
+pub use copy::Overwrite;
+
+pub use copy::Reflink;
+
+pub use copy::Symlinks;
+
+pub use dir::Dir;
+
+pub use dir::Entries;
+
+pub use dir::Sort;
+
+pub use dir::SortBy;
+
 pub use file::File;
 
 pub use file::FileOpener;
 
 pub use file::Open;
 
+pub use file::OpenDyn;
+
 pub use file::CreateMode;
 
 pub use file::WriteOption;
+
+pub use file::SyncPolicy;
+
+pub use file::BackupPolicy;
+
+pub use file::default_buf_capacity;
+
+pub use file::set_default_buf_capacity;
+
+pub use file_set::FileSet;
+
+pub use file_set::Rename;
+
+pub use file_set::RenamePlan;
+
+#[cfg(feature = "csv")]
+pub use format::CsvRecords;
+
+#[cfg(feature = "json")]
+pub use format::JsonLines;
+
+pub use glob::Glob;
+
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+pub use hash::Algorithm;
+
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+pub use hash::Digest;
+
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+pub use hash::Manifest;
+
+#[cfg(any(feature = "blake3", feature = "crc32", feature = "sha1", feature = "sha2"))]
+pub use hash::ManifestReport;
+
+pub use iter::Chunks;
+
+pub use iter::LinesReversed;
+
+pub use lock::FileLock;
+
+pub use lock::LockMode;
+
+pub use log::Level;
+
+pub use log::LogFileWriter;
+
+pub use log::LogFormat;
+
+pub use magic::ContentType;
+
+pub use mirror::Compare;
+
+pub use mirror::DirDiff;
+
+pub use mirror::SyncOptions;
+
+pub use mirror::SyncReport;
+
+#[cfg(feature = "mmap")]
+pub use mmap::ReadMap;
+
+#[cfg(feature = "mmap")]
+pub use mmap::WriteMap;
+
+pub use open::OpenSpec;
+
+pub use progress::ProgressReader;
+
+pub use progress::ProgressWriter;
+
+pub use replace::FileChange;
+
+pub use replace::Pattern;
+
+pub use replace::ReplaceOptions;
+
+pub use replace::ReplaceReport;
+
+pub use retention::CleanupReport;
+
+pub use retention::RetentionPolicy;
+
+pub use retry::Completion;
+
+pub use retry::RetryOptions;
+
+pub use rotate::RotatingWriter;
+
+pub use rotate::TimedRotatingWriter;
+
+pub use tee::TeePolicy;
+
+pub use tee::TeeWriter;
+
+pub use text::Bom;
+
+pub use text::Endian;
+
+pub use text::LineEnding;
+
+#[cfg(feature = "encoding")]
+pub use text::ErrorPolicy;
+
+#[cfg(feature = "encoding")]
+pub use text::TranscodeOptions;
+
+pub use throttle::ThrottledWriter;
+
+pub use tmp::TmpDir;
+
+pub use tmp::TmpFile;
+
+pub use tree::TreeOptions;
+
+pub use usage::CountOptions;
+
+pub use usage::Counts;
+
+pub use usage::SizeOptions;
+
+pub use walk::Walk;
+
+pub use walk::WalkEntry;
+
+pub use walk::WalkOptions;
+
+#[cfg(feature = "notify")]
+pub use watch::Watch;
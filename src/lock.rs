@@ -0,0 +1,177 @@
+use std::fs;
+use std::io::{self, SeekFrom};
+use std::io::prelude::*;
+use std::ops;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `LockMode` tells which advisory lock an opener takes on the file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LockMode {
+    /// Any number of holders, excluding exclusive ones.
+    Shared,
+    /// A single holder.
+    Exclusive,
+}
+
+/// `FileLock` is an open file holding an advisory lock, created by
+/// `FileOpener::open_locked`. The lock is released when it is dropped.
+///
+/// Advisory locks only exclude other processes that lock the file too.
+#[derive(Debug)]
+pub struct FileLock {
+    file: Option<fs::File>,
+    mode: LockMode,
+}
+
+impl LockMode {
+    /// Block until `f` is locked in this mode.
+    pub(crate) fn acquire(self, f: &fs::File) -> io::Result<()> {
+        match self {
+            LockMode::Shared => f.lock_shared(),
+            LockMode::Exclusive => f.lock(),
+        }
+    }
+}
+
+impl FileLock {
+    /// Lock `file` in `mode`, blocking until the lock is available.
+    pub fn new(file: fs::File, mode: LockMode) -> io::Result<Self> {
+        mode.acquire(&file)?;
+        Ok(FileLock {
+            file: Some(file),
+            mode,
+        })
+    }
+
+    /// The mode the file is locked in.
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    /// Release the lock and get the file back.
+    pub fn unlock(mut self) -> io::Result<fs::File> {
+        let file = self.file.take().expect("file is only taken on unlock");
+        file.unlock()?;
+        Ok(file)
+    }
+
+    fn file(&self) -> &fs::File {
+        self.file.as_ref().expect("file is only taken on unlock")
+    }
+
+    fn file_mut(&mut self) -> &mut fs::File {
+        self.file.as_mut().expect("file is only taken on unlock")
+    }
+}
+
+impl ops::Deref for FileLock {
+    type Target = fs::File;
+
+    fn deref(&self) -> &fs::File {
+        self.file()
+    }
+}
+
+impl ops::DerefMut for FileLock {
+    fn deref_mut(&mut self) -> &mut fs::File {
+        self.file_mut()
+    }
+}
+
+impl Read for FileLock {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file_mut().read(buf)
+    }
+}
+
+impl Write for FileLock {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file_mut().flush()
+    }
+}
+
+impl Seek for FileLock {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file_mut().seek(pos)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Some(ref file) = self.file {
+            let _ = file.unlock();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::fs;
+    use self::tempdir::TempDir;
+    use super::*;
+    use file::{FileOpener, Open};
+
+    #[test]
+    fn open_locked() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("config.toml");
+        let mut guard = FileOpener::truncate().lock_exclusive().open_locked(&path).unwrap();
+        assert_eq!(LockMode::Exclusive, guard.mode());
+        guard.write_all(b"locked").unwrap();
+
+        let other = FileOpener::readonly().open(&path).unwrap();
+        assert!(other.try_lock_shared().is_err());
+        drop(guard);
+        assert!(other.try_lock_shared().is_ok());
+        other.unlock().unwrap();
+
+        let f = FileOpener::readonly().lock_shared().open(&path).unwrap();
+        let same = fs::File::open(&path).unwrap();
+        assert!(same.try_lock_shared().is_ok());
+        assert!(same.try_lock().is_err());
+        drop(f);
+    }
+
+    #[test]
+    fn truncates_once_locked() {
+        use std::thread;
+        use std::time::Duration;
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("config.toml");
+        fs::write(&path, b"in use").unwrap();
+        let holder = FileOpener::readonly().lock_exclusive().open(&path).unwrap();
+
+        let opener = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut f = FileOpener::truncate().lock_exclusive().open(&path).unwrap();
+                f.write_all(b"new").unwrap();
+            })
+        };
+        let locker = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut guard = FileOpener::truncate().open_locked(&path).unwrap();
+                guard.write_all(b"new").unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(b"in use".to_vec(), fs::read(&path).unwrap());
+        drop(holder);
+        opener.join().unwrap();
+        locker.join().unwrap();
+        assert_eq!(b"new".to_vec(), fs::read(&path).unwrap());
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use file::SyncPolicy;
+use rotate::RotatingWriter;
+use time::Tm;
+
+/// Severity of a log record, ordered from the most verbose.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// `LogFormat` selects how a record is laid out on its line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogFormat {
+    /// One JSON object per line.
+    Json,
+    /// `key=value` pairs separated by spaces.
+    Logfmt,
+}
+
+/// `LogFileWriter` appends structured log records to a file, one per line,
+/// dropping records below its level and optionally rotating the file
+/// once it grows past a size limit.
+pub struct LogFileWriter {
+    writer: RotatingWriter,
+    format: LogFormat,
+    level: Level,
+    sync: SyncPolicy,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LogFileWriter {
+    /// Open the log file for appending, creating it if needed.
+    /// Records are written as logfmt from `Level::Info`, without rotation
+    /// and without syncing.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(LogFileWriter {
+            writer: RotatingWriter::unlimited(path)?,
+            format: LogFormat::Logfmt,
+            level: Level::Info,
+            sync: SyncPolicy::None,
+        })
+    }
+
+    /// Set the line format of further records.
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Drop records less severe than `level`.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Rotate the file once it exceeds `max_size` bytes, keeping `keep`
+    /// older files as `name.1` (the newest) to `name.<keep>`.
+    pub fn rotate(mut self, max_size: u64, keep: usize) -> Self {
+        self.writer.set_limits(max_size, keep);
+        self
+    }
+
+    /// Flush and sync the file after every record as `policy` says.
+    pub fn sync(mut self, policy: SyncPolicy) -> Self {
+        self.sync = policy;
+        self
+    }
+
+    /// Whether a record of `level` would be written.
+    pub fn enabled(&self, level: Level) -> bool {
+        level >= self.level
+    }
+
+    /// Write a record with the current time and additional `fields`.
+    pub fn log(&mut self, level: Level, message: &str, fields: &[(&str, &str)]) -> io::Result<()> {
+        if !self.enabled(level) {
+            return Ok(());
+        }
+        let ts = Tm::now().rfc3339();
+        let line = match self.format {
+            LogFormat::Json => json_line(&ts, level, message, fields),
+            LogFormat::Logfmt => logfmt_line(&ts, level, message, fields),
+        };
+        self.writer.write_all(line.as_bytes())?;
+        if self.sync.is_enabled() {
+            self.writer.flush()?;
+            self.sync.apply(self.writer.get_ref())?;
+        }
+        Ok(())
+    }
+
+    pub fn trace(&mut self, message: &str) -> io::Result<()> {
+        self.log(Level::Trace, message, &[])
+    }
+
+    pub fn debug(&mut self, message: &str) -> io::Result<()> {
+        self.log(Level::Debug, message, &[])
+    }
+
+    pub fn info(&mut self, message: &str) -> io::Result<()> {
+        self.log(Level::Info, message, &[])
+    }
+
+    pub fn warn(&mut self, message: &str) -> io::Result<()> {
+        self.log(Level::Warn, message, &[])
+    }
+
+    pub fn error(&mut self, message: &str) -> io::Result<()> {
+        self.log(Level::Error, message, &[])
+    }
+
+    /// Write out buffered records.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn json_line(ts: &str, level: Level, message: &str, fields: &[(&str, &str)]) -> String {
+    let mut line = String::new();
+    line.push_str("{\"ts\":");
+    push_json_str(&mut line, ts);
+    line.push_str(",\"level\":");
+    push_json_str(&mut line, level.as_str());
+    line.push_str(",\"msg\":");
+    push_json_str(&mut line, message);
+    for &(k, v) in fields {
+        line.push(',');
+        push_json_str(&mut line, k);
+        line.push(':');
+        push_json_str(&mut line, v);
+    }
+    line.push_str("}\n");
+    line
+}
+
+fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn logfmt_line(ts: &str, level: Level, message: &str, fields: &[(&str, &str)]) -> String {
+    let mut line = String::new();
+    line.push_str("ts=");
+    line.push_str(ts);
+    line.push_str(" level=");
+    line.push_str(level.as_str());
+    line.push_str(" msg=");
+    push_logfmt_value(&mut line, message);
+    for &(k, v) in fields {
+        line.push(' ');
+        line.push_str(k);
+        line.push('=');
+        push_logfmt_value(&mut line, v);
+    }
+    line.push('\n');
+    line
+}
+
+fn push_logfmt_value(out: &mut String, s: &str) {
+    let needs_quotes = s.is_empty() ||
+        s.chars().any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\');
+    if !needs_quotes {
+        out.push_str(s);
+        return;
+    }
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use file::File;
+    use super::*;
+
+    #[test]
+    fn level_filter_and_formats() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("app.log");
+        let mut log = LogFileWriter::new(&path).unwrap().level(Level::Info);
+        log.debug("hidden").unwrap();
+        log.log(Level::Warn, "disk \"low\"", &[("free", "10 MB")]).unwrap();
+        log = log.format(LogFormat::Json);
+        log.log(Level::Error, "boom", &[("code", "42")]).unwrap();
+        log.flush().unwrap();
+
+        let s = File::new(&path).read_string().unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("ts="));
+        assert!(lines[0].ends_with(" level=warn msg=\"disk \\\"low\\\"\" free=\"10 MB\""));
+        assert!(lines[1].starts_with("{\"ts\":\""));
+        assert!(lines[1].ends_with(",\"level\":\"error\",\"msg\":\"boom\",\"code\":\"42\"}"));
+    }
+
+    #[test]
+    fn size_rotation() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("app.log");
+        let mut log = LogFileWriter::new(&path).unwrap().rotate(1, 2).sync(SyncPolicy::Data);
+        log.info("first").unwrap();
+        log.info("second").unwrap();
+        log.info("third").unwrap();
+
+        let read = |name: &str| File::new(tmp_dir.path().join(name)).read_string().unwrap();
+        assert!(read("app.log").contains("msg=third"));
+        assert!(read("app.log.1").contains("msg=second"));
+        assert!(read("app.log.2").contains("msg=first"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::str;
+
+use text::Bom;
+
+/// How many leading bytes of a file `File::detect_type` looks at.
+pub(crate) const SNIFF_LEN: usize = 8 * 1024;
+
+/// `ContentType` is the kind of content a file holds, as told by its
+/// first bytes rather than its extension.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ContentType {
+    Png,
+    Jpeg,
+    Gzip,
+    Zip,
+    Pdf,
+    Utf8Text,
+    Utf16Text,
+    Empty,
+    Binary,
+}
+
+impl ContentType {
+    /// Classify content from its leading bytes.
+    pub fn sniff(bytes: &[u8]) -> ContentType {
+        const SIGNATURES: &[(&[u8], ContentType)] = &[
+            (b"\x89PNG\r\n\x1a\n", ContentType::Png),
+            (b"\xff\xd8\xff", ContentType::Jpeg),
+            (b"\x1f\x8b", ContentType::Gzip),
+            (b"PK\x03\x04", ContentType::Zip),
+            (b"PK\x05\x06", ContentType::Zip),
+            (b"PK\x07\x08", ContentType::Zip),
+            (b"%PDF-", ContentType::Pdf),
+        ];
+        if bytes.is_empty() {
+            return ContentType::Empty;
+        }
+        if let Some(&(_, ty)) = SIGNATURES.iter().find(|s| bytes.starts_with(s.0)) {
+            return ty;
+        }
+        match Bom::detect(bytes) {
+            Some(Bom::Utf8) => return ContentType::Utf8Text,
+            Some(Bom::Utf16Le) | Some(Bom::Utf16Be) => return ContentType::Utf16Text,
+            None => {}
+        }
+        if is_utf8_text(bytes) {
+            ContentType::Utf8Text
+        } else if looks_like_utf16(bytes) {
+            ContentType::Utf16Text
+        } else {
+            ContentType::Binary
+        }
+    }
+
+    /// The MIME type of this content.
+    pub fn mime(&self) -> &'static str {
+        match *self {
+            ContentType::Png => "image/png",
+            ContentType::Jpeg => "image/jpeg",
+            ContentType::Gzip => "application/gzip",
+            ContentType::Zip => "application/zip",
+            ContentType::Pdf => "application/pdf",
+            ContentType::Utf8Text => "text/plain; charset=utf-8",
+            ContentType::Utf16Text => "text/plain; charset=utf-16",
+            ContentType::Empty => "application/x-empty",
+            ContentType::Binary => "application/octet-stream",
+        }
+    }
+
+    /// Whether this is text content.
+    pub fn is_text(&self) -> bool {
+        *self == ContentType::Utf8Text || *self == ContentType::Utf16Text
+    }
+}
+
+/// Valid UTF-8, allowing a character cut at the end of the sample,
+/// without control characters other than usual whitespace and escape.
+fn is_utf8_text(bytes: &[u8]) -> bool {
+    let valid = match str::from_utf8(bytes) {
+        Ok(_) => bytes,
+        Err(e) if e.error_len().is_none() => &bytes[..e.valid_up_to()],
+        Err(_) => return false,
+    };
+    !valid.iter().any(|&b| b < 0x20 && !b"\t\n\r\x0c\x1b".contains(&b))
+}
+
+/// UTF-16 without BOM: mostly ASCII text leaves every other byte zero.
+fn looks_like_utf16(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 {
+        return false;
+    }
+    let zeros_at = |parity: usize| bytes.iter().skip(parity).step_by(2).filter(|&&b| b == 0).count();
+    let half = bytes.len() / 2;
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    (odd * 10 >= half * 9 && even == 0) || (even * 10 >= half * 9 && odd == 0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn sniff() {
+        assert_eq!(ContentType::Png, ContentType::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert_eq!(ContentType::Jpeg, ContentType::sniff(b"\xff\xd8\xff\xe0"));
+        assert_eq!(ContentType::Gzip, ContentType::sniff(b"\x1f\x8b\x08"));
+        assert_eq!(ContentType::Zip, ContentType::sniff(b"PK\x03\x04\x14\0"));
+        assert_eq!(ContentType::Pdf, ContentType::sniff(b"%PDF-1.7\n"));
+        assert_eq!(ContentType::Utf8Text, ContentType::sniff("héllo\tworld\n".as_bytes()));
+        assert_eq!(ContentType::Utf8Text, ContentType::sniff(&"é".as_bytes()[..1]));
+        assert_eq!(ContentType::Utf16Text, ContentType::sniff(b"\xff\xfeh\0i\0"));
+        assert_eq!(ContentType::Utf16Text, ContentType::sniff(b"h\0e\0l\0l\0o\0"));
+        assert_eq!(ContentType::Binary, ContentType::sniff(b"\0\x01\x02\x03\xff"));
+        assert_eq!(ContentType::Empty, ContentType::sniff(b""));
+        assert_eq!("image/png", ContentType::Png.mime());
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use copy::{self, CopyOptions, Overwrite};
+use dir::{self, Dir};
+use file::File;
+use retry::RetryOptions;
+use sys;
+use walk::{Walk, WalkOptions};
+
+/// `Compare` tells how `Dir::sync_to` and `Dir::diff_with` decide that
+/// a file changed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Compare {
+    /// Different size or modification time. Copies get the modification
+    /// time of their source, so unchanged files are skipped on later syncs.
+    #[default]
+    SizeAndModified,
+    /// Different size or content, compared byte for byte. Slower, but exact.
+    Content,
+}
+
+/// `SyncOptions` controls `Dir::sync_to`. By default files are compared by
+/// size and modification time, and nothing is deleted.
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    compare: Compare,
+    delete: bool,
+    dry_run: bool,
+}
+
+/// `SyncReport` lists what `Dir::sync_to` did, or would do for a dry run.
+/// It displays as a one line summary.
+#[derive(Clone, Debug, Default)]
+pub struct SyncReport {
+    /// Files and links of the source missing from the destination.
+    pub copied: Vec<File>,
+    /// Destination files and links which differed from the source.
+    pub updated: Vec<File>,
+    /// Destination entries missing from the source, when deleting.
+    pub deleted: Vec<File>,
+    /// Number of files and links which were already the same.
+    pub unchanged: usize,
+    pub dry_run: bool,
+}
+
+/// `DirDiff` lists the differences between two directory trees found by
+/// `Dir::diff`, as paths relative to the compared directories. A directory
+/// on one side only is listed without its content. It displays as a one
+/// line summary.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DirDiff {
+    /// Entries only in the directory `diff` was called on.
+    pub only_in_left: Vec<PathBuf>,
+    /// Entries only in the other directory.
+    pub only_in_right: Vec<PathBuf>,
+    /// Files and links on both sides which differ, or entries of a
+    /// different type on each side.
+    pub differing: Vec<PathBuf>,
+    /// Number of files and links which are the same on both sides.
+    pub same: usize,
+}
+
+impl SyncOptions {
+    /// Compare by size and modification time, delete nothing.
+    pub fn new() -> Self {
+        SyncOptions::default()
+    }
+
+    /// Set how files are compared.
+    pub fn compare(mut self, compare: Compare) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Delete destination entries which aren't in the source.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Only compute the report, leave the destination untouched.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{} copied, {} updated, {} deleted, {} unchanged",
+            if self.dry_run { "(dry run) " } else { "" },
+            self.copied.len(),
+            self.updated.len(),
+            self.deleted.len(),
+            self.unchanged
+        )
+    }
+}
+
+impl DirDiff {
+    /// Whether both trees have the same content.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_left.is_empty() && self.only_in_right.is_empty() && self.differing.is_empty()
+    }
+}
+
+impl fmt::Display for DirDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} only in left, {} only in right, {} differing, {} same",
+            self.only_in_left.len(),
+            self.only_in_right.len(),
+            self.differing.len(),
+            self.same
+        )
+    }
+}
+
+/// Compare the trees under `left` and `right`.
+pub(crate) fn diff_dirs(left: &Path, right: &Path, compare: Compare) -> io::Result<DirDiff> {
+    let mut diff = DirDiff::default();
+    let mut seen = HashSet::new();
+    // Directories whose content isn't compared, as their counterpart is
+    // missing or not a directory.
+    let mut skipped = HashSet::new();
+    for entry in Walk::new(left, WalkOptions::new()) {
+        let entry = entry?;
+        let rel = entry.file().strip_prefix(left).expect("walked under left").to_path_buf();
+        if is_under(&rel, &skipped) {
+            continue;
+        }
+        seen.insert(rel.clone());
+        let other = right.join(&rel);
+        let meta = match fs::symlink_metadata(&other) {
+            Ok(meta) => meta,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                diff.only_in_left.push(rel.clone());
+                if entry.file_type().is_dir() {
+                    skipped.insert(rel);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if entry.file_type().is_dir() || meta.is_dir() {
+            if entry.file_type() != meta.file_type() {
+                diff.differing.push(rel.clone());
+                skipped.insert(rel);
+            }
+        } else if differs(entry.file(), &other, &meta, compare)? {
+            diff.differing.push(rel);
+        } else {
+            diff.same += 1;
+        }
+    }
+    for entry in Walk::new(right, WalkOptions::new()) {
+        let entry = entry?;
+        let rel = entry.file().strip_prefix(right).expect("walked under right");
+        if seen.contains(rel) || is_under(rel, &skipped) {
+            continue;
+        }
+        diff.only_in_right.push(rel.to_path_buf());
+        if entry.file_type().is_dir() {
+            skipped.insert(rel.to_path_buf());
+        }
+    }
+    Ok(diff)
+}
+
+/// Make the directory `dest` match `src` as `opts` tell.
+pub(crate) fn sync_dir(src: &Path, dest: &Path, opts: &SyncOptions) -> io::Result<SyncReport> {
+    let (real_src, real_dest) = (src.canonicalize()?, copy::canonicalize_nearest(dest)?);
+    if real_dest.starts_with(&real_src) || real_src.starts_with(&real_dest) {
+        let msg = format!("cannot sync {:?} with {:?} which overlaps it", real_src, real_dest);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    let mut report = SyncReport {
+        dry_run: opts.dry_run,
+        ..SyncReport::default()
+    };
+    if !opts.dry_run {
+        fs::create_dir_all(dest)?;
+    }
+    let mut seen = HashSet::new();
+    for entry in Walk::new(src, WalkOptions::new()) {
+        let entry = entry?;
+        let rel = entry.file().strip_prefix(src).expect("walked under src").to_path_buf();
+        let target = dest.join(&rel);
+        seen.insert(rel);
+        let ty = entry.file_type();
+        let target_meta = fs::symlink_metadata(&target).ok();
+        if ty.is_dir() {
+            if !opts.dry_run {
+                match target_meta {
+                    Some(ref meta) if meta.is_dir() => {}
+                    Some(_) => {
+                        remove(&target)?;
+                        fs::create_dir(&target)?;
+                    }
+                    None => fs::create_dir(&target)?,
+                }
+            }
+            continue;
+        }
+        let changed = match target_meta {
+            None => {
+                report.copied.push(File::new(&target));
+                true
+            }
+            Some(ref meta) if differs(entry.file(), &target, meta, opts.compare)? => {
+                report.updated.push(File::new(&target));
+                true
+            }
+            Some(_) => {
+                report.unchanged += 1;
+                false
+            }
+        };
+        if changed && !opts.dry_run {
+            // A file is copied next to the target and renamed over it, so
+            // that a failed copy leaves the previous one in place.
+            let replaced_by_copy = target_meta.as_ref().is_some_and(|m| m.is_file());
+            if target_meta.is_some() && (ty.is_symlink() || !replaced_by_copy) {
+                remove(&target)?;
+            }
+            if ty.is_symlink() {
+                sys::symlink(&fs::read_link(entry.file())?, &target, entry.file().is_dir())?;
+            } else {
+                let mut copy_opts =
+                    CopyOptions::new().overwrite(Overwrite::Always).preserve_times(true);
+                copy::copy_file(entry.file(), &target, &mut copy_opts, 0, 0)?;
+            }
+        }
+    }
+    if opts.delete && fs::symlink_metadata(dest).is_ok() {
+        let mut deleted = HashSet::new();
+        for entry in Walk::new(dest, WalkOptions::new()) {
+            let entry = entry?;
+            let rel = entry.file().strip_prefix(dest).expect("walked under dest");
+            if seen.contains(rel) || is_under(rel, &deleted) {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                deleted.insert(rel.to_path_buf());
+            }
+            report.deleted.push(entry.file().clone());
+        }
+        if !opts.dry_run {
+            for file in &report.deleted {
+                remove(file)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Whether one of the ancestors of `rel` is in `dirs`.
+fn is_under(rel: &Path, dirs: &HashSet<PathBuf>) -> bool {
+    !dirs.is_empty() && rel.ancestors().skip(1).any(|dir| dirs.contains(dir))
+}
+
+/// Whether the file or link `src` differs from `dest` with metadata `meta`.
+fn differs(src: &Path, dest: &Path, meta: &fs::Metadata, compare: Compare) -> io::Result<bool> {
+    let src_meta = fs::symlink_metadata(src)?;
+    if src_meta.file_type() != meta.file_type() {
+        return Ok(true);
+    }
+    if src_meta.file_type().is_symlink() {
+        return Ok(fs::read_link(src)? != fs::read_link(dest)?);
+    }
+    if src_meta.len() != meta.len() {
+        return Ok(true);
+    }
+    match compare {
+        Compare::SizeAndModified => Ok(src_meta.modified()? != meta.modified()?),
+        Compare::Content => Ok(!dir::same_content(src, dest)?),
+    }
+}
+
+/// Remove whatever is at `path`, without following links.
+fn remove(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        Dir::new(path).delete_all(&RetryOptions::new())
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use super::*;
+
+    #[test]
+    fn sync_to() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let src = Dir::new(tmp_dir.path().join("src"));
+        let dest = Dir::new(tmp_dir.path().join("dest"));
+        src.child_dir("a").create().unwrap();
+        src.child("a/one.txt").overwrite(b"one").unwrap();
+        src.child("two.txt").overwrite(b"two").unwrap();
+
+        let report = src.sync_to(&*dest, &SyncOptions::new()).unwrap();
+        assert_eq!("2 copied, 0 updated, 0 deleted, 0 unchanged", &report.to_string());
+        let report = src.sync_to(&*dest, &SyncOptions::new()).unwrap();
+        assert_eq!("0 copied, 0 updated, 0 deleted, 2 unchanged", &report.to_string());
+
+        src.child("two.txt").overwrite(b"TWO").unwrap();
+        dest.child_dir("extra").create().unwrap();
+        dest.child("extra/old.txt").overwrite(b"old").unwrap();
+        let opts = SyncOptions::new().compare(Compare::Content).delete(true);
+        let report = src.sync_to(&*dest, &opts.clone().dry_run(true)).unwrap();
+        assert_eq!("(dry run) 0 copied, 1 updated, 1 deleted, 1 unchanged", &report.to_string());
+        assert_eq!("two", &dest.child("two.txt").read_string().unwrap());
+
+        let report = src.sync_to(&*dest, &opts).unwrap();
+        assert_eq!(vec![dest.child("extra")], report.deleted);
+        assert_eq!("TWO", &dest.child("two.txt").read_string().unwrap());
+        assert!(!dest.child("extra").exists());
+    }
+
+    #[test]
+    fn sync_overlapping() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let parent = Dir::new(tmp_dir.path().join("p"));
+        let src = parent.child_dir("s");
+        src.create().unwrap();
+        src.child("a.txt").overwrite(b"a").unwrap();
+
+        let opts = SyncOptions::new().delete(true);
+        let err = src.sync_to(&*parent, &opts).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        let err = src.sync_to(src.child_dir("backup"), &opts).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!("a", &src.child("a.txt").read_string().unwrap());
+        assert!(!src.child_dir("backup").exists());
+    }
+
+    #[test]
+    fn diff() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let left = Dir::new(tmp_dir.path().join("left"));
+        let right = Dir::new(tmp_dir.path().join("right"));
+        left.child_dir("a/deep").create().unwrap();
+        left.child("a/deep/x.txt").overwrite(b"x").unwrap();
+        left.child("same.txt").overwrite(b"same").unwrap();
+        left.child("changed.txt").overwrite(b"left").unwrap();
+        left.child("kind").overwrite(b"file").unwrap();
+        left.sync_to(&*right, &SyncOptions::new()).unwrap();
+        assert!(left.diff(&*right).unwrap().is_empty());
+
+        left.child("changed.txt").overwrite(b"LEFT").unwrap();
+        fs::remove_file(right.child("kind")).unwrap();
+        right.child_dir("kind").create().unwrap();
+        right.child("kind/inner.txt").overwrite(b"inner").unwrap();
+        right.child_dir("a/deep").delete_all(&RetryOptions::new()).unwrap();
+        right.child_dir("b").create().unwrap();
+        right.child("b/y.txt").overwrite(b"y").unwrap();
+
+        let diff = left.diff_with(&*right, Compare::Content).unwrap();
+        assert_eq!(vec![PathBuf::from("a/deep")], diff.only_in_left);
+        assert_eq!(vec![PathBuf::from("b")], diff.only_in_right);
+        let mut differing = diff.differing.clone();
+        differing.sort();
+        assert_eq!(vec![PathBuf::from("changed.txt"), PathBuf::from("kind")], differing);
+        assert_eq!("1 only in left, 1 only in right, 2 differing, 1 same", &diff.to_string());
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::io;
+use std::ops;
+
+use memmap2::{Mmap, MmapMut};
+
+/// `ReadMap` is a read-only memory map of a whole file, created by
+/// `File::map_read`. It dereferences to the bytes of the file.
+///
+/// The content must not be modified by this or another process while the
+/// map is alive; the operating system doesn't prevent it, and the bytes
+/// seen through the map would change under the reader's feet.
+#[derive(Debug)]
+pub struct ReadMap {
+    map: Mmap,
+}
+
+/// `WriteMap` is a writable memory map of a whole file, created by
+/// `File::map_write`. It dereferences to the bytes of the file, and writes
+/// through it reach the file, at the latest on `flush`.
+///
+/// As with `ReadMap`, the file must not be modified by other means, nor
+/// truncated, while the map is alive.
+#[derive(Debug)]
+pub struct WriteMap {
+    map: MmapMut,
+}
+
+impl ReadMap {
+    pub(crate) fn new(file: &fs::File) -> io::Result<Self> {
+        let map = unsafe { Mmap::map(file)? };
+        Ok(ReadMap { map })
+    }
+}
+
+impl WriteMap {
+    pub(crate) fn new(file: &fs::File) -> io::Result<Self> {
+        let map = unsafe { MmapMut::map_mut(file)? };
+        Ok(WriteMap { map })
+    }
+
+    /// Write modified pages back to the file and wait until they are.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    /// Start writing modified pages back to the file, without waiting.
+    pub fn flush_async(&self) -> io::Result<()> {
+        self.map.flush_async()
+    }
+}
+
+impl ops::Deref for ReadMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl AsRef<[u8]> for ReadMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl ops::Deref for WriteMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl ops::DerefMut for WriteMap {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+}
+
+impl AsRef<[u8]> for WriteMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl AsMut<[u8]> for WriteMap {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+}
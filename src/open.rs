@@ -0,0 +1,392 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use file::{CreateMode, FileOpener, Open, WriteOption};
+use lock::LockMode;
+use retry::{self, RetryOptions};
+use sys;
+
+/// Opener created by `Open::with_mode`.
+#[derive(Clone, Debug)]
+pub struct WithMode<O> {
+    inner: O,
+    mode: u32,
+}
+
+/// Opener created by `Open::then_sync_parent`.
+#[derive(Clone, Debug)]
+pub struct SyncParent<O> {
+    inner: O,
+}
+
+/// Opener created by `Open::fallback`.
+#[derive(Clone, Debug)]
+pub struct Fallback<O, F> {
+    first: O,
+    second: F,
+}
+
+/// Opener created by `Open::retrying`.
+#[derive(Clone, Debug)]
+pub struct Retrying<O> {
+    inner: O,
+    options: RetryOptions,
+    not_found: bool,
+}
+
+/// Opener created by `Open::timeout`.
+#[derive(Clone, Debug)]
+pub struct Timeout<O> {
+    inner: O,
+    timeout: Duration,
+}
+
+/// Opener created by `from_fn`.
+#[derive(Clone)]
+pub struct FromFn<F> {
+    f: F,
+}
+
+/// `OpenSpec` describes how to open files as data, so that it can come
+/// from configuration. With the `serde` feature it (de)serializes tagged
+/// by `mode`, e.g. `{ "mode": "append", "lock": "exclusive" }` in JSON,
+/// with omitted fields taking their defaults.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "mode", rename_all = "snake_case"))]
+pub enum OpenSpec {
+    /// Read an existing file.
+    Read {
+        #[cfg_attr(feature = "serde", serde(default))]
+        lock: Option<LockMode>,
+    },
+    /// Write from the start of the file, created by default.
+    Write {
+        #[cfg_attr(feature = "serde", serde(default = "create_by_default"))]
+        create: CreateMode,
+        /// Discard the content instead of overwriting it in place.
+        #[cfg_attr(feature = "serde", serde(default))]
+        truncate: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        read: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        lock: Option<LockMode>,
+    },
+    /// Write at the end of the file, created by default.
+    Append {
+        #[cfg_attr(feature = "serde", serde(default = "create_by_default"))]
+        create: CreateMode,
+        #[cfg_attr(feature = "serde", serde(default))]
+        read: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        lock: Option<LockMode>,
+    },
+}
+
+#[cfg(feature = "serde")]
+fn create_by_default() -> CreateMode {
+    CreateMode::IfNotExists
+}
+
+impl<O> WithMode<O> {
+    pub(crate) fn new(inner: O, mode: u32) -> Self {
+        WithMode { inner, mode }
+    }
+}
+
+impl<O> SyncParent<O> {
+    pub(crate) fn new(inner: O) -> Self {
+        SyncParent { inner }
+    }
+}
+
+impl<O, F> Fallback<O, F> {
+    pub(crate) fn new(first: O, second: F) -> Self {
+        Fallback { first, second }
+    }
+}
+
+impl<O> Retrying<O> {
+    pub(crate) fn new(inner: O, options: RetryOptions) -> Self {
+        Retrying {
+            inner,
+            options,
+            not_found: false,
+        }
+    }
+
+    /// Also retry when the file doesn't exist, e.g. while a log file is
+    /// being rotated and its replacement isn't created yet.
+    pub fn retry_not_found(mut self, retry: bool) -> Self {
+        self.not_found = retry;
+        self
+    }
+}
+
+impl<O> Timeout<O> {
+    pub(crate) fn new(inner: O, timeout: Duration) -> Self {
+        Timeout { inner, timeout }
+    }
+}
+
+impl OpenSpec {
+    /// The `FileOpener` this specification describes.
+    pub fn to_opener(&self) -> FileOpener {
+        let (opener, lock) = match *self {
+            OpenSpec::Read { lock } => (FileOpener::readonly(), lock),
+            OpenSpec::Write {
+                create,
+                truncate,
+                read,
+                lock,
+            } => {
+                let option = if truncate { WriteOption::Truncate } else { WriteOption::Overwrite };
+                (FileOpener::new().create(create).read(read).write(option), lock)
+            }
+            OpenSpec::Append { create, read, lock } => {
+                (FileOpener::new().create(create).read(read).write(WriteOption::Append), lock)
+            }
+        };
+        match lock {
+            Some(LockMode::Shared) => opener.lock_shared(),
+            Some(LockMode::Exclusive) => opener.lock_exclusive(),
+            None => opener,
+        }
+    }
+}
+
+/// Open files with `f`, for ad-hoc opening logic such as custom flags or
+/// duplicated descriptors.
+pub fn from_fn<F>(f: F) -> FromFn<F>
+where
+    F: Fn(&Path) -> io::Result<fs::File>,
+{
+    FromFn { f }
+}
+
+impl<F> Open for FromFn<F>
+where
+    F: Fn(&Path) -> io::Result<fs::File>,
+{
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        (self.f)(path.as_ref())
+    }
+}
+
+impl<F> fmt::Debug for FromFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FromFn")
+    }
+}
+
+impl Open for OpenSpec {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        self.to_opener().open(path)
+    }
+
+    fn open_options(&self) -> Option<OpenOptions> {
+        self.to_opener().open_options()
+    }
+}
+
+#[cfg(unix)]
+impl<O: Open> Open for WithMode<O> {
+    /// Openers based on `OpenOptions` create the file with the mode directly.
+    /// Others get the mode set right after, if the file didn't exist before.
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let path = path.as_ref();
+        if let Some(mut opts) = self.inner.open_options() {
+            return opts.mode(self.mode).open(path);
+        }
+        let existed = path.exists();
+        let f = self.inner.open(path)?;
+        if !existed {
+            f.set_permissions(fs::Permissions::from_mode(self.mode))?;
+        }
+        Ok(f)
+    }
+
+    fn open_options(&self) -> Option<OpenOptions> {
+        use std::os::unix::fs::OpenOptionsExt;
+        self.inner.open_options().map(|mut opts| {
+            opts.mode(self.mode);
+            opts
+        })
+    }
+}
+
+impl<O: Open> Open for SyncParent<O> {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        let path = path.as_ref();
+        let f = self.inner.open(path)?;
+        sys::sync_parent(path)?;
+        Ok(f)
+    }
+}
+
+impl<O: Open, F: Open> Open for Fallback<O, F> {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        let path = path.as_ref();
+        self.first.open(path).or_else(|_| self.second.open(path))
+    }
+}
+
+/// Retries opening while it fails transiently: busy files, and sharing
+/// or lock violations on Windows, as antivirus scanners cause.
+impl<O: Open> Open for Retrying<O> {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        let path = path.as_ref();
+        let transient = |e: &io::Error| {
+            retry::is_transient(e) || (self.not_found && e.kind() == io::ErrorKind::NotFound)
+        };
+        self.options.run_when(transient, || self.inner.open(path))
+    }
+}
+
+/// Opens on a separate thread and gives up waiting after the timeout.
+/// A hung open can't be cancelled, so its thread is left behind and closes
+/// the file if the open ever completes.
+impl<O: Open + Clone + Send + 'static> Open for Timeout<O> {
+    fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        let path = path.as_ref().to_path_buf();
+        let inner = self.inner.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("good-files-open".to_owned())
+            .spawn(move || {
+                let _ = tx.send(inner.open(&path));
+            })?;
+        match rx.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("opening did not complete within {:?}", self.timeout),
+            )),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(io::Error::other("opening thread panicked"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::io::prelude::*;
+    use self::tempdir::TempDir;
+    use file::{FileOpener, Open};
+
+    #[test]
+    fn fallback_and_sync_parent() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("fallback.txt");
+        assert!(FileOpener::readonly().open(&path).is_err());
+
+        let opener = FileOpener::readonly()
+            .fallback(FileOpener::append_or_create())
+            .then_sync_parent();
+        let mut f = opener.open(&path).unwrap();
+        f.write_all(b"created").unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn retrying() {
+        use std::thread;
+        use std::time::Duration;
+        use retry::RetryOptions;
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("rotated.log");
+        let options = RetryOptions::new().attempts(3).backoff(Duration::from_millis(1));
+        let opener = FileOpener::readonly().retrying(options.clone());
+        assert!(opener.open(&path).is_err());
+
+        let creator = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                FileOpener::truncate().open(&path).unwrap();
+            })
+        };
+        let options = options.attempts(100).max_backoff(Duration::from_millis(5));
+        let opener = FileOpener::readonly().retrying(options).retry_not_found(true);
+        assert!(opener.open(&path).is_ok());
+        creator.join().unwrap();
+    }
+
+    #[test]
+    fn timeout() {
+        use std::path::Path;
+        use std::thread;
+        use std::time::Duration;
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("slow.txt");
+        let opener = FileOpener::append_or_create().timeout(Duration::from_secs(10));
+        assert!(opener.open(&path).is_ok());
+
+        let hanging = super::from_fn(|path: &Path| {
+            thread::sleep(Duration::from_millis(200));
+            FileOpener::readonly().open(path)
+        });
+        let err = hanging.timeout(Duration::from_millis(10)).open(&path).unwrap_err();
+        assert_eq!(::std::io::ErrorKind::TimedOut, err.kind());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn open_spec() {
+        use file::CreateMode;
+        use lock::LockMode;
+        use super::OpenSpec;
+
+        let spec: OpenSpec = ::serde_json::from_str(r#"{"mode": "append", "lock": "shared"}"#)
+            .unwrap();
+        let expected = OpenSpec::Append {
+            create: CreateMode::IfNotExists,
+            read: false,
+            lock: Some(LockMode::Shared),
+        };
+        assert_eq!(expected, spec);
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("spec.log");
+        spec.open(&path).unwrap().write_all(b"a").unwrap();
+        spec.open(&path).unwrap().write_all(b"b").unwrap();
+        let read: OpenSpec = ::serde_json::from_str(r#"{"mode": "read"}"#).unwrap();
+        let mut s = String::new();
+        read.open(&path).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!("ab", &s);
+
+        let fresh = r#"{"mode": "write", "create": "create_new"}"#;
+        let fresh: OpenSpec = ::serde_json::from_str(fresh).unwrap();
+        assert!(fresh.open(&path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("secret.txt");
+        let f = FileOpener::append_or_create().with_mode(0o600).open(&path).unwrap();
+        assert_eq!(0o600, f.metadata().unwrap().permissions().mode() & 0o777);
+
+        let other = tmp_dir.path().join("secret-2.txt");
+        let opener = FileOpener::readonly().fallback(FileOpener::append_or_create());
+        let f = opener.with_mode(0o640).open(&other).unwrap();
+        assert_eq!(0o640, f.metadata().unwrap().permissions().mode() & 0o777);
+    }
+}
@@ -0,0 +1,136 @@
+use std::io;
+use std::io::prelude::*;
+
+/// `ProgressReader` wraps a reader and reports how many bytes have been
+/// read so far, along with the expected total, after every read.
+pub struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    total: u64,
+    callback: F,
+}
+
+/// `ProgressWriter` wraps a writer and reports how many bytes have been
+/// written so far, along with the expected total, after every write.
+pub struct ProgressWriter<W, F> {
+    inner: W,
+    written: u64,
+    total: u64,
+    callback: F,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> ProgressReader<R, F> {
+    /// Wrap `inner`, expected to yield `total` bytes, calling
+    /// `callback(bytes_read, total)` whenever bytes are read.
+    pub fn new(inner: R, total: u64, callback: F) -> Self {
+        ProgressReader {
+            inner,
+            read: 0,
+            total,
+            callback,
+        }
+    }
+
+    /// Number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Unwrap the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.read += n as u64;
+            (self.callback)(self.read, self.total);
+        }
+        Ok(n)
+    }
+}
+
+impl<W: Write, F: FnMut(u64, u64)> ProgressWriter<W, F> {
+    /// Wrap `inner`, expected to be given `total` bytes, calling
+    /// `callback(bytes_written, total)` whenever bytes are written.
+    pub fn new(inner: W, total: u64, callback: F) -> Self {
+        ProgressWriter {
+            inner,
+            written: 0,
+            total,
+            callback,
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Borrow the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, F: FnMut(u64, u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if n > 0 {
+            self.written += n as u64;
+            (self.callback)(self.written, self.total);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn reports_progress() {
+        let mut calls = Vec::new();
+        let mut out = Vec::new();
+        {
+            let mut r = ProgressReader::new(&b"0123456789"[..], 10, |n, total| calls.push((n, total)));
+            let mut buf = [0; 4];
+            while let Ok(n) = r.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                out.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(10, r.bytes_read());
+        }
+        assert_eq!(b"0123456789", &out[..]);
+        assert_eq!(vec![(4, 10), (8, 10), (10, 10)], calls);
+    }
+
+    #[test]
+    fn reports_write_progress() {
+        let mut calls = Vec::new();
+        let out = {
+            let mut w = ProgressWriter::new(Vec::new(), 6, |n, total| calls.push((n, total)));
+            w.write_all(b"abc").unwrap();
+            w.write_all(b"def").unwrap();
+            assert_eq!(6, w.bytes_written());
+            w.into_inner()
+        };
+        assert_eq!(b"abcdef", &out[..]);
+        assert_eq!(vec![(3, 6), (6, 6)], calls);
+    }
+}
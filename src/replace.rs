@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use atomic::AtomicFile;
+use file::File;
+
+/// Files with a NUL byte in this many leading bytes are considered binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Unchanged lines shown around every change in a diff, as `diff -u` does.
+const DIFF_CONTEXT: usize = 3;
+
+/// `Pattern` is what search-and-replace looks for. Matching is done line
+/// by line, so a match never spans a line terminator.
+pub enum Pattern {
+    /// A literal string. An empty string never matches.
+    Literal(String),
+    /// A regular expression, with `$1`/`${name}` references allowed in
+    /// the replacement.
+    #[cfg(feature = "regex")]
+    Regex(Regex),
+}
+
+/// `ReplaceOptions` controls `Dir::replace_in_files`.
+pub struct ReplaceOptions {
+    dry_run: bool,
+    filter: Option<Box<FileFilter>>,
+}
+
+type FileFilter = dyn Fn(&File) -> bool;
+
+/// The replacements made in one file, with a unified diff of the changed
+/// lines.
+#[derive(Clone, Debug)]
+pub struct FileChange {
+    pub file: File,
+    pub replacements: usize,
+    pub diff: String,
+}
+
+/// `ReplaceReport` summarizes a search-and-replace over a tree.
+/// It displays as a one line summary, `diff()` has the details.
+/// Files which couldn't be read or rewritten are listed in `failed`,
+/// and don't stop the other files from being processed.
+#[derive(Debug, Default)]
+pub struct ReplaceReport {
+    pub changes: Vec<FileChange>,
+    pub skipped_binary: Vec<File>,
+    pub failed: Vec<(File, io::Error)>,
+    pub scanned: usize,
+    pub dry_run: bool,
+}
+
+impl Pattern {
+    /// Match `s` literally.
+    pub fn literal<S: Into<String>>(s: S) -> Self {
+        Pattern::Literal(s.into())
+    }
+
+    /// Match a regular expression.
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, ::regex::Error> {
+        Ok(Pattern::Regex(Regex::new(pattern)?))
+    }
+
+    /// Replace every match in `line`, `None` if nothing matches.
+    fn replace_in(&self, line: &str, replacement: &str) -> Option<(String, usize)> {
+        match *self {
+            Pattern::Literal(ref lit) => {
+                if lit.is_empty() {
+                    return None;
+                }
+                match line.matches(lit.as_str()).count() {
+                    0 => None,
+                    n => Some((line.replace(lit.as_str(), replacement), n)),
+                }
+            }
+            #[cfg(feature = "regex")]
+            Pattern::Regex(ref re) => match re.find_iter(line).count() {
+                0 => None,
+                n => Some((re.replace_all(line, replacement).into_owned(), n)),
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Pattern {
+    fn from(s: &'a str) -> Pattern {
+        Pattern::literal(s)
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(s: String) -> Pattern {
+        Pattern::Literal(s)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<Regex> for Pattern {
+    fn from(re: Regex) -> Pattern {
+        Pattern::Regex(re)
+    }
+}
+
+impl ReplaceOptions {
+    /// Rewrite every text file, for real.
+    pub fn new() -> Self {
+        ReplaceOptions {
+            dry_run: false,
+            filter: None,
+        }
+    }
+
+    /// Only compute the report and diffs, leave files untouched.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Only process files for which `filter` returns `true`.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&File) -> bool + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub(crate) fn accepts(&self, file: &File) -> bool {
+        self.filter.as_ref().is_none_or(|f| f(file))
+    }
+}
+
+impl Default for ReplaceOptions {
+    fn default() -> Self {
+        ReplaceOptions::new()
+    }
+}
+
+impl ReplaceReport {
+    /// Total number of replacements over all files.
+    pub fn total_replacements(&self) -> usize {
+        self.changes.iter().map(|c| c.replacements).sum()
+    }
+
+    /// Unified diff of every changed file, with `---`/`+++` headers.
+    pub fn diff(&self) -> String {
+        let mut out = String::new();
+        for change in &self.changes {
+            out.push_str(&format!("--- {0}\n+++ {0}\n", change.file.display()));
+            out.push_str(&change.diff);
+        }
+        out
+    }
+}
+
+impl fmt::Display for ReplaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{} replacements in {} of {} files, {} binary files skipped, {} failed",
+            if self.dry_run { "(dry run) " } else { "" },
+            self.total_replacements(),
+            self.changes.len(),
+            self.scanned,
+            self.skipped_binary.len(),
+            self.failed.len()
+        )
+    }
+}
+
+/// What `replace_stream` found in a text file.
+struct Outcome {
+    replacements: usize,
+    diff: String,
+}
+
+/// Replace in `file` line by line. Without `dry_run`, the file is rewritten
+/// atomically if anything matched. Returns `None` for binary files.
+pub(crate) fn replace_in_file(
+    file: &File,
+    pattern: &Pattern,
+    replacement: &str,
+    dry_run: bool,
+) -> io::Result<Option<FileChange>> {
+    let outcome = match replace_stream(file, pattern, replacement, None)? {
+        Some(outcome) => outcome,
+        None => return Ok(None),
+    };
+    if outcome.replacements > 0 && !dry_run {
+        let mut out = AtomicFile::new(file)?;
+        replace_stream(file, pattern, replacement, Some(&mut out))?;
+        out.commit()?;
+    }
+    Ok(Some(FileChange {
+        file: file.clone(),
+        replacements: outcome.replacements,
+        diff: outcome.diff,
+    }))
+}
+
+/// Replace in `file` line by line, writing the result to `out` if given.
+/// Returns `None` when the file isn't text.
+fn replace_stream(
+    file: &File,
+    pattern: &Pattern,
+    replacement: &str,
+    mut out: Option<&mut AtomicFile>,
+) -> io::Result<Option<Outcome>> {
+    let mut r = file.buf_reader()?;
+    {
+        let head = r.fill_buf()?;
+        if head[..head.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+            return Ok(None);
+        }
+    }
+    let mut replacements = 0;
+    let mut diff = UnifiedDiff::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if r.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = match ::std::str::from_utf8(&buf) {
+            Ok(line) => line,
+            Err(_) => return Ok(None),
+        };
+        let content = line.trim_end_matches(['\n', '\r']);
+        let terminator = &line[content.len()..];
+        let eol = !terminator.is_empty();
+        match pattern.replace_in(content, replacement) {
+            Some((replaced, n)) => {
+                replacements += n;
+                diff.changed(content, &replaced, eol);
+                if let Some(ref mut out) = out {
+                    out.write_all(replaced.as_bytes())?;
+                    out.write_all(terminator.as_bytes())?;
+                }
+            }
+            None => {
+                diff.unchanged(content, eol);
+                if let Some(ref mut out) = out {
+                    out.write_all(line.as_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(Some(Outcome {
+        replacements,
+        diff: diff.finish(),
+    }))
+}
+
+/// Builds the hunks of a unified diff from a stream of unchanged and
+/// changed lines, merging changes whose context overlaps like `diff -u`.
+struct UnifiedDiff {
+    out: String,
+    hunk: Option<Hunk>,
+    /// Unchanged lines not in a hunk yet, which lead the next one.
+    pending: VecDeque<String>,
+    old_line: usize,
+    new_line: usize,
+}
+
+struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+    /// Context lines following the last change.
+    trailing: usize,
+    body: String,
+}
+
+impl UnifiedDiff {
+    fn new() -> Self {
+        UnifiedDiff {
+            out: String::new(),
+            hunk: None,
+            pending: VecDeque::new(),
+            old_line: 0,
+            new_line: 0,
+        }
+    }
+
+    fn unchanged(&mut self, line: &str, eol: bool) {
+        self.old_line += 1;
+        self.new_line += 1;
+        if let Some(ref mut hunk) = self.hunk {
+            if hunk.trailing < DIFF_CONTEXT {
+                hunk.push(' ', line, eol);
+                hunk.old_len += 1;
+                hunk.new_len += 1;
+                hunk.trailing += 1;
+                return;
+            }
+        }
+        self.pending.push_back(diff_line(' ', line, eol));
+        if self.pending.len() > DIFF_CONTEXT {
+            // Too far from the last change to share its hunk.
+            self.flush();
+            self.pending.pop_front();
+        }
+    }
+
+    fn changed(&mut self, old: &str, new: &str, eol: bool) {
+        let old_start = self.old_line + 1 - self.pending.len();
+        let new_start = self.new_line + 1 - self.pending.len();
+        let hunk = self.hunk.get_or_insert_with(|| Hunk {
+            old_start,
+            old_len: 0,
+            new_start,
+            new_len: 0,
+            trailing: 0,
+            body: String::new(),
+        });
+        for line in self.pending.drain(..) {
+            hunk.body.push_str(&line);
+            hunk.old_len += 1;
+            hunk.new_len += 1;
+        }
+        hunk.push('-', old, eol);
+        hunk.old_len += 1;
+        let new_lines = new.split('\n').count();
+        for (i, line) in new.split('\n').enumerate() {
+            hunk.push('+', line, eol || i + 1 < new_lines);
+        }
+        hunk.new_len += new_lines;
+        hunk.trailing = 0;
+        self.old_line += 1;
+        self.new_line += new_lines;
+    }
+
+    fn flush(&mut self) {
+        if let Some(hunk) = self.hunk.take() {
+            self.out.push_str(&format!(
+                "@@ -{} +{} @@\n{}",
+                diff_range(hunk.old_start, hunk.old_len),
+                diff_range(hunk.new_start, hunk.new_len),
+                hunk.body
+            ));
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        self.out
+    }
+}
+
+impl Hunk {
+    fn push(&mut self, tag: char, line: &str, eol: bool) {
+        self.body.push_str(&diff_line(tag, line, eol));
+    }
+}
+
+fn diff_line(tag: char, line: &str, eol: bool) -> String {
+    if eol {
+        format!("{}{}\n", tag, line)
+    } else {
+        format!("{}{}\n\\ No newline at end of file\n", tag, line)
+    }
+}
+
+/// Format a hunk range, omitting a length of one like `diff -u`.
+fn diff_range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn diff(lines: &[(&str, Option<&str>)]) -> String {
+        let mut diff = UnifiedDiff::new();
+        for &(old, new) in lines {
+            match new {
+                Some(new) => diff.changed(old, new, true),
+                None => diff.unchanged(old, true),
+            }
+        }
+        diff.finish()
+    }
+
+    #[test]
+    fn unified_diff() {
+        let mut lines: Vec<(&str, Option<&str>)> = vec![("same", None); 20];
+        lines[1] = ("a", Some("A"));
+        lines[6] = ("b", Some("B1\nB2"));
+        lines[16] = ("c", Some("C"));
+        let expected = "@@ -1,10 +1,11 @@\n same\n-a\n+A\n same\n same\n same\n same\n\
+                        -b\n+B1\n+B2\n same\n same\n same\n\
+                        @@ -14,7 +15,7 @@\n same\n same\n same\n-c\n+C\n same\n same\n same\n";
+        assert_eq!(expected, &diff(&lines));
+
+        let mut last = UnifiedDiff::new();
+        last.changed("x", "y", false);
+        assert_eq!("@@ -1 +1 @@\n-x\n\\ No newline at end of file\n\
+                    +y\n\\ No newline at end of file\n",
+                   &last.finish());
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use file::File;
+use glob;
+
+/// `RetentionPolicy` tells which files `Dir::cleanup` deletes, among the
+/// regular files directly in the directory, optionally only those whose
+/// name matches a pattern. Without `older_than` nor `keep_newest`, nothing
+/// is deleted. With both, only files which are old enough and not among
+/// the newest are deleted.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    older_than: Option<Duration>,
+    keep_newest: Option<usize>,
+    pattern: Option<String>,
+    dry_run: bool,
+}
+
+/// `CleanupReport` lists what `Dir::cleanup` deleted, or would delete for
+/// a dry run. It displays as a one line summary.
+#[derive(Clone, Debug, Default)]
+pub struct CleanupReport {
+    /// Deleted files, newest first.
+    pub deleted: Vec<File>,
+    /// Number of files the policy applied to which were kept.
+    pub kept: usize,
+    pub dry_run: bool,
+}
+
+impl RetentionPolicy {
+    /// A policy which deletes nothing.
+    pub fn new() -> Self {
+        RetentionPolicy::default()
+    }
+
+    /// Delete files last modified longer than `age` ago.
+    pub fn older_than(mut self, age: Duration) -> Self {
+        self.older_than = Some(age);
+        self
+    }
+
+    /// Delete files last modified more than `days` days ago.
+    pub fn older_than_days(self, days: u64) -> Self {
+        self.older_than(Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    /// Keep the `n` most recently modified files.
+    pub fn keep_newest(mut self, n: usize) -> Self {
+        self.keep_newest = Some(n);
+        self
+    }
+
+    /// Only consider files whose name matches `pattern`, such as
+    /// `backup-*.tar`, see `Glob`.
+    pub fn matching<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    /// Only compute the report, leave files untouched.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+impl fmt::Display for CleanupReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{} deleted, {} kept",
+            if self.dry_run { "(dry run) " } else { "" },
+            self.deleted.len(),
+            self.kept
+        )
+    }
+}
+
+/// Apply `policy` to the files in `dir`.
+pub(crate) fn cleanup(dir: &Path, policy: &RetentionPolicy) -> io::Result<CleanupReport> {
+    let matches = match policy.pattern {
+        Some(ref pattern) => Some(glob::name_matcher(pattern)?),
+        None => None,
+    };
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        if let Some(ref matches) = matches {
+            if !matches(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+        }
+        files.push((meta.modified()?, File::from(entry.path())));
+    }
+    // Newest first, ties broken by path so that the result is deterministic.
+    files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let now = SystemTime::now();
+    let cutoff = policy
+        .older_than
+        .map(|age| now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH));
+    let mut report = CleanupReport {
+        dry_run: policy.dry_run,
+        ..CleanupReport::default()
+    };
+    for (i, (modified, file)) in files.into_iter().enumerate() {
+        let protected = policy.keep_newest.is_some_and(|n| i < n);
+        let old = cutoff.is_none_or(|cutoff| modified < cutoff);
+        let applies = policy.keep_newest.is_some() || cutoff.is_some();
+        if applies && !protected && old {
+            if !policy.dry_run {
+                fs::remove_file(&*file)?;
+            }
+            report.deleted.push(file);
+        } else {
+            report.kept += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::time::{Duration, SystemTime};
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use file::{FileOpener, Open};
+    use super::*;
+
+    #[test]
+    fn cleanup() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let day = Duration::from_secs(24 * 60 * 60);
+        for i in 0..5u32 {
+            let file = dir.child(format!("backup-{}.tar", i));
+            file.overwrite(b"").unwrap();
+            let modified = SystemTime::now() - day * (i * 10);
+            FileOpener::appending().open(&*file).unwrap().set_modified(modified).unwrap();
+        }
+        dir.child("notes.txt").overwrite(b"").unwrap();
+        FileOpener::appending()
+            .open(&*dir.child("notes.txt"))
+            .unwrap()
+            .set_modified(SystemTime::now() - day * 100)
+            .unwrap();
+
+        let report = dir.cleanup(&RetentionPolicy::new()).unwrap();
+        assert_eq!("0 deleted, 6 kept", &report.to_string());
+
+        let policy = RetentionPolicy::new().older_than_days(15).dry_run(true);
+        let report = dir.cleanup(&policy).unwrap();
+        assert_eq!("(dry run) 4 deleted, 2 kept", &report.to_string());
+        assert!(dir.child("notes.txt").exists());
+
+        let policy = RetentionPolicy::new().keep_newest(3).older_than_days(25);
+        let report = dir.cleanup(&policy.matching("backup-*.tar")).unwrap();
+        assert_eq!(vec![dir.child("backup-3.tar"), dir.child("backup-4.tar")], report.deleted);
+        assert!(!dir.child("backup-4.tar").exists());
+        assert!(dir.child("notes.txt").exists());
+
+        let report = dir.cleanup(&RetentionPolicy::new().keep_newest(1)).unwrap();
+        assert_eq!(3, report.deleted.len());
+        assert_eq!(vec![dir.child("backup-0.tar")], dir.files().unwrap());
+    }
+}
@@ -0,0 +1,176 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// `RetryOptions` controls how file system operations that fail
+/// transiently are retried, e.g. removing a file that was just closed
+/// on Windows while an antivirus or indexer still holds a handle to it.
+#[derive(Clone, Debug)]
+pub struct RetryOptions {
+    attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    delay_until_reboot: bool,
+}
+
+impl RetryOptions {
+    /// Retry up to 5 times, starting with 10ms backoff doubling up to 1s.
+    pub fn new() -> Self {
+        RetryOptions {
+            attempts: 5,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            delay_until_reboot: false,
+        }
+    }
+
+    /// Never retry, the first failure is returned as is.
+    pub fn never() -> Self {
+        RetryOptions::new().attempts(1)
+    }
+
+    /// Set the total number of attempts, including the first one.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the wait before the first retry. It doubles on every retry.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound of the wait between retries.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// On Windows, when every attempt fails, schedule the operation with
+    /// `MoveFileEx(MOVEFILE_DELAY_UNTIL_REBOOT)` and report
+    /// `Completion::Scheduled`. Ignored on other platforms.
+    pub fn delay_until_reboot(mut self, delay: bool) -> Self {
+        self.delay_until_reboot = delay;
+        self
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn is_delay_until_reboot(&self) -> bool {
+        self.delay_until_reboot
+    }
+
+    /// Run `op` until it succeeds, fails with a non-transient error,
+    /// or attempts are exhausted.
+    pub(crate) fn run<T, F>(&self, op: F) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+    {
+        self.run_when(is_transient, op)
+    }
+
+    /// Like `run`, with `transient` telling which errors are retried.
+    pub(crate) fn run_when<T, F, P>(&self, transient: P, mut op: F) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+        P: Fn(&io::Error) -> bool,
+    {
+        let mut wait = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(ref e) if attempt < self.attempts && transient(e) => {
+                    thread::sleep(wait);
+                    wait = (wait * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        RetryOptions::new()
+    }
+}
+
+/// How an operation run with `RetryOptions` took effect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Completion<T> {
+    /// The operation is done, with its result.
+    Done(T),
+    /// Every attempt failed and the operation was scheduled to run on the
+    /// next reboot, see `RetryOptions::delay_until_reboot`.
+    Scheduled,
+}
+
+impl<T> Completion<T> {
+    /// The result, if the operation is already done.
+    pub fn done(self) -> Option<T> {
+        match self {
+            Completion::Done(value) => Some(value),
+            Completion::Scheduled => None,
+        }
+    }
+
+    /// Whether the operation only takes effect on the next reboot.
+    pub fn is_scheduled(&self) -> bool {
+        match *self {
+            Completion::Done(_) => false,
+            Completion::Scheduled => true,
+        }
+    }
+}
+
+/// Whether `err` is likely to go away if the operation is retried.
+#[cfg(windows)]
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    match err.raw_os_error() {
+        Some(ERROR_ACCESS_DENIED) |
+        Some(ERROR_SHARING_VIOLATION) |
+        Some(ERROR_LOCK_VIOLATION) => true,
+        _ => err.kind() == io::ErrorKind::ResourceBusy,
+    }
+}
+
+/// Whether `err` is likely to go away if the operation is retried.
+#[cfg(not(windows))]
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::ResourceBusy
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io;
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn retries_transient_errors() {
+        let opts = RetryOptions::new().attempts(3).backoff(Duration::from_millis(1));
+        let mut calls = 0;
+        let result = opts.run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::ResourceBusy))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(3, result.unwrap());
+
+        let mut calls = 0;
+        let result: io::Result<()> = opts.run(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(1, calls);
+    }
+}
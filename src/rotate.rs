@@ -0,0 +1,308 @@
+use std::fs;
+use std::io::{self, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use file::{default_buf_capacity, File, FileOpener, Open};
+use sys;
+use time::Tm;
+
+/// `RotatingWriter` appends to a file and, when a write would make it
+/// exceed a size limit, renames it to `name.1` (shifting older files to
+/// `name.2` and so on) and continues in a fresh file.
+///
+/// A single `write` call never spans two files, so records written with
+/// one `write_all` each stay whole. Writes are buffered; call `flush`
+/// to write them out.
+pub struct RotatingWriter {
+    file: File,
+    writer: BufWriter<fs::File>,
+    written: u64,
+    max_size: Option<u64>,
+    keep: usize,
+}
+
+/// `TimedRotatingWriter` appends to a file whose name is derived from the
+/// current UTC time with a pattern such as `app-%Y-%m-%d.log`, switching to
+/// a new file when the name changes, i.e. when the period rolls over.
+///
+/// Supported fields are `%Y` (year), `%m` (month), `%d` (day), `%H` (hour),
+/// `%M` (minute), and `%%` for a literal `%`. As with `RotatingWriter`,
+/// a single `write` call never spans two files.
+pub struct TimedRotatingWriter {
+    pattern: String,
+    file: File,
+    writer: BufWriter<fs::File>,
+    link: Option<PathBuf>,
+}
+
+impl RotatingWriter {
+    /// Open `path` for appending, creating it if needed, to be rotated
+    /// once it exceeds `max_size` bytes, keeping `keep` older files as
+    /// `name.1` (the newest) to `name.<keep>`. With `keep == 0` the file
+    /// is emptied instead.
+    pub fn new<P: AsRef<Path>>(path: P, max_size: u64, keep: usize) -> io::Result<Self> {
+        let mut w = RotatingWriter::unlimited(path)?;
+        w.set_limits(max_size, keep);
+        Ok(w)
+    }
+
+    /// Open `path` for appending, creating it if needed, never rotating
+    /// by size until `set_limits` is called.
+    pub(crate) fn unlimited<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::new(path);
+        let f = FileOpener::append_or_create().open(&*file)?;
+        let written = f.metadata()?.len();
+        Ok(RotatingWriter {
+            file,
+            writer: BufWriter::with_capacity(default_buf_capacity(), f),
+            written,
+            max_size: None,
+            keep: 0,
+        })
+    }
+
+    /// The file currently written to.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Size of the current file, including buffered writes.
+    pub fn len(&self) -> u64 {
+        self.written
+    }
+
+    /// Whether the current file is empty, including buffered writes.
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Borrow the underlying open file, e.g. to sync it after a `flush`.
+    pub fn get_ref(&self) -> &fs::File {
+        self.writer.get_ref()
+    }
+
+    /// Rotate now, regardless of the size of the current file.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.keep == 0 {
+            // Nothing to keep, start over in the file still open for
+            // appending rather than removing it.
+            FileOpener::truncate().open(&*self.file)?;
+        } else {
+            rotate_files(&self.file, self.keep)?;
+            let f = FileOpener::append_or_create().open(&*self.file)?;
+            self.writer = BufWriter::with_capacity(default_buf_capacity(), f);
+        }
+        self.written = 0;
+        Ok(())
+    }
+
+    pub(crate) fn set_limits(&mut self, max_size: u64, keep: usize) {
+        self.max_size = Some(max_size);
+        self.keep = keep;
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let over = self.max_size.is_some_and(|max| {
+            self.written.checked_add(buf.len() as u64).is_none_or(|len| len > max)
+        });
+        if self.written > 0 && over {
+            self.rotate()?;
+        }
+        self.writer.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl TimedRotatingWriter {
+    /// Open the file for the current period, creating it if needed.
+    /// Fails with `InvalidInput` on an unknown `%` field.
+    pub fn new<S: Into<String>>(pattern: S) -> io::Result<Self> {
+        let pattern = pattern.into();
+        let file = File::new(expand(&pattern, &Tm::now())?);
+        let f = FileOpener::append_or_create().open(&*file)?;
+        Ok(TimedRotatingWriter {
+            pattern,
+            file,
+            writer: BufWriter::with_capacity(default_buf_capacity(), f),
+            link: None,
+        })
+    }
+
+    /// Keep a symbolic link at `link`, e.g. `app.log`, pointing to the
+    /// current file. It is replaced atomically on every switch.
+    pub fn symlink<P: AsRef<Path>>(mut self, link: P) -> io::Result<Self> {
+        self.link = Some(link.as_ref().to_path_buf());
+        self.update_link()?;
+        Ok(self)
+    }
+
+    /// The file currently written to.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Borrow the underlying open file, e.g. to sync it after a `flush`.
+    pub fn get_ref(&self) -> &fs::File {
+        self.writer.get_ref()
+    }
+
+    /// Switch files if the current period's name is not the open one.
+    fn roll_over(&mut self, now: &Tm) -> io::Result<()> {
+        let name = expand(&self.pattern, now)?;
+        if Path::new(&name) == &*self.file {
+            return Ok(());
+        }
+        self.writer.flush()?;
+        let file = File::new(name);
+        let f = FileOpener::append_or_create().open(&*file)?;
+        self.writer = BufWriter::with_capacity(default_buf_capacity(), f);
+        self.file = file;
+        self.update_link()
+    }
+
+    fn update_link(&self) -> io::Result<()> {
+        let link = match self.link {
+            Some(ref link) => link,
+            None => return Ok(()),
+        };
+        let target = match (link.parent(), self.file.parent()) {
+            (Some(a), Some(b)) if a == b => Path::new(self.file.file_name().unwrap_or_default()),
+            _ => &*self.file,
+        };
+        let mut tmp = link.as_os_str().to_owned();
+        tmp.push(".tmp-link");
+        let tmp = PathBuf::from(tmp);
+        let _ = fs::remove_file(&tmp);
+        sys::symlink(target, &tmp, false)?;
+        fs::rename(&tmp, link)
+    }
+}
+
+impl Write for TimedRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.roll_over(&Tm::now())?;
+        self.writer.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Expand the `%` fields of `pattern` with `tm`.
+fn expand(pattern: &str, tm: &Tm) -> io::Result<String> {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", tm.year)),
+            Some('m') => out.push_str(&format!("{:02}", tm.month)),
+            Some('d') => out.push_str(&format!("{:02}", tm.day)),
+            Some('H') => out.push_str(&format!("{:02}", tm.hour)),
+            Some('M') => out.push_str(&format!("{:02}", tm.minute)),
+            Some('%') => out.push('%'),
+            other => {
+                let field = other.map(|c| c.to_string()).unwrap_or_default();
+                let msg = format!("unknown field %{} in {}", field, pattern);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Shift `name.1`..`name.<keep - 1>` up by one and move `name` to `name.1`.
+/// `keep` must be at least 1.
+fn rotate_files(file: &File, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| {
+        let mut name = file.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        name
+    };
+    let oldest = numbered(keep);
+    if Path::new(&oldest).exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for n in (1..keep).rev() {
+        let from = numbered(n);
+        if Path::new(&from).exists() {
+            fs::rename(&from, numbered(n + 1))?;
+        }
+    }
+    fs::rename(&**file, numbered(1))
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use super::*;
+
+    #[test]
+    fn rotates_by_size() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("out.txt");
+        let mut w = RotatingWriter::new(&path, 8, 1).unwrap();
+        w.write_all(b"12345\n").unwrap();
+        w.write_all(b"67890\n").unwrap();
+        w.write_all(b"abcde\n").unwrap();
+        w.flush().unwrap();
+        assert_eq!(6, w.len());
+
+        let read = |name: &str| File::new(tmp_dir.path().join(name)).read_string().unwrap();
+        assert_eq!("abcde\n", &read("out.txt"));
+        assert_eq!("67890\n", &read("out.txt.1"));
+        assert!(!tmp_dir.path().join("out.txt.2").exists());
+    }
+
+    #[test]
+    fn rotates_without_keeping() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let path = tmp_dir.path().join("out.txt");
+        let mut w = RotatingWriter::new(&path, 8, 0).unwrap();
+        w.write_all(b"12345\n").unwrap();
+        w.write_all(b"67890\n").unwrap();
+        w.flush().unwrap();
+        assert_eq!("67890\n", &File::new(&path).read_string().unwrap());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn rotates_by_time() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let pattern = tmp_dir.path().join("app-%Y-%m-%d-%H.log").to_string_lossy().into_owned();
+        let link = tmp_dir.path().join("app.log");
+        let mut w = TimedRotatingWriter::new(pattern.as_str()).unwrap().symlink(&link).unwrap();
+        w.write_all(b"now\n").unwrap();
+        w.flush().unwrap();
+        assert_eq!("now\n", &File::new(&link).read_string().unwrap());
+
+        let current = w.file().clone();
+        w.roll_over(&Tm::from_unix(1_700_000_000, 0)).unwrap();
+        assert!(w.file().ends_with("app-2023-11-14-22.log"));
+        assert!(fs::read_link(&link).unwrap().ends_with("app-2023-11-14-22.log"));
+        w.write_all(b"back\n").unwrap();
+        w.flush().unwrap();
+        assert_eq!(&current, w.file());
+        assert_eq!("now\nback\n", &File::new(&link).read_string().unwrap());
+
+        let bad = TimedRotatingWriter::new("app-%Q.log").err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, bad.kind());
+    }
+}
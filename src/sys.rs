@@ -0,0 +1,659 @@
+//! Platform specific helpers which `std` does not expose.
+
+use std::fs;
+use std::io;
+#[cfg(not(unix))]
+use std::path::PathBuf;
+use std::path::Path;
+
+/// Sync the directory containing `path`, making a rename or creation
+/// of `path` durable.
+#[cfg(unix)]
+pub fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent)?.sync_all()
+}
+
+/// Directories can't be synced through `std` on this platform.
+#[cfg(not(unix))]
+pub fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Identity of a file, the same for every path leading to it.
+#[cfg(unix)]
+pub type FileKey = (u64, u64);
+
+/// Identity of a file, the same for every path leading to it.
+#[cfg(not(unix))]
+pub type FileKey = PathBuf;
+
+/// The identity of the file `path` leads to, following symbolic links:
+/// its device and inode numbers.
+#[cfg(unix)]
+pub fn file_key(path: &Path) -> io::Result<FileKey> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = fs::metadata(path)?;
+    Ok((meta.dev(), meta.ino()))
+}
+
+/// The identity of the file `path` leads to, following symbolic links:
+/// its canonical path, as `std` has no stable file index here.
+#[cfg(not(unix))]
+pub fn file_key(path: &Path) -> io::Result<FileKey> {
+    path.canonicalize()
+}
+
+/// Read from `f` at `offset` without moving a shared cursor where possible.
+#[cfg(unix)]
+pub fn read_at(f: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    f.read_at(buf, offset)
+}
+
+/// Write to `f` at `offset` without moving a shared cursor where possible.
+#[cfg(unix)]
+pub fn write_at(f: &fs::File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    f.write_at(buf, offset)
+}
+
+#[cfg(windows)]
+pub fn read_at(f: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    f.seek_read(buf, offset)
+}
+
+#[cfg(windows)]
+pub fn write_at(f: &fs::File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    f.seek_write(buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn read_at(mut f: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::io::{Read, Seek, SeekFrom};
+    f.seek(SeekFrom::Start(offset))?;
+    f.read(buf)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn write_at(mut f: &fs::File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    use std::io::{Seek, SeekFrom, Write};
+    f.seek(SeekFrom::Start(offset))?;
+    f.write(buf)
+}
+
+/// Write all of `buf` to `f` at `offset`, going on after short writes.
+pub fn write_all_at(f: &fs::File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match write_at(f, buf, offset) {
+            Ok(0) => {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer"))
+            }
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Create a symbolic link at `link` pointing to `target`. Windows tells
+/// links to directories apart, as `dir` does.
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path, _dir: bool) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path, dir: bool) -> io::Result<()> {
+    if dir {
+        ::std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        ::std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn symlink(_target: &Path, _link: &Path, _dir: bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links are not supported"))
+}
+
+/// Reserve disk space for the first `len` bytes of `f`, growing it if needed.
+// `posix_fallocate` takes a 64-bit `off_t` there, and the errno values
+// below are those of x86 and arm.
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn preallocate(f: &fs::File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    const EINTR: i32 = 4;
+    const EOPNOTSUPP: i32 = 95;
+
+    extern "C" {
+        fn posix_fallocate(fd: i32, offset: i64, len: i64) -> i32;
+    }
+
+    if len == 0 {
+        return Ok(());
+    }
+    if len > i64::MAX as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "length too large"));
+    }
+    loop {
+        match unsafe { posix_fallocate(f.as_raw_fd(), 0, len as i64) } {
+            0 => return Ok(()),
+            EINTR => continue,
+            EOPNOTSUPP => return fill_zeros(f, len),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+/// Extending a file with `SetEndOfFile` allocates its clusters on NTFS.
+#[cfg(windows)]
+pub fn preallocate(f: &fs::File, len: u64) -> io::Result<()> {
+    if f.metadata()?.len() < len {
+        f.set_len(len)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")),
+    windows
+)))]
+pub fn preallocate(f: &fs::File, len: u64) -> io::Result<()> {
+    fill_zeros(f, len)
+}
+
+/// Write zeros from the end of `f` up to `len`, so that the file system
+/// has to allocate the space, unlike with a sparse `set_len`.
+#[cfg(not(windows))]
+fn fill_zeros(f: &fs::File, len: u64) -> io::Result<()> {
+    let zeros = [0u8; 64 * 1024];
+    let mut pos = f.metadata()?.len();
+    while pos < len {
+        let n = (len - pos).min(zeros.len() as u64) as usize;
+        write_all_at(f, &zeros[..n], pos)?;
+        pos += n as u64;
+    }
+    Ok(())
+}
+
+/// `open(2)` flag bypassing the page cache, where it has a known value.
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+pub const O_DIRECT: Option<i32> = Some(0o40_000);
+
+#[cfg(all(target_os = "linux", any(target_arch = "arm", target_arch = "aarch64")))]
+pub const O_DIRECT: Option<i32> = Some(0o200_000);
+
+#[cfg(all(
+    unix,
+    not(all(
+        target_os = "linux",
+        any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "arm",
+            target_arch = "aarch64"
+        )
+    ))
+))]
+pub const O_DIRECT: Option<i32> = None;
+
+/// `open(2)` flag making the open, and later reads and writes, non-blocking.
+#[cfg(any(
+    all(
+        target_os = "linux",
+        not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc64"))
+    ),
+    target_os = "android"
+))]
+pub const O_NONBLOCK: Option<i32> = Some(0o4_000);
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+pub const O_NONBLOCK: Option<i32> = Some(0x4);
+
+#[cfg(all(
+    unix,
+    not(any(
+        all(
+            target_os = "linux",
+            not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc64"))
+        ),
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly"
+    ))
+))]
+pub const O_NONBLOCK: Option<i32> = None;
+
+/// `open(2)` flag failing the open if the path is a symbolic link.
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+pub const O_NOFOLLOW: Option<i32> = Some(0o400_000);
+
+#[cfg(all(target_os = "linux", any(target_arch = "arm", target_arch = "aarch64")))]
+pub const O_NOFOLLOW: Option<i32> = Some(0o100_000);
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+pub const O_NOFOLLOW: Option<i32> = Some(0x100);
+
+#[cfg(all(
+    unix,
+    not(any(
+        all(
+            target_os = "linux",
+            any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "arm",
+                target_arch = "aarch64"
+            )
+        ),
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly"
+    ))
+))]
+pub const O_NOFOLLOW: Option<i32> = None;
+
+/// `CreateFileW` flag bypassing the system cache.
+#[cfg(windows)]
+pub const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+
+/// Turn caching off on an open file, for platforms without `O_DIRECT`.
+#[cfg(target_os = "macos")]
+pub fn disable_cache(f: &fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    const F_NOCACHE: i32 = 48;
+
+    extern "C" {
+        fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    }
+
+    if unsafe { fcntl(f.as_raw_fd(), F_NOCACHE, 1) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Open an unnamed file in `dir` with `O_TMPFILE`, `None` where the kernel
+/// or the file system doesn't support it.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+))]
+pub fn open_tmpfile(dir: &Path) -> io::Result<Option<fs::File>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    const O_TMPFILE: i32 = 0o20_200_000;
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    const O_TMPFILE: i32 = 0o20_040_000;
+    const EISDIR: i32 = 21;
+    const EINVAL: i32 = 22;
+    const EOPNOTSUPP: i32 = 95;
+
+    let opened = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .mode(0o600)
+        .custom_flags(O_TMPFILE)
+        .open(dir);
+    match opened {
+        Ok(f) => Ok(Some(f)),
+        Err(e) => match e.raw_os_error() {
+            Some(EISDIR) | Some(EINVAL) | Some(EOPNOTSUPP) => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+)))]
+pub fn open_tmpfile(_dir: &Path) -> io::Result<Option<fs::File>> {
+    Ok(None)
+}
+
+/// `ioctl(2)` request cloning a file, where it has a known value.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+))]
+const FICLONE: Option<::std::os::raw::c_ulong> = Some(0x4004_9409);
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64"
+    ))
+))]
+const FICLONE: Option<::std::os::raw::c_ulong> = None;
+
+/// Clone the data of the file `src` to `dest`, created or truncated, if
+/// the filesystem supports copy-on-write clones. Returns `false` when it
+/// doesn't. A link at `src` is followed.
+#[cfg(target_os = "linux")]
+pub fn reflink(src: &Path, dest: &Path) -> io::Result<bool> {
+    use std::os::raw::c_ulong;
+    use std::os::unix::io::AsRawFd;
+
+    const EXDEV: i32 = 18;
+    const EINVAL: i32 = 22;
+    const ENOTTY: i32 = 25;
+    const EOPNOTSUPP: i32 = 95;
+
+    extern "C" {
+        fn ioctl(fd: i32, request: c_ulong, ...) -> i32;
+    }
+
+    let ficlone = match FICLONE {
+        Some(ficlone) => ficlone,
+        None => return Ok(false),
+    };
+    let r = fs::File::open(src)?;
+    let w = fs::OpenOptions::new().write(true).create(true).truncate(true).open(dest)?;
+    if unsafe { ioctl(w.as_raw_fd(), ficlone, r.as_raw_fd()) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(EXDEV) | Some(EINVAL) | Some(ENOTTY) | Some(EOPNOTSUPP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn reflink(src: &Path, dest: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+
+    const EXDEV: i32 = 18;
+    const ENOTSUP: i32 = 45;
+
+    extern "C" {
+        fn clonefile(src: *const c_char, dst: *const c_char, flags: u32) -> i32;
+    }
+
+    // `clonefile` creates the destination itself.
+    if let Err(e) = fs::remove_file(dest) {
+        if e.kind() != io::ErrorKind::NotFound {
+            return Err(e);
+        }
+    }
+    let from = CString::new(src.as_os_str().as_bytes())?;
+    let to = CString::new(dest.as_os_str().as_bytes())?;
+    // No `CLONE_NOFOLLOW`: like the Linux version this clones the target
+    // of a link, links are copied as links elsewhere.
+    if unsafe { clonefile(from.as_ptr(), to.as_ptr(), 0) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(EXDEV) | Some(ENOTSUP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn reflink(_src: &Path, _dest: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Copy up to `len` bytes from the current offset of `r` to the current
+/// offset of `w` within the kernel, with `copy_file_range` or `sendfile`.
+/// Returns `None` when neither can copy between these files.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+))]
+pub fn copy_range(r: &fs::File, w: &fs::File, len: usize) -> io::Result<Option<usize>> {
+    use std::os::raw::c_long;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    const EPERM: i32 = 1;
+    const EINTR: i32 = 4;
+    const EXDEV: i32 = 18;
+    const EINVAL: i32 = 22;
+    const ENOSYS: i32 = 38;
+    const EOPNOTSUPP: i32 = 95;
+
+    // Called through `syscall`, glibc only has a wrapper since 2.27.
+    #[cfg(target_arch = "x86")]
+    const SYS_COPY_FILE_RANGE: c_long = 377;
+    #[cfg(target_arch = "x86_64")]
+    const SYS_COPY_FILE_RANGE: c_long = 326;
+    #[cfg(target_arch = "arm")]
+    const SYS_COPY_FILE_RANGE: c_long = 391;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_COPY_FILE_RANGE: c_long = 285;
+
+    extern "C" {
+        fn syscall(num: c_long, ...) -> c_long;
+        fn sendfile(out_fd: i32, in_fd: i32, offset: *mut i64, count: usize) -> isize;
+    }
+
+    let (r, w) = (r.as_raw_fd(), w.as_raw_fd());
+    loop {
+        let n = unsafe {
+            syscall(
+                SYS_COPY_FILE_RANGE,
+                r,
+                ptr::null_mut::<i64>(),
+                w,
+                ptr::null_mut::<i64>(),
+                len,
+                0u32,
+            )
+        };
+        if n >= 0 {
+            return Ok(Some(n as usize));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(EINTR) => continue,
+            // Old kernels, filesystems or sandboxes without it.
+            Some(EPERM) | Some(EXDEV) | Some(EINVAL) | Some(ENOSYS) | Some(EOPNOTSUPP) => break,
+            _ => return Err(err),
+        }
+    }
+    loop {
+        let n = unsafe { sendfile(w, r, ptr::null_mut(), len) };
+        if n >= 0 {
+            return Ok(Some(n as usize));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(EINTR) => continue,
+            Some(EINVAL) | Some(ENOSYS) => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+)))]
+pub fn copy_range(_r: &fs::File, _w: &fs::File, _len: usize) -> io::Result<Option<usize>> {
+    Ok(None)
+}
+
+/// Copy the extended attributes of `src` to `dest`, without following
+/// links. Attributes which can't be read or set, such as `security.*` ones
+/// without privileges, are skipped. Fails if the list of attributes can't
+/// be read, for example on filesystems without them.
+#[cfg(target_os = "linux")]
+pub fn copy_xattrs(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_void};
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    extern "C" {
+        fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        fn lgetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+        fn lsetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: i32,
+        ) -> i32;
+    }
+
+    let from = CString::new(src.as_os_str().as_bytes())?;
+    let to = CString::new(dest.as_os_str().as_bytes())?;
+    let size = unsafe { llistxattr(from.as_ptr(), ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe { llistxattr(from.as_ptr(), names.as_mut_ptr() as *mut c_char, names.len()) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    names.truncate(size as usize);
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name)?;
+        let len = unsafe { lgetxattr(from.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if len < 0 {
+            continue;
+        }
+        let mut value = vec![0u8; len as usize];
+        let len = unsafe {
+            lgetxattr(from.as_ptr(), name.as_ptr(), value.as_mut_ptr() as *mut c_void, value.len())
+        };
+        if len < 0 {
+            continue;
+        }
+        unsafe {
+            lsetxattr(to.as_ptr(), name.as_ptr(), value.as_ptr() as *const c_void, len as usize, 0);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn copy_xattrs(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Give a name to a file opened by `open_tmpfile`.
+#[cfg(target_os = "linux")]
+pub fn link_tmpfile(f: &fs::File, path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    const AT_FDCWD: i32 = -100;
+    const AT_SYMLINK_FOLLOW: i32 = 0x400;
+
+    extern "C" {
+        fn linkat(
+            olddirfd: i32,
+            oldpath: *const c_char,
+            newdirfd: i32,
+            newpath: *const c_char,
+            flags: i32,
+        ) -> i32;
+    }
+
+    let from = CString::new(format!("/proc/self/fd/{}", f.as_raw_fd()))?;
+    let to = CString::new(path.as_os_str().as_bytes())?;
+    let ret = unsafe { linkat(AT_FDCWD, from.as_ptr(), AT_FDCWD, to.as_ptr(), AT_SYMLINK_FOLLOW) };
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+pub use self::windows::*;
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+    const MOVEFILE_DELAY_UNTIL_REBOOT: u32 = 0x4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    /// Ask Windows to remove `path` on next reboot.
+    pub fn remove_on_reboot(path: &Path) -> io::Result<()> {
+        let from = wide(path);
+        let ok = unsafe { MoveFileExW(from.as_ptr(), ptr::null(), MOVEFILE_DELAY_UNTIL_REBOOT) };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Ask Windows to rename `from` to `to` on next reboot.
+    pub fn rename_on_reboot(from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (wide(from), wide(to));
+        let flags = MOVEFILE_DELAY_UNTIL_REBOOT | MOVEFILE_REPLACE_EXISTING;
+        let ok = unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), flags) };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::io;
+use std::io::prelude::*;
+
+/// `TeePolicy` decides what a `TeeWriter` does when a destination fails.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TeePolicy {
+    /// Fail the write as soon as one destination fails.
+    FailFast,
+    /// Drop the failing destination, keep its error, and go on with the
+    /// others. Writes fail only once every destination has failed.
+    BestEffort,
+}
+
+/// `TeeWriter` duplicates every write to several destinations, such as
+/// files and standard output.
+pub struct TeeWriter {
+    outputs: Vec<Option<Box<dyn Write>>>,
+    policy: TeePolicy,
+    errors: Vec<(usize, io::Error)>,
+}
+
+impl TeeWriter {
+    /// Create a writer without destinations, failing fast.
+    pub fn new() -> Self {
+        TeeWriter {
+            outputs: Vec::new(),
+            policy: TeePolicy::FailFast,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Add a destination.
+    pub fn with<W: Write + 'static>(mut self, w: W) -> Self {
+        self.outputs.push(Some(Box::new(w)));
+        self
+    }
+
+    /// Set what happens when a destination fails.
+    pub fn policy(mut self, policy: TeePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Errors of the destinations dropped under `TeePolicy::BestEffort`,
+    /// with their position in the order they were added.
+    pub fn errors(&self) -> &[(usize, io::Error)] {
+        &self.errors
+    }
+
+    /// Apply `op` to every remaining destination according to the policy.
+    fn each<F>(&mut self, mut op: F) -> io::Result<()>
+    where
+        F: FnMut(&mut dyn Write) -> io::Result<()>,
+    {
+        for (i, slot) in self.outputs.iter_mut().enumerate() {
+            let result = match *slot {
+                Some(ref mut w) => op(&mut **w),
+                None => continue,
+            };
+            if let Err(e) = result {
+                if self.policy == TeePolicy::FailFast {
+                    return Err(e);
+                }
+                *slot = None;
+                self.errors.push((i, e));
+            }
+        }
+        match self.errors.last() {
+            Some((_, e)) if self.outputs.iter().all(Option::is_none) => {
+                Err(io::Error::new(e.kind(), "every destination failed"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for TeeWriter {
+    fn default() -> Self {
+        TeeWriter::new()
+    }
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.each(|w| w.write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.each(|w| w.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let out = Shared::default();
+        let mut tee = TeeWriter::new().with(Broken).with(out.clone());
+        assert!(tee.write_all(b"lost").is_err());
+
+        let mut tee = tee.policy(TeePolicy::BestEffort);
+        tee.write_all(b"kept").unwrap();
+        tee.write_all(b" too").unwrap();
+        assert_eq!(1, tee.errors().len());
+        assert_eq!(0, tee.errors()[0].0);
+        assert_eq!(b"kept too", &out.0.borrow()[..]);
+
+        let mut all_broken = TeeWriter::new().with(Broken).policy(TeePolicy::BestEffort);
+        assert!(all_broken.write_all(b"x").is_err());
+    }
+}
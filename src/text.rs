@@ -0,0 +1,393 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::io;
+#[cfg(feature = "encoding")]
+use std::io::prelude::*;
+
+#[cfg(feature = "encoding")]
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Size of the buffers used while transcoding.
+#[cfg(feature = "encoding")]
+const TRANSCODE_BUF_SIZE: usize = 16 * 1024;
+
+/// `Bom` is the byte order mark a text file may start with.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Byte order of UTF-16 text.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// `LineEnding` is the terminator written after each line.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows.
+    CrLf,
+}
+
+impl Bom {
+    /// Detect the BOM at the start of `bytes`.
+    pub fn detect(bytes: &[u8]) -> Option<Bom> {
+        [Bom::Utf8, Bom::Utf16Le, Bom::Utf16Be]
+            .iter()
+            .cloned()
+            .find(|bom| bytes.starts_with(bom.as_bytes()))
+    }
+
+    /// The bytes of this BOM.
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match *self {
+            Bom::Utf8 => b"\xef\xbb\xbf",
+            Bom::Utf16Le => b"\xff\xfe",
+            Bom::Utf16Be => b"\xfe\xff",
+        }
+    }
+
+    /// The BOM of `encoding`, for the Unicode encodings.
+    #[cfg(feature = "encoding")]
+    pub fn for_encoding(encoding: &'static Encoding) -> Option<Bom> {
+        if encoding == UTF_8 {
+            Some(Bom::Utf8)
+        } else if encoding == UTF_16LE {
+            Some(Bom::Utf16Le)
+        } else if encoding == UTF_16BE {
+            Some(Bom::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// The BOM of UTF-16 in the given byte order.
+    pub fn utf16(endian: Endian) -> Bom {
+        match endian {
+            Endian::Little => Bom::Utf16Le,
+            Endian::Big => Bom::Utf16Be,
+        }
+    }
+
+    /// Decode `bytes`, which start with this BOM, into a string without it.
+    pub(crate) fn decode(&self, bytes: &[u8]) -> io::Result<String> {
+        let bytes = &bytes[self.as_bytes().len()..];
+        match *self {
+            Bom::Utf8 => String::from_utf8(bytes.to_vec()).map_err(invalid_data),
+            Bom::Utf16Le => decode_utf16(bytes, Endian::Little),
+            Bom::Utf16Be => decode_utf16(bytes, Endian::Big),
+        }
+    }
+}
+
+impl LineEnding {
+    /// The line ending of the platform this is compiled for.
+    pub fn native() -> LineEnding {
+        if cfg!(windows) {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// Replace every line break of `s`, be it `\n`, `\r\n` or a lone `\r`,
+    /// with this line ending. Borrows `s` when it is already normalized.
+    pub fn normalize<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let normalized = match *self {
+            LineEnding::Lf => !s.contains('\r'),
+            LineEnding::CrLf => {
+                let bytes = s.as_bytes();
+                bytes.iter().enumerate().all(|(i, &b)| match b {
+                    b'\r' => bytes.get(i + 1) == Some(&b'\n'),
+                    b'\n' => i > 0 && bytes[i - 1] == b'\r',
+                    _ => true,
+                })
+            }
+        };
+        if normalized {
+            return Cow::Borrowed(s);
+        }
+        let mut out = String::with_capacity(s.len() + s.len() / 32);
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => {
+                    if chars.peek() == Some(&'\n') {
+                        chars.next();
+                    }
+                    out.push_str(self.as_str());
+                }
+                '\n' => out.push_str(self.as_str()),
+                c => out.push(c),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// `ErrorPolicy` decides what happens to input which can't be decoded,
+/// or characters the output encoding can't represent.
+#[cfg(feature = "encoding")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// Fail with an `InvalidData` error.
+    Strict,
+    /// Decode malformed input as U+FFFD, encode unmappable characters as `?`.
+    /// Malformed input becomes `?` too when U+FFFD is unmappable.
+    Replace,
+    /// Drop them silently.
+    Skip,
+}
+
+/// `TranscodeOptions` controls `File::transcode_to`.
+#[cfg(feature = "encoding")]
+#[derive(Clone, Debug)]
+pub struct TranscodeOptions {
+    errors: ErrorPolicy,
+    bom: bool,
+}
+
+#[cfg(feature = "encoding")]
+impl TranscodeOptions {
+    /// Strict conversion, without BOM in the output.
+    pub fn new() -> Self {
+        TranscodeOptions {
+            errors: ErrorPolicy::Strict,
+            bom: false,
+        }
+    }
+
+    /// Set how conversion errors are handled.
+    pub fn errors(mut self, errors: ErrorPolicy) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Start the output with a BOM, for UTF-8 and UTF-16 outputs.
+    pub fn bom(mut self, bom: bool) -> Self {
+        self.bom = bom;
+        self
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        TranscodeOptions::new()
+    }
+}
+
+/// Stream `r` in encoding `from` to `w` in encoding `to`, in constant memory.
+/// A BOM of `from` at the start of the input is dropped. Returns how many
+/// errors were replaced or skipped.
+#[cfg(feature = "encoding")]
+pub(crate) fn transcode<R: Read, W: Write>(
+    mut r: R,
+    mut w: W,
+    from: &'static Encoding,
+    to: &'static Encoding,
+    opts: &TranscodeOptions,
+) -> io::Result<usize> {
+    if opts.bom {
+        if let Some(bom) = Bom::for_encoding(to) {
+            w.write_all(bom.as_bytes())?;
+        }
+    }
+    // Malformed input is replaced with a character `to` can encode, so
+    // that it isn't counted a second time as unmappable.
+    let replacement = match to.encode("\u{fffd}") {
+        (_, _, false) => '\u{fffd}',
+        (_, _, true) => '?',
+    };
+    let mut decoder = from.new_decoder_with_bom_removal();
+    let mut encoder = to.new_encoder();
+    let mut input = vec![0; TRANSCODE_BUF_SIZE];
+    let mut text = String::with_capacity(TRANSCODE_BUF_SIZE);
+    let mut output = Vec::with_capacity(TRANSCODE_BUF_SIZE);
+    let mut errors = 0;
+    loop {
+        let n = r.read(&mut input)?;
+        let last = n == 0;
+        let mut src = &input[..n];
+        loop {
+            let (result, read) = decoder.decode_to_string_without_replacement(src, &mut text, last);
+            src = &src[read..];
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(_, _) => {
+                    errors += 1;
+                    match opts.errors {
+                        ErrorPolicy::Strict => {
+                            return Err(invalid_data(format!("malformed {} input", from.name())))
+                        }
+                        ErrorPolicy::Replace => text.push(replacement),
+                        ErrorPolicy::Skip => {}
+                    }
+                }
+            }
+            if text.capacity() - text.len() < 4 {
+                errors += encode(to, &mut encoder, &text, &mut output, &mut w, false, opts.errors)?;
+                text.clear();
+            }
+        }
+        errors += encode(to, &mut encoder, &text, &mut output, &mut w, last, opts.errors)?;
+        text.clear();
+        if last {
+            return w.flush().map(|_| errors);
+        }
+    }
+}
+
+/// Encode `text` and write it to `w`, returning the number of unmappable
+/// characters replaced or skipped.
+#[cfg(feature = "encoding")]
+fn encode<W: Write>(
+    to: &'static Encoding,
+    encoder: &mut ::encoding_rs::Encoder,
+    mut text: &str,
+    output: &mut Vec<u8>,
+    w: &mut W,
+    last: bool,
+    policy: ErrorPolicy,
+) -> io::Result<usize> {
+    // encoding_rs deliberately can't encode into UTF-16, its encoders
+    // for UTF-16 produce UTF-8
+    if to == UTF_16LE || to == UTF_16BE {
+        let endian = if to == UTF_16LE { Endian::Little } else { Endian::Big };
+        encode_utf16(text, endian, output);
+        w.write_all(output)?;
+        output.clear();
+        return Ok(0);
+    }
+    let mut errors = 0;
+    loop {
+        let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(text, output, last);
+        text = &text[read..];
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {
+                w.write_all(output)?;
+                output.clear();
+            }
+            EncoderResult::Unmappable(c) => {
+                errors += 1;
+                match policy {
+                    ErrorPolicy::Strict => {
+                        let msg = format!("{:?} can't be encoded in {}", c, to.name());
+                        return Err(invalid_data(msg));
+                    }
+                    ErrorPolicy::Replace => output.push(b'?'),
+                    ErrorPolicy::Skip => {}
+                }
+            }
+        }
+        if output.capacity() - output.len() < 16 {
+            w.write_all(output)?;
+            output.clear();
+        }
+    }
+    w.write_all(output)?;
+    output.clear();
+    Ok(errors)
+}
+
+/// Decode UTF-16 `bytes` without BOM, in the given byte order.
+pub(crate) fn decode_utf16(bytes: &[u8], endian: Endian) -> io::Result<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(invalid_data("UTF-16 input of odd length"));
+    }
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|b| match endian {
+            Endian::Little => u16::from_le_bytes([b[0], b[1]]),
+            Endian::Big => u16::from_be_bytes([b[0], b[1]]),
+        })
+        .collect();
+    String::from_utf16(&units).map_err(invalid_data)
+}
+
+/// Append `s` encoded as UTF-16 in the given byte order to `out`, without BOM.
+pub(crate) fn encode_utf16(s: &str, endian: Endian, out: &mut Vec<u8>) {
+    out.reserve(s.len() * 2);
+    for unit in s.encode_utf16() {
+        match endian {
+            Endian::Little => out.extend_from_slice(&unit.to_le_bytes()),
+            Endian::Big => out.extend_from_slice(&unit.to_be_bytes()),
+        }
+    }
+}
+
+/// Decode `bytes` as `encoding`, stripping its BOM if present.
+/// Malformed input is an `InvalidData` error.
+#[cfg(feature = "encoding")]
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> io::Result<String> {
+    let (s, had_errors) = encoding.decode_with_bom_removal(bytes);
+    if had_errors {
+        return Err(invalid_data(format!("malformed {} input", encoding.name())));
+    }
+    Ok(s.into_owned())
+}
+
+/// Guess the encoding of `bytes`: from its BOM, else UTF-8 if it is valid,
+/// else by statistical detection.
+#[cfg(feature = "encoding")]
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if ::std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = ::chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+pub(crate) fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn normalize_line_endings() {
+        let mixed = "a\r\nb\nc\rd";
+        assert_eq!("a\nb\nc\nd", LineEnding::Lf.normalize(mixed));
+        assert_eq!("a\r\nb\r\nc\r\nd", LineEnding::CrLf.normalize(mixed));
+        match LineEnding::CrLf.normalize("a\r\nb") {
+            Cow::Borrowed(_) => {}
+            Cow::Owned(s) => panic!("unexpected copy {:?}", s),
+        }
+    }
+
+    #[test]
+    fn detect_and_decode_bom() {
+        assert_eq!(None, Bom::detect(b"plain"));
+        let le = b"\xff\xfeh\x00i\x00";
+        assert_eq!(Some(Bom::Utf16Le), Bom::detect(le));
+        assert_eq!("hi", &Bom::Utf16Le.decode(le).unwrap());
+        assert_eq!("hi", &Bom::Utf16Be.decode(b"\xfe\xff\x00h\x00i").unwrap());
+        assert!(Bom::Utf16Be.decode(b"\xfe\xff\x00").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::io::prelude::*;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `ThrottledWriter` wraps a writer and sleeps as needed to keep the
+/// average throughput at or under a number of bytes per second.
+///
+/// Writes larger than a tenth of a second worth of bytes are split, so
+/// that the rate stays smooth instead of coming in bursts.
+pub struct ThrottledWriter<W> {
+    inner: W,
+    bytes_per_sec: u64,
+    written: u64,
+    start: Instant,
+}
+
+impl<W: Write> ThrottledWriter<W> {
+    /// Wrap `inner`, writing at most `bytes_per_sec` bytes per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is 0.
+    pub fn new(inner: W, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "rate must be positive");
+        ThrottledWriter {
+            inner,
+            bytes_per_sec,
+            written: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Borrow the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Sleep until writing `written` bytes since the start is within the rate.
+    fn wait(&self) {
+        let due = Duration::from_secs_f64(self.written as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let max = (self.bytes_per_sec / 10).max(1) as usize;
+        let n = self.inner.write(&buf[..buf.len().min(max)])?;
+        self.written += n as u64;
+        self.wait();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn limits_rate() {
+        let start = Instant::now();
+        let mut w = ThrottledWriter::new(Vec::new(), 10_000);
+        w.write_all(&[0; 2_000]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        assert_eq!(2_000, w.into_inner().len());
+    }
+}
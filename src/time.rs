@@ -0,0 +1,111 @@
+//! Minimal UTC calendar arithmetic, enough to stamp log records and
+//! file names without pulling in a date library.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Broken down UTC time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tm {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+impl Tm {
+    pub fn from_unix(secs: i64, millis: u32) -> Tm {
+        let days = secs.div_euclid(86_400);
+        let rem = secs.rem_euclid(86_400) as u32;
+        // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Tm {
+            year,
+            month,
+            day,
+            hour: rem / 3_600,
+            minute: rem % 3_600 / 60,
+            second: rem % 60,
+            millis,
+        }
+    }
+
+    pub fn from_system_time(t: SystemTime) -> Tm {
+        match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => Tm::from_unix(d.as_secs() as i64, d.subsec_millis()),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_millis() {
+                    0 => Tm::from_unix(-(d.as_secs() as i64), 0),
+                    ms => Tm::from_unix(-(d.as_secs() as i64) - 1, 1_000 - ms),
+                }
+            }
+        }
+    }
+
+    pub fn now() -> Tm {
+        Tm::from_system_time(SystemTime::now())
+    }
+
+    /// Format as `2024-01-31T23:59:59.999Z`.
+    pub fn rfc3339(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.millis
+        )
+    }
+
+    /// Format as `20240131T235959.999Z`, which is safe in file names.
+    pub fn basic(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}.{:03}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.millis
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn civil_time() {
+        let tm = Tm::from_unix(1_700_000_000, 42);
+        assert_eq!("2023-11-14T22:13:20.042Z", &tm.rfc3339());
+        assert_eq!("1970-01-01T00:00:00.000Z", &Tm::from_unix(0, 0).rfc3339());
+        assert_eq!("2000-02-29T00:00:00.000Z", &Tm::from_unix(951_782_400, 0).rfc3339());
+        assert_eq!("20231114T221320.042Z", &tm.basic());
+    }
+
+    #[test]
+    fn before_epoch() {
+        use std::time::Duration;
+        let t = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!("1969-12-31T23:59:59.000Z", &Tm::from_system_time(t).rfc3339());
+        let t = UNIX_EPOCH - Duration::from_millis(1_250);
+        assert_eq!("1969-12-31T23:59:58.750Z", &Tm::from_system_time(t).rfc3339());
+    }
+}
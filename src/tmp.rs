@@ -0,0 +1,259 @@
+use std::env;
+use std::fs;
+use std::io::{self, SeekFrom};
+use std::io::prelude::*;
+use std::ops;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dir::Dir;
+use file::File;
+use retry::RetryOptions;
+use sys;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// `TmpFile` is a temporary file without a name, created by
+/// `FileOpener::tmpfile_in`. Its content is lost when it is dropped,
+/// unless `link_into` gives it a name first.
+#[derive(Debug)]
+pub struct TmpFile {
+    file: fs::File,
+    linkable: bool,
+}
+
+/// `TmpDir` is a temporary directory created by `Dir::temp`, deleted with
+/// everything in it when it is dropped, unless `keep` is called.
+/// It derefs to the `Dir`.
+#[derive(Debug)]
+pub struct TmpDir {
+    dir: Option<Dir>,
+}
+
+impl TmpDir {
+    pub(crate) fn new_in(parent: &Path) -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        // Only the owner may look inside, the parent is often shared.
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        loop {
+            let path = parent.join(format!(
+                ".tmpdir.{}.{}.{}",
+                process::id(),
+                nanos,
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match builder.create(&path) {
+                Ok(()) => return Ok(TmpDir { dir: Some(Dir::new(path)) }),
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Create a temporary directory in the system temporary directory.
+    pub fn new() -> io::Result<Self> {
+        TmpDir::new_in(&env::temp_dir())
+    }
+
+    /// The file `name` in the temporary directory, which may not exist yet.
+    pub fn child<P: AsRef<Path>>(&self, name: P) -> File {
+        self.dir().child(name)
+    }
+
+    /// The subdirectory `name`, which may not exist yet.
+    pub fn child_dir<P: AsRef<Path>>(&self, name: P) -> Dir {
+        self.dir().child_dir(name)
+    }
+
+    /// Keep the directory on disk and return it.
+    pub fn keep(mut self) -> Dir {
+        self.dir.take().expect("directory already released")
+    }
+
+    /// Delete the directory now, reporting errors that dropping ignores.
+    pub fn close(mut self) -> io::Result<()> {
+        let dir = self.dir.take().expect("directory already released");
+        dir.delete_all(&RetryOptions::new())
+    }
+
+    fn dir(&self) -> &Dir {
+        self.dir.as_ref().expect("directory already released")
+    }
+}
+
+impl ops::Deref for TmpDir {
+    type Target = Dir;
+
+    fn deref(&self) -> &Dir {
+        self.dir()
+    }
+}
+
+impl AsRef<Path> for TmpDir {
+    fn as_ref(&self) -> &Path {
+        self.dir()
+    }
+}
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.take() {
+            let _ = dir.delete_all(&RetryOptions::new());
+        }
+    }
+}
+
+impl TmpFile {
+    pub(crate) fn new_in(dir: &Path) -> io::Result<Self> {
+        if let Some(file) = sys::open_tmpfile(dir)? {
+            return Ok(TmpFile {
+                file,
+                linkable: true,
+            });
+        }
+        loop {
+            let path = dir.join(format!(
+                ".tmpfile.{}.{}",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let opened = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path);
+            match opened {
+                Ok(file) => {
+                    fs::remove_file(&path)?;
+                    return Ok(TmpFile {
+                        file,
+                        linkable: false,
+                    });
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The underlying file.
+    pub fn as_file(&self) -> &fs::File {
+        &self.file
+    }
+
+    /// Give the file the name `path`, which must not exist yet, making its
+    /// content durable. Without `O_TMPFILE` the content is copied instead.
+    pub fn link_into<P: AsRef<Path>>(mut self, path: P) -> io::Result<File> {
+        let path = path.as_ref();
+        self.file.sync_all()?;
+        if self.linkable {
+            link(&self.file, path)?;
+        } else {
+            let mut out = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+            self.file.seek(SeekFrom::Start(0))?;
+            io::copy(&mut self.file, &mut out)?;
+            out.sync_all()?;
+        }
+        sys::sync_parent(path)?;
+        Ok(File::new(path))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn link(f: &fs::File, path: &Path) -> io::Result<()> {
+    sys::link_tmpfile(f, path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link(_f: &fs::File, _path: &Path) -> io::Result<()> {
+    unreachable!("only O_TMPFILE files are linkable")
+}
+
+impl Read for TmpFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for TmpFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for TmpFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::fs;
+    use self::tempdir::TempDir;
+    use super::*;
+    use file::FileOpener;
+
+    #[test]
+    fn link_into() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let mut tmp = FileOpener::tmpfile_in(tmp_dir.path()).unwrap();
+        tmp.write_all(b"staged").unwrap();
+        assert_eq!(0, fs::read_dir(tmp_dir.path()).unwrap().count());
+
+        let path = tmp_dir.path().join("done.txt");
+        let file = tmp.link_into(&path).unwrap();
+        assert_eq!("staged", &file.read_string().unwrap());
+
+        let tmp = FileOpener::tmpfile_in(tmp_dir.path()).unwrap();
+        assert!(tmp.link_into(&path).is_err());
+        drop(FileOpener::tmpfile_in(tmp_dir.path()).unwrap());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn tmp_dir() {
+        let tmp = Dir::temp().unwrap();
+        let path = tmp.to_path_buf();
+        tmp.child("a.txt").overwrite(b"a").unwrap();
+        tmp.child_dir("sub").create().unwrap();
+        tmp.child_dir("sub").child("b.txt").overwrite(b"b").unwrap();
+        assert!(tmp.child("a.txt").exists());
+        drop(tmp);
+        assert!(!path.exists());
+
+        let tmp = Dir::temp().unwrap();
+        assert_ne!(path, tmp.to_path_buf());
+        tmp.child("kept.txt").overwrite(b"kept").unwrap();
+        let kept = tmp.keep();
+        assert_eq!("kept", &kept.child("kept.txt").read_string().unwrap());
+        kept.delete_all(&RetryOptions::new()).unwrap();
+
+        Dir::temp().unwrap().close().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tmp_dir_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = Dir::temp().unwrap();
+        assert_eq!(0o700, fs::metadata(&*tmp).unwrap().permissions().mode() & 0o777);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+
+/// `TreeOptions` controls `Dir::render_tree`. By default entries are sorted
+/// by name, hidden ones included, without sizes.
+#[derive(Clone, Debug, Default)]
+pub struct TreeOptions {
+    sizes: bool,
+    skip_hidden: bool,
+    dirs_first: bool,
+}
+
+impl TreeOptions {
+    /// Sort by name, show hidden entries, no sizes.
+    pub fn new() -> Self {
+        TreeOptions::default()
+    }
+
+    /// Show the size of regular files in bytes.
+    pub fn sizes(mut self, sizes: bool) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    /// Leave out entries whose name starts with a dot.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// List directories before files.
+    pub fn dirs_first(mut self, dirs_first: bool) -> Self {
+        self.dirs_first = dirs_first;
+        self
+    }
+}
+
+/// Render the tree under `root` down to `depth`, starting with a `.` line
+/// so that the output doesn't depend on where the tree is.
+pub(crate) fn render(root: &Path, depth: usize, opts: &TreeOptions) -> String {
+    let mut out = String::from(".\n");
+    render_dir(root, depth, opts, "", &mut out);
+    out
+}
+
+fn render_dir(dir: &Path, depth: usize, opts: &TreeOptions, indent: &str, out: &mut String) {
+    if depth == 0 {
+        return;
+    }
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            out.push_str(&format!("{}└── [error: {}]\n", indent, e));
+            return;
+        }
+    };
+    let mut entries: Vec<(String, fs::Metadata)> = read_dir
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if opts.skip_hidden && name.starts_with('.') {
+                return None;
+            }
+            Some((name, fs::symlink_metadata(entry.path()).ok()?))
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        let dirs_first = if opts.dirs_first {
+            b.1.is_dir().cmp(&a.1.is_dir())
+        } else {
+            ::std::cmp::Ordering::Equal
+        };
+        dirs_first.then_with(|| a.0.cmp(&b.0))
+    });
+    let count = entries.len();
+    for (i, (name, meta)) in entries.into_iter().enumerate() {
+        let last = i + 1 == count;
+        out.push_str(indent);
+        out.push_str(if last { "└── " } else { "├── " });
+        out.push_str(&name);
+        let path = dir.join(&name);
+        if meta.file_type().is_symlink() {
+            if let Ok(target) = fs::read_link(&path) {
+                out.push_str(&format!(" -> {}", target.display()));
+            }
+        } else if meta.is_file() && opts.sizes {
+            out.push_str(&format!(" ({} B)", meta.len()));
+        }
+        out.push('\n');
+        if meta.is_dir() {
+            let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            render_dir(&path, depth - 1, opts, &indent, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use super::*;
+
+    #[test]
+    fn render_tree() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("src/bin").create().unwrap();
+        dir.child("src/bin/main.rs").overwrite(b"fn main() {}").unwrap();
+        dir.child("src/lib.rs").overwrite(b"").unwrap();
+        dir.child("Cargo.toml").overwrite(b"[package]").unwrap();
+        dir.child(".gitignore").overwrite(b"target").unwrap();
+
+        let expected = "\
+.
+├── .gitignore
+├── Cargo.toml
+└── src
+    ├── bin
+    │   └── main.rs
+    └── lib.rs
+";
+        assert_eq!(expected, dir.render_tree(3, &TreeOptions::new()));
+
+        let options = TreeOptions::new().sizes(true).skip_hidden(true).dirs_first(true);
+        let expected = "\
+.
+├── src
+│   ├── bin
+│   └── lib.rs (0 B)
+└── Cargo.toml (9 B)
+";
+        assert_eq!(expected, dir.render_tree(2, &options));
+    }
+}
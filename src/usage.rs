@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use walk::{Walk, WalkOptions};
+
+/// `SizeOptions` controls how `Dir::total_size` adds up sizes. By default
+/// links are not followed and apparent sizes are summed.
+#[derive(Clone, Debug, Default)]
+pub struct SizeOptions {
+    follow_links: bool,
+    allocated: bool,
+}
+
+impl SizeOptions {
+    /// Sum apparent sizes, without following links.
+    pub fn new() -> Self {
+        SizeOptions::default()
+    }
+
+    /// Count what symbolic links point to instead of the links.
+    /// Directories reached several times are counted once, and broken
+    /// links count as links.
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Sum the disk space allocated to files, as `du` does, instead of
+    /// their length. Sparse files take less, small files usually more.
+    /// Files with several hard links are counted once. Only supported on
+    /// unix, elsewhere apparent sizes are summed.
+    pub fn allocated(mut self, allocated: bool) -> Self {
+        self.allocated = allocated;
+        self
+    }
+}
+
+/// `CountOptions` controls what `Dir::count` visits. By default the whole
+/// tree is counted, without following links.
+#[derive(Clone, Debug, Default)]
+pub struct CountOptions {
+    max_depth: Option<usize>,
+    follow_links: bool,
+    skip_hidden: bool,
+}
+
+/// Numbers of entries found by `Dir::count`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Counts {
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Sockets, pipes, devices and the like.
+    pub others: u64,
+}
+
+impl CountOptions {
+    /// Count the whole tree, without following links.
+    pub fn new() -> Self {
+        CountOptions::default()
+    }
+
+    /// Don't go deeper than `depth`, 1 only counts the children.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Count what symbolic links point to instead of the links.
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Ignore entries whose name starts with a dot, and their content.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+}
+
+impl Counts {
+    /// Number of entries of every kind.
+    pub fn total(&self) -> u64 {
+        self.files + self.dirs + self.symlinks + self.others
+    }
+}
+
+/// Count the entries under `path`, one at a time.
+pub(crate) fn count(path: &Path, opts: &CountOptions) -> io::Result<Counts> {
+    let mut walk_options = WalkOptions::new()
+        .follow_links(opts.follow_links)
+        .skip_hidden(opts.skip_hidden);
+    if let Some(depth) = opts.max_depth {
+        walk_options = walk_options.max_depth(depth);
+    }
+    let mut counts = Counts::default();
+    for entry in Walk::new(path, walk_options) {
+        let ty = entry?.file_type();
+        if ty.is_file() {
+            counts.files += 1;
+        } else if ty.is_dir() {
+            counts.dirs += 1;
+        } else if ty.is_symlink() {
+            counts.symlinks += 1;
+        } else {
+            counts.others += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// The size of `path` and everything under it, as `opts` tell.
+pub(crate) fn total_size(path: &Path, opts: &SizeOptions) -> io::Result<u64> {
+    let mut seen = HashSet::new();
+    let meta = if opts.follow_links { fs::metadata(path)? } else { fs::symlink_metadata(path)? };
+    let mut total = size_of(&meta, opts, &mut seen);
+    if meta.is_dir() {
+        for entry in Walk::new(path, WalkOptions::new().follow_links(opts.follow_links)) {
+            let entry = entry?;
+            let meta = if opts.follow_links {
+                match fs::metadata(entry.file()) {
+                    Ok(meta) => meta,
+                    // A broken link, `du -L` counts the link itself.
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => entry.metadata()?,
+                    Err(e) => return Err(e),
+                }
+            } else {
+                entry.metadata()?
+            };
+            total += size_of(&meta, opts, &mut seen);
+        }
+    }
+    Ok(total)
+}
+
+/// Directories themselves only count in allocated sizes.
+#[cfg(unix)]
+fn size_of(meta: &fs::Metadata, opts: &SizeOptions, seen: &mut HashSet<(u64, u64)>) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    if !opts.allocated {
+        return if meta.is_dir() { 0 } else { meta.len() };
+    }
+    if meta.nlink() > 1 && !meta.is_dir() && !seen.insert((meta.dev(), meta.ino())) {
+        return 0;
+    }
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn size_of(meta: &fs::Metadata, _opts: &SizeOptions, _seen: &mut HashSet<(u64, u64)>) -> u64 {
+    if meta.is_dir() {
+        0
+    } else {
+        meta.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use super::*;
+
+    #[test]
+    fn count() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("a/b").create().unwrap();
+        dir.child_dir(".git").create().unwrap();
+        for name in &["a/1", "a/b/2", ".git/HEAD", "3"] {
+            dir.child(name).overwrite(b"").unwrap();
+        }
+        let counts = dir.count(&CountOptions::new()).unwrap();
+        assert_eq!(Counts { files: 4, dirs: 3, symlinks: 0, others: 0 }, counts);
+        let counts = dir.count(&CountOptions::new().skip_hidden(true).max_depth(2)).unwrap();
+        assert_eq!((2, 2), (counts.files, counts.dirs));
+        assert_eq!(4, counts.total());
+    }
+
+    #[test]
+    fn total_size() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("big/inner").create().unwrap();
+        dir.child("big/inner/a.bin").overwrite(&[0; 3000]).unwrap();
+        dir.child("big/b.bin").overwrite(&[0; 1000]).unwrap();
+        dir.child("small.txt").overwrite(b"12345").unwrap();
+
+        assert_eq!(4005, dir.total_size(&SizeOptions::new()).unwrap());
+        let by_child = dir.size_by_child(&SizeOptions::new()).unwrap();
+        assert_eq!(vec![(dir.child("big"), 4000), (dir.child("small.txt"), 5)], by_child);
+        #[cfg(unix)]
+        {
+            let allocated = dir.total_size(&SizeOptions::new().allocated(true)).unwrap();
+            assert!(allocated > 0);
+            let link = dir.child("link");
+            ::std::os::unix::fs::symlink(&*dir.child("big"), &*link).unwrap();
+            // `big` is only entered once, through the link or directly.
+            let followed = dir.total_size(&SizeOptions::new().follow_links(true)).unwrap();
+            assert_eq!(4005, followed);
+
+            let broken = dir.child("broken");
+            ::std::os::unix::fs::symlink("missing.bin", &*broken).unwrap();
+            let len = fs::symlink_metadata(&*broken).unwrap().len();
+            let followed = dir.total_size(&SizeOptions::new().follow_links(true)).unwrap();
+            assert_eq!(4005 + len, followed);
+        }
+    }
+}
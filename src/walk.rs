@@ -0,0 +1,423 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{self, ParallelIterator};
+
+use file::File;
+use sys::{self, FileKey};
+
+/// `Walk` iterates over every descendant of a directory, created by
+/// `Dir::walk` or `Dir::walk_with`. Directories come before their content,
+/// depth first unless `WalkOptions::breadth_first` is set. It keeps a stack
+/// of open directories instead of recursing, so deep trees are fine.
+///
+/// A directory which can't be read is yielded as an entry, followed by
+/// the error which prevented descending into it, then the walk goes on.
+pub struct Walk {
+    stack: Vec<(fs::ReadDir, usize)>,
+    queue: VecDeque<(File, usize)>,
+    pending: Option<io::Error>,
+    options: WalkOptions,
+    visited: HashSet<FileKey>,
+}
+
+/// `WalkOptions` controls what `Dir::walk_with` visits. By default every
+/// descendant is visited and symbolic links are not followed.
+pub struct WalkOptions {
+    max_depth: Option<usize>,
+    follow_links: bool,
+    skip_hidden: bool,
+    breadth_first: bool,
+    prune: Option<Box<PruneFn>>,
+}
+
+type PruneFn = dyn Fn(&File) -> bool;
+
+/// A file or directory found by `Walk`.
+#[derive(Clone, Debug)]
+pub struct WalkEntry {
+    file: File,
+    file_type: fs::FileType,
+    depth: usize,
+}
+
+impl Walk {
+    pub(crate) fn new(root: &Path, options: WalkOptions) -> Self {
+        let mut walk = Walk {
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+            pending: None,
+            options,
+            visited: HashSet::new(),
+        };
+        if walk.options.follow_links {
+            if let Ok(key) = sys::file_key(root) {
+                walk.visited.insert(key);
+            }
+        }
+        match fs::read_dir(root) {
+            Ok(read_dir) => walk.stack.push((read_dir, 1)),
+            Err(e) => walk.pending = Some(with_path(e, root)),
+        }
+        walk
+    }
+
+    /// Whether to descend into the directory `file` at `depth`.
+    fn descends(&mut self, file: &File, depth: usize) -> bool {
+        if self.options.max_depth.is_some_and(|max| depth >= max) {
+            return false;
+        }
+        if self.options.prune.as_ref().is_some_and(|prune| prune(file)) {
+            return false;
+        }
+        if self.options.follow_links {
+            // Links may lead back to an ancestor, never enter a directory twice.
+            if let Ok(key) = sys::file_key(file) {
+                if !self.visited.insert(key) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl WalkOptions {
+    /// Visit everything, without following symbolic links.
+    pub fn new() -> Self {
+        WalkOptions {
+            max_depth: None,
+            follow_links: false,
+            skip_hidden: false,
+            breadth_first: false,
+            prune: None,
+        }
+    }
+
+    /// Don't go deeper than `depth`, 1 only visits the children.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Follow symbolic links to directories. Each directory is entered
+    /// once at most, so link cycles don't loop forever.
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Skip entries whose name starts with a dot, and their content.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Visit all the entries of a depth before going deeper, so that the
+    /// entries nearest to the walked directory come first.
+    pub fn breadth_first(mut self, breadth_first: bool) -> Self {
+        self.breadth_first = breadth_first;
+        self
+    }
+
+    /// Don't descend into directories for which `prune` returns `true`,
+    /// such as `node_modules`. They are still yielded themselves.
+    pub fn prune<F>(mut self, prune: F) -> Self
+    where
+        F: Fn(&File) -> bool + 'static,
+    {
+        self.prune = Some(Box::new(prune));
+        self
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions::new()
+    }
+}
+
+impl Iterator for Walk {
+    type Item = io::Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<io::Result<WalkEntry>> {
+        if let Some(e) = self.pending.take() {
+            return Some(Err(e));
+        }
+        loop {
+            if self.stack.is_empty() {
+                let (dir, depth) = self.queue.pop_front()?;
+                match fs::read_dir(&*dir) {
+                    Ok(read_dir) => self.stack.push((read_dir, depth)),
+                    Err(e) => return Some(Err(with_path(e, &dir))),
+                }
+            }
+            let depth = self.stack.last()?.1;
+            let entry = match self.stack.last_mut()?.0.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if self.options.skip_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let mut file_type = match entry.file_type() {
+                Ok(ty) => ty,
+                Err(e) => return Some(Err(with_path(e, &path))),
+            };
+            if file_type.is_symlink() && self.options.follow_links {
+                if let Ok(meta) = fs::metadata(&path) {
+                    file_type = meta.file_type();
+                }
+            }
+            let file = File::from(path);
+            if file_type.is_dir() && self.descends(&file, depth) {
+                if self.options.breadth_first {
+                    self.queue.push_back((file.clone(), depth + 1));
+                } else {
+                    match fs::read_dir(&*file) {
+                        Ok(read_dir) => self.stack.push((read_dir, depth + 1)),
+                        Err(e) => self.pending = Some(with_path(e, &file)),
+                    }
+                }
+            }
+            return Some(Ok(WalkEntry {
+                file,
+                file_type,
+                depth,
+            }));
+        }
+    }
+}
+
+impl WalkEntry {
+    /// The path of the entry.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Take the path of the entry.
+    pub fn into_file(self) -> File {
+        self.file
+    }
+
+    /// The type of the entry, of the link target when following links.
+    pub fn file_type(&self) -> fs::FileType {
+        self.file_type
+    }
+
+    /// How deep the entry is, 1 for the children of the walked directory.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The metadata of the entry, without following symbolic links.
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        fs::symlink_metadata(&*self.file)
+    }
+}
+
+/// Walk the tree under `root` on the rayon thread pool, each directory
+/// being read by the thread which found it. Symbolic links aren't followed.
+#[cfg(feature = "rayon")]
+pub(crate) fn par_walk(root: &Path) -> impl ParallelIterator<Item = io::Result<WalkEntry>> {
+    let root = match fs::metadata(root) {
+        Ok(ref meta) if meta.is_dir() => Ok(WalkEntry {
+            file: File::new(root),
+            file_type: meta.file_type(),
+            depth: 0,
+        }),
+        Ok(_) => Err(with_path(io::ErrorKind::NotADirectory.into(), root)),
+        Err(e) => Err(with_path(e, root)),
+    };
+    iter::walk_tree_prefix(root, children).filter(|e| e.as_ref().map_or(true, |e| e.depth > 0))
+}
+
+/// The entries of `entry` if it is a directory, or the error reading it.
+#[cfg(feature = "rayon")]
+fn children(entry: &io::Result<WalkEntry>) -> Vec<io::Result<WalkEntry>> {
+    let dir = match *entry {
+        Ok(ref dir) if dir.file_type.is_dir() => dir,
+        _ => return Vec::new(),
+    };
+    let read_dir = match fs::read_dir(&*dir.file) {
+        Ok(read_dir) => read_dir,
+        Err(e) => return vec![Err(with_path(e, &dir.file))],
+    };
+    read_dir
+        .map(|entry| {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|e| with_path(e, &path))?;
+            Ok(WalkEntry {
+                file: File::from(path),
+                file_type,
+                depth: dir.depth + 1,
+            })
+        })
+        .collect()
+}
+
+/// Tell which path `err` is about.
+fn with_path(err: io::Error, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use super::*;
+
+    fn relative(dir: &TempDir, walk: Walk) -> Vec<String> {
+        let mut found: Vec<String> = walk
+            .map(|e| {
+                let e = e.unwrap();
+                let rel = e.file().strip_prefix(dir.path()).unwrap();
+                rel.to_string_lossy().into_owned()
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn walk() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("a/b/c").create().unwrap();
+        dir.child("a/b/c/deep.txt").overwrite(b"").unwrap();
+        dir.child("top.txt").overwrite(b"").unwrap();
+
+        let mut found: Vec<(String, usize)> = dir
+            .walk()
+            .map(|e| {
+                let e = e.unwrap();
+                let rel = e.file().strip_prefix(tmp_dir.path()).unwrap();
+                (rel.to_string_lossy().into_owned(), e.depth())
+            })
+            .collect();
+        found.sort();
+        let expected = vec![
+            ("a".to_owned(), 1),
+            ("a/b".to_owned(), 2),
+            ("a/b/c".to_owned(), 3),
+            ("a/b/c/deep.txt".to_owned(), 4),
+            ("top.txt".to_owned(), 1),
+        ];
+        assert_eq!(expected, found);
+
+        let missing: Vec<_> = dir.child_dir("missing").walk().collect();
+        assert_eq!(1, missing.len());
+        assert!(missing[0].as_ref().unwrap_err().to_string().contains("missing"));
+    }
+
+    #[test]
+    fn walk_options() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        for sub in &["src/deep", "node_modules/pkg", ".git"] {
+            dir.child_dir(sub).create().unwrap();
+        }
+        dir.child("src/deep/lib.rs").overwrite(b"").unwrap();
+        dir.child(".git/HEAD").overwrite(b"").unwrap();
+
+        let options = WalkOptions::new()
+            .max_depth(2)
+            .skip_hidden(true)
+            .prune(|d| d.ends_with("node_modules"));
+        let expected = vec!["node_modules", "src", "src/deep"];
+        assert_eq!(expected, relative(&tmp_dir, dir.walk_with(options)));
+        assert_eq!(7, dir.walk().count());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_follow_links() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        dir.child_dir("real").create().unwrap();
+        dir.child("real/a.txt").overwrite(b"").unwrap();
+        ::std::os::unix::fs::symlink(tmp_dir.path().join("real"), dir.child("link")).unwrap();
+        ::std::os::unix::fs::symlink(tmp_dir.path(), dir.child("real/loop")).unwrap();
+
+        let expected = vec!["link", "real", "real/a.txt", "real/loop"];
+        assert_eq!(expected, relative(&tmp_dir, dir.walk()));
+
+        // `real` is entered once, through whichever of `link` and `real`
+        // comes first, and `loop` leads back to the root which isn't entered.
+        let followed = relative(&tmp_dir, dir.walk_with(WalkOptions::new().follow_links(true)));
+        assert_eq!(4, followed.len());
+        let a = followed.iter().filter(|p| p.ends_with("a.txt")).count();
+        assert_eq!(1, a);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_walk() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        for i in 0..4 {
+            dir.child_dir(format!("d{}/sub", i)).create().unwrap();
+            for j in 0..5 {
+                dir.child(format!("d{}/sub/{}.txt", i, j)).overwrite(b"12345").unwrap();
+            }
+        }
+        let mut found: Vec<_> = dir.par_walk().map(|e| e.unwrap().into_file()).collect();
+        found.sort();
+        let mut expected: Vec<_> = dir.walk().map(|e| e.unwrap().into_file()).collect();
+        expected.sort();
+        assert_eq!(expected, found);
+
+        let total = AtomicU64::new(0);
+        dir.par_for_each(|e| {
+            if e.file_type().is_file() {
+                total.fetch_add(e.metadata()?.len(), Ordering::Relaxed);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(100, total.into_inner());
+
+        assert!(dir.child_dir("missing").par_for_each(|_| Ok(())).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_skips_unreadable() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path());
+        let locked = dir.child_dir("locked");
+        locked.create().unwrap();
+        dir.child("locked/hidden.txt").overwrite(b"").unwrap();
+        dir.child("open.txt").overwrite(b"").unwrap();
+        fs::set_permissions(&*locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions don't restrict root.
+        let readable = fs::read_dir(&*locked).is_ok();
+
+        let results: Vec<_> = dir.walk().collect();
+        fs::set_permissions(&*locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let errors = results.iter().filter(|r| r.is_err()).count();
+        let entries = results.iter().filter(|r| r.is_ok()).count();
+        if readable {
+            assert_eq!((0, 3), (errors, entries));
+        } else {
+            assert_eq!((1, 2), (errors, entries));
+        }
+    }
+}
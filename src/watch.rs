@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use file::File;
+
+/// `Watch` receives the changes under a directory, created by `Dir::watch`.
+/// Iterating blocks until the next change. Watching stops when it is
+/// dropped.
+///
+/// Renames are reported as the removal of the old path and the creation
+/// of the new one. Events are as precise as the platform makes them: some
+/// only report that something changed, as `Change::Modified`.
+pub struct Watch {
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<notify::Event>>,
+    pending: VecDeque<Event>,
+}
+
+/// A change reported by `Watch`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    pub change: Change,
+    pub file: File,
+}
+
+/// What happened to a watched entry.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Change {
+    Created,
+    Modified,
+    Removed,
+}
+
+impl Watch {
+    pub(crate) fn new(root: &Path) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(to_io)?;
+        watcher.watch(root, RecursiveMode::Recursive).map_err(to_io)?;
+        Ok(Watch {
+            _watcher: watcher,
+            rx,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next change at most `timeout`, `None` if nothing changed.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<io::Result<Event>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            match self.rx.recv_timeout(timeout) {
+                Ok(Ok(event)) => self.queue(event),
+                Ok(Err(e)) => return Some(Err(to_io(e))),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    fn queue(&mut self, event: notify::Event) {
+        let changes: Vec<Change> = match event.kind {
+            EventKind::Create(_) => vec![Change::Created],
+            EventKind::Remove(_) => vec![Change::Removed],
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => vec![Change::Removed],
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => vec![Change::Created],
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                vec![Change::Removed, Change::Created]
+            }
+            EventKind::Modify(_) | EventKind::Any => vec![Change::Modified],
+            EventKind::Access(_) | EventKind::Other => return,
+        };
+        for (i, path) in event.paths.into_iter().enumerate() {
+            let change = changes[i.min(changes.len() - 1)];
+            self.pending.push_back(Event {
+                change,
+                file: File::from(path),
+            });
+        }
+    }
+}
+
+impl Iterator for Watch {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            match self.rx.recv() {
+                Ok(Ok(event)) => self.queue(event),
+                Ok(Err(e)) => return Some(Err(to_io(e))),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+fn to_io(err: notify::Error) -> io::Error {
+    match err.kind {
+        notify::ErrorKind::Io(e) => e,
+        notify::ErrorKind::PathNotFound => io::Error::new(io::ErrorKind::NotFound, err),
+        _ => io::Error::other(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate tempdir;
+
+    use std::time::Duration;
+    use self::tempdir::TempDir;
+    use dir::Dir;
+    use super::*;
+
+    #[test]
+    fn watch() {
+        let tmp_dir = TempDir::new("good-files-test").unwrap();
+        let dir = Dir::new(tmp_dir.path().canonicalize().unwrap());
+        let mut watch = dir.watch().unwrap();
+        let file = dir.child("new.txt");
+        file.overwrite(b"hello").unwrap();
+        let created = Event {
+            change: Change::Created,
+            file: file.clone(),
+        };
+        let mut seen = false;
+        while let Some(event) = watch.next_timeout(Duration::from_secs(5)) {
+            if event.unwrap() == created {
+                seen = true;
+                break;
+            }
+        }
+        assert!(seen);
+        assert!(dir.child_dir("missing").watch().is_err());
+    }
+}